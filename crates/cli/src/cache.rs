@@ -0,0 +1,32 @@
+use koca::source::{format_bytes, SourceCache};
+
+use crate::{
+    cli::{CacheArgs, CacheCommand},
+    error::{CliError, CliMultiResult},
+};
+
+pub async fn run(args: CacheArgs) -> CliMultiResult<()> {
+    let cache = SourceCache::open().map_err(|err| CliError::Koca { err })?;
+
+    match args.command {
+        CacheCommand::Ls => {
+            let entries = cache.entries()?;
+            for entry in &entries {
+                println!("{:>9}  {}", format_bytes(entry.size), entry.key);
+            }
+            let total: u64 = entries.iter().map(|entry| entry.size).sum();
+            zolt::infoln!(
+                "{} cached source(s), {} in {}",
+                entries.len(),
+                format_bytes(total),
+                cache.root().display()
+            );
+        }
+        CacheCommand::Clean => {
+            let freed = cache.clean()?;
+            zolt::infoln!("Freed {}", format_bytes(freed));
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use clap::{Parser, Subcommand, ValueEnum};
-use koca::BundleFormat;
+use koca::{BundleFormat, PkgVersion};
+
+use crate::env::EnvArgs;
 use std::path::PathBuf;
 
 #[derive(Clone, ValueEnum)]
@@ -8,7 +10,11 @@ pub enum OutputType {
     Deb,
     /// The ".rpm" output type.
     Rpm,
-    /// Output both ".deb" and ".rpm" package types.
+    /// The Arch Linux ".pkg.tar.zst" output type.
+    Pacman,
+    /// The Alpine ".apk" output type.
+    Apk,
+    /// Output every package type: ".deb", ".rpm", ".pkg.tar.zst" and ".apk".
     All,
 }
 
@@ -17,6 +23,8 @@ impl OutputType {
         match self {
             Self::Deb => "deb",
             Self::Rpm => "rpm",
+            Self::Pacman => "pacman",
+            Self::Apk => "apk",
             Self::All => "all",
         }
     }
@@ -25,7 +33,14 @@ impl OutputType {
         match self {
             Self::Deb => vec![BundleFormat::Deb],
             Self::Rpm => vec![BundleFormat::Rpm],
-            Self::All => vec![BundleFormat::Deb, BundleFormat::Rpm],
+            Self::Pacman => vec![BundleFormat::Pacman],
+            Self::Apk => vec![BundleFormat::Apk],
+            Self::All => vec![
+                BundleFormat::Deb,
+                BundleFormat::Rpm,
+                BundleFormat::Pacman,
+                BundleFormat::Apk,
+            ],
         }
     }
 }
@@ -47,6 +62,38 @@ pub struct CreateArgs {
     /// Skip interactive confirmation prompts.
     #[arg(long)]
     pub noconfirm: bool,
+    /// Skip the build file's check() function.
+    #[arg(long)]
+    pub nocheck: bool,
+    /// Build for this architecture from the build file's `arch` array. Repeat to
+    /// build several in turn. Defaults to the one matching the host.
+    #[arg(long)]
+    pub arch: Vec<String>,
+    /// Install makedepends and build inside a throwaway podman container instead
+    /// of on the host. Only `koca-out` is copied back.
+    #[arg(long)]
+    pub isolated: bool,
+    /// The container image for `--isolated`. Defaults to the official image of
+    /// the target distro.
+    #[arg(long, requires = "isolated")]
+    pub image: Option<String>,
+    /// Run build() and package() under bubblewrap, with no network, a read-only
+    /// root filesystem and only the build directories writable.
+    #[arg(long)]
+    pub sandbox: bool,
+    /// Write the version computed by the build file's pkgver() back into its
+    /// `pkgver=` line.
+    #[arg(long, conflicts_with = "isolated")]
+    pub write_pkgver: bool,
+    #[command(flatten)]
+    pub env: EnvArgs,
+}
+
+#[derive(Parser)]
+pub struct UpdpkgsumsArgs {
+    /// The path to the build file. If omitted, searches for a single *.koca
+    /// file in the current directory, then in a `koca/` subdirectory.
+    pub build_file: Option<PathBuf>,
 }
 
 #[derive(Parser)]
@@ -59,6 +106,42 @@ pub struct PackageArgs {
     /// Only package these sub-packages (omit to package all).
     #[arg(long)]
     pub package: Vec<String>,
+    /// The architecture to bundle for, from the build file's `arch` array.
+    #[arg(long)]
+    pub arch: Option<String>,
+    /// The version computed by the build file's pkgver(), replacing its `pkgver`.
+    #[arg(long)]
+    pub pkgver: Option<PkgVersion>,
+    #[command(flatten)]
+    pub env: EnvArgs,
+}
+
+#[derive(Parser)]
+pub struct BuildArgs {
+    /// The path to the build file.
+    pub build_file: PathBuf,
+    /// The architecture to build for, from the build file's `arch` array.
+    #[arg(long)]
+    pub arch: Option<String>,
+    /// The version computed by the build file's pkgver(), replacing its `pkgver`.
+    #[arg(long)]
+    pub pkgver: Option<PkgVersion>,
+    #[command(flatten)]
+    pub env: EnvArgs,
+}
+
+#[derive(Parser)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommand {
+    /// List cached sources and their sizes.
+    Ls,
+    /// Remove every cached source.
+    Clean,
 }
 
 #[derive(Parser)]
@@ -76,14 +159,22 @@ pub struct BackendSocketArgs {
 #[derive(Subcommand)]
 pub enum InternalCommand {
     Package(PackageArgs),
+    Build(BuildArgs),
     BackendApt(BackendSocketArgs),
     BackendAlpm(BackendSocketArgs),
+    BackendDnf(BackendSocketArgs),
+    BackendZypper(BackendSocketArgs),
+    BackendApk(BackendSocketArgs),
 }
 
 #[derive(Parser)]
 pub enum Cli {
     /// Create a package from a build script.
     Create(CreateArgs),
+    /// Fetch every source and rewrite the build file's checksum arrays.
+    Updpkgsums(UpdpkgsumsArgs),
+    /// Manage the cache of downloaded sources shared between builds.
+    Cache(CacheArgs),
     /// Internal commands (hidden from help)
     #[command(hide = true)]
     Internal(InternalArgs),
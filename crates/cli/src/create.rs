@@ -1,9 +1,10 @@
 use koca::{
     backend::{Backend, Command, InstalledStatus, ResultPayload},
     distro::Distro,
-    source::{fetch_source, SourceProgress, SourceProgressState},
-    BuildFile,
+    source::{fetch_source, Source, SourceCache, SourceProgress, SourceProgressState},
+    Arch, BuildFile,
 };
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use tokio::io::{AsyncBufReadExt, BufReader};
@@ -14,6 +15,11 @@ use crate::{
     tui::{CreateUi, KocaCreateUi},
 };
 
+/// `$srcdir`, writable by sandboxed steps.
+const SRC_DIR: &str = "koca-build/src";
+/// The parent of every `$pkgdir`, writable by a sandboxed `package()`.
+const PKG_DIR: &str = "koca-build/pkg";
+
 fn ke(e: koca::KocaError) -> CliMultiError {
     CliMultiError::from(CliError::Koca { err: e })
 }
@@ -30,7 +36,59 @@ fn spawn_line_reader(
     });
 }
 
+/// Spawn `cmd` with stdout and stderr piped back to us.
+fn spawn_piped(cmd: &mut tokio::process::Command) -> std::io::Result<tokio::process::Child> {
+    cmd.stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+}
+
+/// Feed each line a piped `child` prints to `on_line`, ticking `ui` while it
+/// runs, and return its exit status.
+async fn stream_child(
+    mut child: tokio::process::Child,
+    ui: &mut dyn CreateUi,
+    on_line: impl Fn(&mut dyn CreateUi, &str) -> std::io::Result<()>,
+) -> CliMultiResult<std::process::ExitStatus> {
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let mut ticker = tokio::time::interval(std::time::Duration::from_millis(80));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    spawn_line_reader(stdout, tx.clone());
+    spawn_line_reader(stderr, tx);
+
+    let status = loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                ui.tick()?;
+            }
+            maybe_line = rx.recv() => {
+                match maybe_line {
+                    Some(line) => {
+                        on_line(ui, &line).ok();
+                    }
+                    None => {
+                        break child.wait().await.map_err(|err| CliError::Io { err })?;
+                    }
+                }
+            }
+        }
+    };
+
+    Ok(status)
+}
+
 pub async fn run(args: CreateArgs) -> CliMultiResult<()> {
+    if args.isolated {
+        let build_file_path = match &args.build_file {
+            Some(p) => p.clone(),
+            None => crate::discover::find_build_file()?,
+        };
+        return crate::isolated::run(&args, &build_file_path).await;
+    }
+
     let mut ui: Box<dyn CreateUi> = Box::new(KocaCreateUi::new()?);
 
     let result = run_inner(&args, ui.as_mut()).await;
@@ -51,6 +109,21 @@ async fn run_inner(args: &CreateArgs, ui: &mut dyn CreateUi) -> CliMultiResult<(
         .map_err(|errs| {
             CliMultiError(errs.into_iter().map(|err| CliError::Koca { err }).collect())
         })?;
+    args.env.apply(&mut build_file)?;
+
+    let archs = if args.arch.is_empty() {
+        vec![build_file.target_arch().clone()]
+    } else {
+        args.arch
+            .iter()
+            .map(|arch| Arch::from_str(arch))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(ke)?
+    };
+    for arch in &archs {
+        // Fail on undeclared arches before installing anything.
+        build_file.set_target_arch(arch).map_err(ke)?;
+    }
 
     let depends = build_file.depends().to_vec();
     let makedepends = build_file.makedepends().to_vec();
@@ -61,7 +134,9 @@ async fn run_inner(args: &CreateArgs, ui: &mut dyn CreateUi) -> CliMultiResult<(
         Distro::detect().map_err(ke)?
     };
 
-    let backend_kind = distro.backend_kind();
+    // Only look up the backend once something needs installing or removing, so
+    // distros without one can still build packages with no makedepends.
+    let backend_kind = || distro.backend_kind().map_err(ke);
 
     let mut newly_installed: Vec<String> = Vec::new();
     let total_download_bytes: u64;
@@ -75,6 +150,7 @@ async fn run_inner(args: &CreateArgs, ui: &mut dyn CreateUi) -> CliMultiResult<(
             let mut resolve_ticker = tokio::time::interval(std::time::Duration::from_millis(80));
             resolve_ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
+            let backend_kind = backend_kind()?;
             let mut check_backend = Backend::spawn(backend_kind, false).await.map_err(ke)?;
 
             let check_result = {
@@ -172,77 +248,182 @@ async fn run_inner(args: &CreateArgs, ui: &mut dyn CreateUi) -> CliMultiResult<(
         }
     }
 
-    let arch = build_file.arch()[0].clone();
+    for (i, arch) in archs.iter().enumerate() {
+        if i > 0 {
+            // Each arch builds from freshly fetched sources, so build() can't pick
+            // up the previous arch's objects.
+            clear_srcdir()?;
+        }
+        build_file.set_target_arch(arch).map_err(ke)?;
+        build_arch(args, &mut build_file, &build_file_path, ui).await?;
+    }
+
+    if args.rm_deps && !newly_installed.is_empty() {
+        zolt::infoln!("Removing {} makedepend(s)...", newly_installed.len());
+        let mut rm_backend = Backend::spawn(backend_kind()?, true).await.map_err(ke)?;
+
+        rm_backend
+            .call_streaming(
+                Command::Remove {
+                    packages: newly_installed,
+                },
+                |event| match event {
+                    None => {
+                        ui.tick().ok();
+                    }
+                    Some(ev) => {
+                        ui.on_event(ev).ok();
+                    }
+                },
+            )
+            .await
+            .map_err(ke)?;
+
+        rm_backend.shutdown().await.map_err(ke)?;
+    }
+
+    Ok(())
+}
+
+/// Remove `$srcdir` and the manifest of what was fetched into it.
+fn clear_srcdir() -> CliMultiResult<()> {
+    for path in [SRC_DIR, "koca-build/sources.json"] {
+        let path = Path::new(path);
+        let removed = if path.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        };
+        match removed {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                return Err(CliError::Io { err }.into())
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Fetch sources, run every build step and package the build file for its
+/// currently selected target arch.
+async fn build_arch(
+    args: &CreateArgs,
+    build_file: &mut BuildFile,
+    build_file_path: &Path,
+    ui: &mut dyn CreateUi,
+) -> CliMultiResult<()> {
+    let arch = build_file.target_arch().clone();
     let sources = build_file.sources(&arch).to_vec();
+    // Hand the subprocesses our timestamp, so one falling back to the current time can't drift.
+    let source_date_epoch = build_file.source_date_epoch();
 
     if !sources.is_empty() {
-        let srcdir = std::path::Path::new("koca-build/src");
-        std::fs::create_dir_all(srcdir).map_err(|err| CliError::Io { err })?;
+        fetch_sources(&sources, ui).await?;
+    }
 
-        let display_urls: Vec<String> = sources.iter().map(|s| s.display_url()).collect();
-        let progress: SourceProgressState = Arc::new(Mutex::new(
-            sources.iter().map(|_| SourceProgress::new()).collect(),
-        ));
+    let pkgbase = build_file.pkgbase().to_string();
+
+    if build_file.has_prepare() {
+        ui.start_prepare()?;
 
-        ui.redraw_sources(&progress.lock().unwrap(), &display_urls)?;
+        let prepare_result = build_file
+            .run_prepare_with_output(|line| match line {
+                Some(line) => {
+                    ui.on_build_line(&line.line).ok();
+                }
+                None => {
+                    ui.tick().ok();
+                }
+            })
+            .await;
 
-        let mut handles = Vec::new();
-        for (i, source) in sources.iter().enumerate() {
-            let source = source.clone();
-            let srcdir = srcdir.to_path_buf();
-            let progress = Arc::clone(&progress);
-            handles.push(tokio::spawn(async move {
-                fetch_source(&source, &srcdir, i, &progress).await
-            }));
+        if let Err(err) = prepare_result {
+            ui.show_failure("prepare")?;
+            return Err(CliError::Koca { err }.into());
         }
 
-        let mut ticker = tokio::time::interval(std::time::Duration::from_millis(80));
-        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        ui.finish_prepare(&pkgbase)?;
+    }
 
-        let all_done = async {
-            let mut errors = Vec::new();
-            for handle in handles {
-                match handle.await {
-                    Ok(Err(e)) => errors.push(e),
-                    Err(join_err) => errors.push(join_err.to_string()),
-                    Ok(Ok(())) => {}
-                }
-            }
-            errors
-        };
-        tokio::pin!(all_done);
+    // Like makepkg, pkgver() runs once the sources are prepared.
+    let mut pkgver_args = Vec::new();
+    if build_file.has_pkgver() {
+        ui.start_pkgver()?;
 
-        let fetch_errors = loop {
-            tokio::select! {
-                _ = ticker.tick() => {
+        let pkgver_result = build_file
+            .run_pkgver_with_output(|line| match line {
+                Some(line) => {
+                    ui.on_build_line(&line.line).ok();
+                }
+                None => {
                     ui.tick().ok();
-                    ui.redraw_sources(&progress.lock().unwrap(), &display_urls)?;
                 }
-                errors = &mut all_done => break errors,
-            }
-        };
-
-        ui.finish_sources(&progress.lock().unwrap(), &display_urls)?;
+            })
+            .await;
 
-        if !fetch_errors.is_empty() {
-            return Err(CliError::Koca {
-                err: koca::KocaError::InvalidSource(format!(
-                    "{} source(s) failed to fetch",
-                    fetch_errors.len()
-                )),
+        let pkgver = match pkgver_result {
+            Ok(pkgver) => pkgver,
+            Err(err) => {
+                ui.show_failure("pkgver")?;
+                return Err(CliError::Koca { err }.into());
             }
-            .into());
+        };
+        if args.write_pkgver {
+            write_pkgver(build_file_path, pkgver.as_str())?;
         }
-    }
+        pkgver_args = vec!["--pkgver".to_string(), pkgver.to_string()];
 
-    let pkgbase = build_file.pkgbase().to_string();
+        ui.finish_pkgver(&pkgbase, &build_file.version().to_string())?;
+    }
     let version = build_file.version().to_string();
 
     if build_file.has_build() {
         ui.start_build()?;
 
-        let build_result = build_file
-            .run_build_with_output(|line| match line {
+        if args.sandbox {
+            let exe = std::env::current_exe().map_err(|err| CliError::Io { err })?;
+            let mut cmd = crate::sandbox::command(&exe, &[SRC_DIR])?;
+            cmd.arg("internal")
+                .arg("build")
+                .arg(build_file_path)
+                .arg("--arch")
+                .arg(arch.get_string())
+                .args(&pkgver_args)
+                .args(args.env.to_args())
+                .env("SOURCE_DATE_EPOCH", source_date_epoch.to_string());
+            let child = spawn_piped(&mut cmd).map_err(crate::sandbox::spawn_error)?;
+            let status = stream_child(child, ui, |ui, line| ui.on_build_line(line)).await?;
+
+            if !status.success() {
+                ui.show_failure("build")?;
+                return Err(CliError::BuildFailed.into());
+            }
+        } else {
+            let build_result = build_file
+                .run_build_with_output(|line| match line {
+                    Some(line) => {
+                        ui.on_build_line(&line.line).ok();
+                    }
+                    None => {
+                        ui.tick().ok();
+                    }
+                })
+                .await;
+
+            if let Err(err) = build_result {
+                ui.show_failure("build")?;
+                return Err(CliError::Koca { err }.into());
+            }
+        }
+
+        ui.finish_build(&pkgbase, &version)?;
+    }
+
+    if build_file.has_check() && !args.nocheck {
+        ui.start_check()?;
+
+        let check_result = build_file
+            .run_check_with_output(|line| match line {
                 Some(line) => {
                     ui.on_build_line(&line.line).ok();
                 }
@@ -252,12 +433,12 @@ async fn run_inner(args: &CreateArgs, ui: &mut dyn CreateUi) -> CliMultiResult<(
             })
             .await;
 
-        if let Err(err) = build_result {
-            ui.show_failure("build")?;
+        if let Err(err) = check_result {
+            ui.show_failure("check")?;
             return Err(CliError::Koca { err }.into());
         }
 
-        ui.finish_build(&pkgbase, &version)?;
+        ui.finish_check(&pkgbase, &version)?;
     }
 
     let output_type_str = args.output_type.as_str();
@@ -265,50 +446,39 @@ async fn run_inner(args: &CreateArgs, ui: &mut dyn CreateUi) -> CliMultiResult<(
     ui.start_package()?;
 
     let exe = std::env::current_exe().map_err(|err| CliError::Io { err })?;
-    let mut child = tokio::process::Command::new("fakeroot")
-        .arg(&exe)
+    // Already root (e.g. in an `--isolated` container), so fakeroot isn't needed.
+    let (program, exe_arg) = if nix::unistd::geteuid().is_root() {
+        (exe.as_path(), None)
+    } else {
+        (Path::new("fakeroot"), Some(&exe))
+    };
+    let mut cmd = if args.sandbox {
+        crate::sandbox::command(program, &[SRC_DIR, PKG_DIR, "koca-out"])?
+    } else {
+        tokio::process::Command::new(program)
+    };
+    cmd.args(exe_arg)
         .arg("internal")
         .arg("package")
-        .arg(&build_file_path)
+        .arg(build_file_path)
         .arg("--output-type")
         .arg(output_type_str)
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .map_err(|err| {
-            if err.kind() == std::io::ErrorKind::NotFound {
-                CliError::FakerootNotFound
-            } else {
-                CliError::Io { err }
-            }
-        })?;
-
-    let stdout = child.stdout.take().unwrap();
-    let stderr = child.stderr.take().unwrap();
-    let mut ticker = tokio::time::interval(std::time::Duration::from_millis(80));
-    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
-    spawn_line_reader(stdout, tx.clone());
-    spawn_line_reader(stderr, tx);
-
-    let status = loop {
-        tokio::select! {
-            _ = ticker.tick() => {
-                ui.tick()?;
-            }
-            maybe_line = rx.recv() => {
-                match maybe_line {
-                    Some(line) => {
-                        ui.on_package_line(&line).ok();
-                    }
-                    None => {
-                        break child.wait().await.map_err(|err| CliError::Io { err })?;
-                    }
-                }
-            }
+        .arg("--arch")
+        .arg(arch.get_string())
+        .args(&pkgver_args)
+        .args(args.env.to_args())
+        .env("SOURCE_DATE_EPOCH", source_date_epoch.to_string());
+    let child = spawn_piped(&mut cmd).map_err(|err| {
+        if args.sandbox {
+            crate::sandbox::spawn_error(err)
+        } else if err.kind() == std::io::ErrorKind::NotFound {
+            CliError::FakerootNotFound
+        } else {
+            CliError::Io { err }
         }
-    };
+    })?;
+
+    let status = stream_child(child, ui, |ui, line| ui.on_package_line(line)).await?;
 
     if !status.success() {
         ui.show_failure("package")?;
@@ -320,35 +490,93 @@ async fn run_inner(args: &CreateArgs, ui: &mut dyn CreateUi) -> CliMultiResult<(
         for fmt in args.output_type.bundle_formats() {
             output_files.push(format!(
                 "koca-out/{}",
-                fmt.output_filename(name, &version, &arch)
+                fmt.output_filename(name, build_file.version(), &arch)
+                    .map_err(ke)?
             ));
         }
     }
 
     ui.finish_package(&output_files.join(", "))?;
 
-    if args.rm_deps && !newly_installed.is_empty() {
-        zolt::infoln!("Removing {} makedepend(s)...", newly_installed.len());
-        let mut rm_backend = Backend::spawn(backend_kind, true).await.map_err(ke)?;
+    Ok(())
+}
 
-        rm_backend
-            .call_streaming(
-                Command::Remove {
-                    packages: newly_installed,
-                },
-                |event| match event {
-                    None => {
-                        ui.tick().ok();
-                    }
-                    Some(ev) => {
-                        ui.on_event(ev).ok();
-                    }
-                },
-            )
-            .await
-            .map_err(ke)?;
+/// Rewrite the `pkgver=` line of the build file at `path` to `pkgver`.
+fn write_pkgver(path: &Path, pkgver: &str) -> CliMultiResult<()> {
+    let raw = std::fs::read_to_string(path).map_err(|err| CliError::Io { err })?;
+    let updated =
+        koca::edit::set_string(&raw, koca::vars::PKGVER, pkgver).ok_or_else(|| CliError::Io {
+            err: std::io::Error::other("couldn't find a top-level 'pkgver' assignment to update"),
+        })?;
+    if updated != raw {
+        std::fs::write(path, updated).map_err(|err| CliError::Io { err })?;
+    }
+    Ok(())
+}
 
-        rm_backend.shutdown().await.map_err(ke)?;
+/// Fetch `sources` into `koca-build/src` in parallel, drawing progress on `ui`.
+///
+/// Every failed source is returned as its own error.
+pub(crate) async fn fetch_sources(sources: &[Source], ui: &mut dyn CreateUi) -> CliMultiResult<()> {
+    let srcdir = std::path::Path::new("koca-build/src");
+    std::fs::create_dir_all(srcdir).map_err(|err| CliError::Io { err })?;
+
+    let display_urls: Vec<String> = sources.iter().map(|s| s.display_url()).collect();
+    let progress: SourceProgressState = Arc::new(Mutex::new(
+        sources.iter().map(|_| SourceProgress::new()).collect(),
+    ));
+
+    ui.redraw_sources(&progress.lock().unwrap(), &display_urls)?;
+
+    // The cache is only an optimisation, so builds go ahead without one.
+    let cache = SourceCache::open().ok().map(Arc::new);
+
+    let mut handles = Vec::new();
+    for (i, source) in sources.iter().enumerate() {
+        let source = source.clone();
+        let srcdir = srcdir.to_path_buf();
+        let progress = Arc::clone(&progress);
+        let cache = cache.clone();
+        handles.push(tokio::spawn(async move {
+            fetch_source(&source, &srcdir, i, &progress, cache.as_deref()).await
+        }));
+    }
+
+    let mut ticker = tokio::time::interval(std::time::Duration::from_millis(80));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    let all_done = async {
+        let mut errors = Vec::new();
+        for handle in handles {
+            match handle.await {
+                Ok(Err(e)) => errors.push(e),
+                Err(join_err) => errors.push(koca::KocaError::IO(join_err.into())),
+                Ok(Ok(())) => {}
+            }
+        }
+        errors
+    };
+    tokio::pin!(all_done);
+
+    let fetch_errors = loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                ui.tick().ok();
+                ui.redraw_sources(&progress.lock().unwrap(), &display_urls)?;
+            }
+            errors = &mut all_done => break errors,
+        }
+    };
+
+    ui.finish_sources(&progress.lock().unwrap(), &display_urls)?;
+
+    if !fetch_errors.is_empty() {
+        return Err(CliMultiError(
+            fetch_errors
+                .into_iter()
+                .map(|err| CliError::Koca { err })
+                .collect(),
+        ));
     }
 
     Ok(())
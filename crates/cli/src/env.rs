@@ -0,0 +1,83 @@
+//! The environment build functions run with.
+//!
+//! Koca passes only [`koca::ENV_ALLOWLIST`] through from the caller. Extra
+//! variables come from the `[env]` table of a `koca.toml` in the current
+//! directory, then from `--env KEY=VAL` flags, which win over the file.
+use clap::Args;
+use koca::BuildFile;
+
+use crate::error::{CliError, CliMultiResult};
+
+/// The config file read from the current directory.
+const CONFIG_FILE: &str = "koca.toml";
+
+#[derive(Args, Clone, Default)]
+pub struct EnvArgs {
+    /// Export a variable to build functions. Repeat for several.
+    #[arg(long = "env", value_name = "KEY=VAL", value_parser = parse_env_pair)]
+    pub env: Vec<(String, String)>,
+    /// Pass the whole calling environment to build functions, not just PATH,
+    /// HOME, TERM and the other allowlisted variables.
+    #[arg(long)]
+    pub keep_env: bool,
+}
+
+impl EnvArgs {
+    /// Set up `build_file`'s environment from `koca.toml` and these flags.
+    pub fn apply(&self, build_file: &mut BuildFile) -> CliMultiResult<()> {
+        build_file.set_keep_env(self.keep_env);
+        for (key, value) in config_env()?.into_iter().chain(self.env.iter().cloned()) {
+            build_file.set_env(key, value);
+        }
+        Ok(())
+    }
+
+    /// These flags as arguments for a `koca` subprocess.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut out = Vec::new();
+        for (key, value) in &self.env {
+            out.push(format!("--env={key}={value}"));
+        }
+        if self.keep_env {
+            out.push("--keep-env".to_string());
+        }
+        out
+    }
+}
+
+fn parse_env_pair(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VAL, got '{s}'")),
+    }
+}
+
+/// Read the `[env]` table from `koca.toml`, if there is one.
+fn config_env() -> CliMultiResult<Vec<(String, String)>> {
+    let text = match std::fs::read_to_string(CONFIG_FILE) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(CliError::Io { err }.into()),
+    };
+    let invalid = |message: String| CliError::InvalidConfig { message };
+
+    let doc = text
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|err| invalid(err.to_string()))?;
+    let Some(item) = doc.get("env") else {
+        return Ok(Vec::new());
+    };
+    let table = item
+        .as_table_like()
+        .ok_or_else(|| invalid("`env` should be a table".to_string()))?;
+
+    table
+        .iter()
+        .map(|(key, value)| {
+            value
+                .as_str()
+                .map(|value| (key.to_string(), value.to_string()))
+                .ok_or_else(|| invalid(format!("`env.{key}` should be a string")).into())
+        })
+        .collect()
+}
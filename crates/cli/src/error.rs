@@ -19,12 +19,30 @@ pub enum CliError {
         #[source]
         err: std::io::Error,
     },
+    /// The sandboxed build phase failed.
+    #[error("Build phase failed")]
+    BuildFailed,
     /// The fakeroot package phase failed.
     #[error("Package phase failed")]
     PackageFailed,
     /// fakeroot is not installed.
     #[error("fakeroot is not installed or not in PATH")]
     FakerootNotFound,
+    /// `koca.toml` couldn't be parsed.
+    #[error("invalid koca.toml: {message}")]
+    InvalidConfig { message: String },
+    /// bubblewrap is not installed.
+    #[error("bwrap is not installed or not in PATH (required by --sandbox)")]
+    BwrapNotFound,
+    /// podman is not installed.
+    #[error("podman is not installed or not in PATH (required by --isolated)")]
+    PodmanNotFound,
+    /// There's no known container image for the target distro.
+    #[error("no default container image for distro '{distro}', pass one with --image")]
+    NoDefaultImage { distro: String },
+    /// The containerised build failed.
+    #[error("isolated build in '{image}' failed")]
+    IsolatedBuildFailed { image: String },
 }
 
 /// A list of [`CliError`] instances.
@@ -0,0 +1,42 @@
+use std::str::FromStr;
+
+use koca::{Arch, BuildFile};
+
+use super::package::print_build_output;
+use crate::{
+    cli::BuildArgs,
+    error::{CliError, CliMultiError, CliMultiResult},
+};
+
+pub async fn run(args: BuildArgs) -> CliMultiResult<()> {
+    let mut build_file = match BuildFile::parse_file(&args.build_file).await {
+        Ok(file) => file,
+        Err(errs) => {
+            return Err(CliMultiError(
+                errs.into_iter().map(|err| CliError::Koca { err }).collect(),
+            ))
+        }
+    };
+
+    args.env.apply(&mut build_file)?;
+
+    if let Some(arch) = &args.arch {
+        Arch::from_str(arch)
+            .and_then(|arch| build_file.set_target_arch(&arch))
+            .map_err(|err| CliError::Koca { err })?;
+    }
+    if let Some(pkgver) = args.pkgver.clone() {
+        build_file.set_pkgver(pkgver);
+    }
+
+    build_file
+        .run_build_with_output(|line| {
+            if let Some(line) = line {
+                print_build_output(line);
+            }
+        })
+        .await
+        .map_err(|err| CliError::Koca { err })?;
+
+    Ok(())
+}
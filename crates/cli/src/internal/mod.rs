@@ -1,3 +1,4 @@
+mod build;
 mod package;
 
 use koca::backend::{self, BackendKind};
@@ -7,6 +8,7 @@ use crate::{cli::InternalArgs, cli::InternalCommand, error::CliMultiResult};
 pub async fn run(args: InternalArgs) -> CliMultiResult<()> {
     match args.command {
         InternalCommand::Package(package_args) => package::run(package_args).await,
+        InternalCommand::Build(build_args) => build::run(build_args).await,
         InternalCommand::BackendApt(args) => {
             backend::run_backend_loop(&args.socket, BackendKind::Apt)
                 .await
@@ -23,5 +25,29 @@ pub async fn run(args: InternalArgs) -> CliMultiResult<()> {
                 })?;
             Ok(())
         }
+        InternalCommand::BackendDnf(args) => {
+            backend::run_backend_loop(&args.socket, BackendKind::Dnf)
+                .await
+                .map_err(|e| crate::error::CliError::Io {
+                    err: std::io::Error::other(e.to_string()),
+                })?;
+            Ok(())
+        }
+        InternalCommand::BackendZypper(args) => {
+            backend::run_backend_loop(&args.socket, BackendKind::Zypper)
+                .await
+                .map_err(|e| crate::error::CliError::Io {
+                    err: std::io::Error::other(e.to_string()),
+                })?;
+            Ok(())
+        }
+        InternalCommand::BackendApk(args) => {
+            backend::run_backend_loop(&args.socket, BackendKind::Apk)
+                .await
+                .map_err(|e| crate::error::CliError::Io {
+                    err: std::io::Error::other(e.to_string()),
+                })?;
+            Ok(())
+        }
     }
 }
@@ -1,6 +1,6 @@
-use std::path::Path;
+use std::{path::Path, str::FromStr};
 
-use koca::{BuildFile, BuildOutputLine, BuildOutputStream};
+use koca::{Arch, BuildFile, BuildOutputLine, BuildOutputStream};
 
 use crate::{
     cli::PackageArgs,
@@ -17,6 +17,17 @@ pub async fn run(args: PackageArgs) -> CliMultiResult<()> {
         }
     };
 
+    args.env.apply(&mut build_file)?;
+
+    if let Some(arch) = &args.arch {
+        Arch::from_str(arch)
+            .and_then(|arch| build_file.set_target_arch(&arch))
+            .map_err(|err| CliError::Koca { err })?;
+    }
+    if let Some(pkgver) = args.pkgver.clone() {
+        build_file.set_pkgver(pkgver);
+    }
+
     let pkg_names: Vec<String> = if args.package.is_empty() {
         build_file.pkgnames().to_vec()
     } else {
@@ -38,9 +49,9 @@ pub async fn run(args: PackageArgs) -> CliMultiResult<()> {
         }
 
         for bundle_format in args.output_type.bundle_formats() {
-            let arch = build_file.arch()[0].clone();
-            let file_name =
-                bundle_format.output_filename(pkg_name, &build_file.version().to_string(), &arch);
+            let file_name = bundle_format
+                .output_filename(pkg_name, build_file.version(), build_file.target_arch())
+                .map_err(|err| CliError::Koca { err })?;
             let out_path = Path::new("koca-out").join(&file_name);
 
             if let Err(err) = build_file.bundle(pkg_name, bundle_format, &out_path) {
@@ -52,7 +63,7 @@ pub async fn run(args: PackageArgs) -> CliMultiResult<()> {
     Ok(())
 }
 
-fn print_build_output(line: BuildOutputLine) {
+pub(super) fn print_build_output(line: BuildOutputLine) {
     match line.stream {
         BuildOutputStream::Stdout => println!("{}", line.line),
         BuildOutputStream::Stderr => eprintln!("{}", line.line),
@@ -0,0 +1,194 @@
+//! `koca create --isolated`: build inside a throwaway podman container.
+//!
+//! The current directory is mounted read-only and copied into the container,
+//! the running `koca` binary is mounted in, and `koca create` is re-run there
+//! as root. Makedepends land in the container rather than on the host, and only
+//! `koca-out` is written back.
+use std::io::IsTerminal;
+use std::path::Path;
+use std::str::FromStr;
+
+use koca::{distro::Distro, BuildFile};
+
+use crate::{
+    cli::CreateArgs,
+    error::{CliError, CliMultiError, CliMultiResult},
+};
+
+/// Runs inside the container: stage a private copy of the build tree, build it,
+/// then hand the packages back through the `/koca/out` mount.
+///
+/// Official images ship without package indices (empty apt lists, no pacman sync
+/// databases), and the backends don't refresh them, so that happens here first.
+/// Arch gets a full `-Syu`, since installing from a newer database onto an older
+/// image is a partial upgrade.
+const SCRIPT: &str = r#"set -e
+if command -v apt-get >/dev/null; then
+    apt-get update -qq
+elif command -v pacman >/dev/null; then
+    pacman -Syu --noconfirm
+elif command -v dnf >/dev/null; then
+    dnf makecache -q
+elif command -v zypper >/dev/null; then
+    zypper --non-interactive --quiet refresh
+elif command -v apk >/dev/null; then
+    apk update -q
+fi
+mkdir -p /build
+cp -a /koca/src/. /build
+cd /build
+rm -rf koca-build koca-out
+koca "$@"
+cp -a koca-out/. /koca/out/
+"#;
+
+/// Proxy settings passed into the container, in either case.
+const PROXY_VARS: &[&str] = &["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY", "NO_PROXY"];
+
+/// Where `KOCA_CA_BUNDLE` is mounted in the container.
+const CA_BUNDLE: &str = "/koca/ca-bundle.pem";
+
+pub async fn run(args: &CreateArgs, build_file_path: &Path) -> CliMultiResult<()> {
+    let image = match &args.image {
+        Some(image) => image.clone(),
+        None => {
+            let distro = match &args.target {
+                Some(target) => Distro::from_str(target),
+                None => Distro::detect(),
+            }
+            .map_err(|err| CliError::Koca { err })?;
+            default_image(&distro).ok_or(CliError::NoDefaultImage { distro: distro.id })?
+        }
+    };
+
+    let parsed = BuildFile::parse_file(build_file_path)
+        .await
+        .map_err(|errs| {
+            CliMultiError(errs.into_iter().map(|err| CliError::Koca { err }).collect())
+        })?;
+    // The container has no git history for the build file to take a timestamp from.
+    let source_date_epoch = parsed.source_date_epoch();
+    let sources: Vec<_> = parsed
+        .source_arrays()
+        .into_iter()
+        .flat_map(|(_, sources)| sources.iter().cloned())
+        .collect();
+
+    let cwd = std::env::current_dir()?;
+    let exe = std::env::current_exe()?;
+    let build_file = std::fs::canonicalize(build_file_path)?;
+    let build_file_name = build_file
+        .file_name()
+        .expect("a canonical file path has a file name")
+        .to_string_lossy()
+        .into_owned();
+    let out_dir = cwd.join("koca-out");
+    std::fs::create_dir_all(&out_dir)?;
+
+    let mut cmd = tokio::process::Command::new("podman");
+    cmd.args(["run", "--rm", "--interactive"]);
+    if std::io::stdout().is_terminal() {
+        cmd.arg("--tty");
+    }
+    cmd.arg("--env")
+        .arg(format!("SOURCE_DATE_EPOCH={source_date_epoch}"));
+    // Sources are fetched inside the container, so pass on the fetch settings and
+    // the tokens for the hosts they come from, and nothing else. `--env NAME`
+    // takes the value from our environment.
+    let fetch_vars = koca::source::fetch_env_vars(&sources);
+    for (name, _) in std::env::vars_os() {
+        let name = name.to_string_lossy();
+        if fetch_vars.iter().any(|var| *var == name)
+            || PROXY_VARS.contains(&name.to_ascii_uppercase().as_str())
+        {
+            cmd.arg("--env").arg(name.as_ref());
+        }
+    }
+    // The CA bundle is a host path, so mount it in too.
+    if let Some(bundle) = std::env::var_os("KOCA_CA_BUNDLE").filter(|v| !v.is_empty()) {
+        let bundle = std::fs::canonicalize(bundle)?;
+        cmd.arg("--volume")
+            .arg(volume(&bundle, CA_BUNDLE, "ro"))
+            .arg("--env")
+            .arg(format!("KOCA_CA_BUNDLE={CA_BUNDLE}"));
+    }
+    cmd.arg("--volume")
+        .arg(volume(&cwd, "/koca/src", "ro"))
+        .arg("--volume")
+        .arg(volume(&out_dir, "/koca/out", "rw"))
+        .arg("--volume")
+        .arg(volume(&exe, "/usr/local/bin/koca", "ro"))
+        .arg("--volume")
+        .arg(volume(
+            &build_file,
+            &format!("/koca/file/{build_file_name}"),
+            "ro",
+        ))
+        .arg(&image)
+        .args(["sh", "-c", SCRIPT, "koca", "create"])
+        .arg(format!("/koca/file/{build_file_name}"))
+        .args(forwarded_args(args));
+
+    let status = cmd.status().await.map_err(|err| {
+        if err.kind() == std::io::ErrorKind::NotFound {
+            CliError::PodmanNotFound
+        } else {
+            CliError::Io { err }
+        }
+    })?;
+
+    if !status.success() {
+        return Err(CliError::IsolatedBuildFailed { image }.into());
+    }
+
+    Ok(())
+}
+
+/// A `--volume` spec, relabelled for SELinux hosts.
+fn volume(host: &Path, container: &str, mode: &str) -> String {
+    format!("{}:{container}:{mode},Z", host.display())
+}
+
+/// The `create` flags that carry over to the build inside the container.
+///
+/// Prompts are skipped since anything installed is thrown away with the
+/// container, which also makes `--rm-deps` pointless.
+fn forwarded_args(args: &CreateArgs) -> Vec<String> {
+    let mut out = vec![
+        "--noconfirm".to_string(),
+        "--output-type".to_string(),
+        args.output_type.as_str().to_string(),
+    ];
+    if args.nocheck {
+        out.push("--nocheck".to_string());
+    }
+    if args.sandbox {
+        out.push("--sandbox".to_string());
+    }
+    for arch in &args.arch {
+        out.push("--arch".to_string());
+        out.push(arch.clone());
+    }
+    out.extend(args.env.to_args());
+    out
+}
+
+/// The official container image for `distro`, if there is one.
+fn default_image(distro: &Distro) -> Option<String> {
+    let version = distro.version_id.as_deref();
+    let image = match distro.id.as_str() {
+        "arch" => "docker.io/library/archlinux:latest".to_string(),
+        "debian" | "ubuntu" | "fedora" | "alpine" => format!(
+            "docker.io/library/{}:{}",
+            distro.id,
+            version.unwrap_or("latest")
+        ),
+        "opensuse-tumbleweed" => "registry.opensuse.org/opensuse/tumbleweed:latest".to_string(),
+        "opensuse-leap" => format!(
+            "registry.opensuse.org/opensuse/leap:{}",
+            version.unwrap_or("latest")
+        ),
+        _ => return None,
+    };
+    Some(image)
+}
@@ -1,11 +1,16 @@
 #![allow(clippy::result_large_err)]
 
+mod cache;
 mod cli;
 mod create;
 mod discover;
+mod env;
 mod error;
 mod internal;
+mod isolated;
+mod sandbox;
 mod tui;
+mod updpkgsums;
 
 use clap::Parser;
 use cli::Cli;
@@ -25,6 +30,8 @@ async fn main() {
 
     let output = match cli {
         Cli::Create(create_args) => create::run(create_args).await,
+        Cli::Updpkgsums(args) => updpkgsums::run(args).await,
+        Cli::Cache(args) => cache::run(args).await,
         Cli::Internal(args) => internal::run(args).await,
     };
 
@@ -0,0 +1,42 @@
+//! Running build steps under bubblewrap for `koca create --sandbox`.
+//!
+//! The sandbox has no network, sees the host's `/` read-only, and can only write
+//! to the build directories it's given, so undeclared downloads and stray writes
+//! to `$HOME` or `/usr` fail instead of quietly succeeding.
+use std::path::Path;
+
+use crate::error::{CliError, CliMultiResult};
+
+/// Build a command running `program` inside the sandbox, where only `writable`
+/// (paths relative to the current directory) can be written to.
+pub fn command(program: &Path, writable: &[&str]) -> CliMultiResult<tokio::process::Command> {
+    let cwd = std::env::current_dir()?;
+
+    let mut cmd = tokio::process::Command::new("bwrap");
+    cmd.args(["--unshare-net", "--die-with-parent", "--new-session"])
+        .args(["--ro-bind", "/", "/"])
+        .args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"])
+        // Re-expose the build tree in case it lives under the fresh /tmp.
+        .arg("--ro-bind")
+        .arg(&cwd)
+        .arg(&cwd);
+
+    for dir in writable {
+        let dir = cwd.join(dir);
+        std::fs::create_dir_all(&dir)?;
+        cmd.arg("--bind").arg(&dir).arg(&dir);
+    }
+
+    cmd.arg("--chdir").arg(&cwd).arg("--").arg(program);
+    Ok(cmd)
+}
+
+/// Map a failure to spawn a sandboxed command, where `bwrap` is the program
+/// actually being run.
+pub fn spawn_error(err: std::io::Error) -> CliError {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        CliError::BwrapNotFound
+    } else {
+        CliError::Io { err }
+    }
+}
@@ -39,10 +39,19 @@ pub trait CreateUi {
         display_urls: &[String],
     ) -> io::Result<()>;
 
+    fn start_prepare(&mut self) -> io::Result<()>;
+    fn finish_prepare(&mut self, pkgname: &str) -> io::Result<()>;
+
+    fn start_pkgver(&mut self) -> io::Result<()>;
+    fn finish_pkgver(&mut self, pkgname: &str, version: &str) -> io::Result<()>;
+
     fn start_build(&mut self) -> io::Result<()>;
     fn on_build_line(&mut self, line: &str) -> io::Result<()>;
     fn finish_build(&mut self, pkgname: &str, version: &str) -> io::Result<()>;
 
+    fn start_check(&mut self) -> io::Result<()>;
+    fn finish_check(&mut self, pkgname: &str, version: &str) -> io::Result<()>;
+
     fn start_package(&mut self) -> io::Result<()>;
     fn on_package_line(&mut self, line: &str) -> io::Result<()>;
     fn finish_package(&mut self, output_file: &str) -> io::Result<()>;
@@ -100,14 +100,17 @@ fn show_with_columns_inner(items: &[String], colored: Option<&[String]>, indent:
 
 /// Tracks build/package output lines for the scrolling gutter.
 struct BuildState {
+    /// The spinner line shown above the gutter (e.g. "Building...").
+    header: &'static str,
     lines: Vec<String>,
     /// How many gutter lines we last wrote (so we know how far to move up).
     drawn_lines: u16,
 }
 
 impl BuildState {
-    fn new() -> Self {
+    fn new(header: &'static str) -> Self {
         Self {
+            header,
             lines: Vec::new(),
             drawn_lines: 0,
         }
@@ -118,7 +121,7 @@ impl BuildState {
     }
 
     /// Redraw the gutter: move up over previous output, clear, rewrite last N lines.
-    fn redraw(&mut self, header: &str, tick: usize) -> io::Result<()> {
+    fn redraw(&mut self, tick: usize) -> io::Result<()> {
         let mut out = io::stdout();
 
         if self.drawn_lines > 0 {
@@ -127,7 +130,7 @@ impl BuildState {
 
         let spinner = SPINNERS[tick % SPINNERS.len()];
         clear_line(&mut out)?;
-        writeln!(out, "{} {}", spinner.blue(), header.bold())?;
+        writeln!(out, "{} {}", spinner.blue(), self.header.bold())?;
 
         let start = self.lines.len().saturating_sub(BUILD_GUTTER_MAX);
         let visible = &self.lines[start..];
@@ -206,8 +209,8 @@ impl KocaCreateUi {
     pub fn new() -> io::Result<Self> {
         Ok(Self {
             tick: 0,
-            build_state: BuildState::new(),
-            pkg_state: BuildState::new(),
+            build_state: BuildState::new("Building..."),
+            pkg_state: BuildState::new("Packaging..."),
             dl_bytes_done: 0,
             dl_total_bytes: 0,
             dl_percent: None,
@@ -636,10 +639,10 @@ impl CreateUi for KocaCreateUi {
             self.redraw_install()?;
         }
         if self.build_state.drawn_lines > 0 {
-            self.build_state.redraw("Building...", self.tick)?;
+            self.build_state.redraw(self.tick)?;
         }
         if self.pkg_state.drawn_lines > 0 {
-            self.pkg_state.redraw("Packaging...", self.tick)?;
+            self.pkg_state.redraw(self.tick)?;
         }
         Ok(())
     }
@@ -792,14 +795,38 @@ impl CreateUi for KocaCreateUi {
         out.flush()
     }
 
+    fn start_prepare(&mut self) -> io::Result<()> {
+        self.build_state = BuildState::new("Preparing...");
+        self.build_state.redraw(self.tick)
+    }
+
+    fn finish_prepare(&mut self, pkgname: &str) -> io::Result<()> {
+        self.build_state
+            .finish(&format!("{} {}", "Prepared".green(), pkgname.bold()))
+    }
+
+    fn start_pkgver(&mut self) -> io::Result<()> {
+        self.build_state = BuildState::new("Updating version...");
+        self.build_state.redraw(self.tick)
+    }
+
+    fn finish_pkgver(&mut self, pkgname: &str, version: &str) -> io::Result<()> {
+        self.build_state.finish(&format!(
+            "{} {} {}",
+            "Versioned".green(),
+            pkgname.bold(),
+            version.dimmed()
+        ))
+    }
+
     fn start_build(&mut self) -> io::Result<()> {
-        self.build_state = BuildState::new();
-        self.build_state.redraw("Building...", self.tick)
+        self.build_state = BuildState::new("Building...");
+        self.build_state.redraw(self.tick)
     }
 
     fn on_build_line(&mut self, line: &str) -> io::Result<()> {
         self.build_state.push(line.to_string());
-        self.build_state.redraw("Building...", self.tick)
+        self.build_state.redraw(self.tick)
     }
 
     fn finish_build(&mut self, pkgname: &str, version: &str) -> io::Result<()> {
@@ -811,14 +838,28 @@ impl CreateUi for KocaCreateUi {
         ))
     }
 
+    fn start_check(&mut self) -> io::Result<()> {
+        self.build_state = BuildState::new("Checking...");
+        self.build_state.redraw(self.tick)
+    }
+
+    fn finish_check(&mut self, pkgname: &str, version: &str) -> io::Result<()> {
+        self.build_state.finish(&format!(
+            "{} {} {}",
+            "Checked".green(),
+            pkgname.bold(),
+            version.dimmed()
+        ))
+    }
+
     fn start_package(&mut self) -> io::Result<()> {
-        self.pkg_state = BuildState::new();
-        self.pkg_state.redraw("Packaging...", self.tick)
+        self.pkg_state = BuildState::new("Packaging...");
+        self.pkg_state.redraw(self.tick)
     }
 
     fn on_package_line(&mut self, line: &str) -> io::Result<()> {
         self.pkg_state.push(line.to_string());
-        self.pkg_state.redraw("Packaging...", self.tick)
+        self.pkg_state.redraw(self.tick)
     }
 
     fn finish_package(&mut self, output_file: &str) -> io::Result<()> {
@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use koca::{
+    edit,
+    source::{ChecksumKind, SKIP},
+    BuildFile,
+};
+
+use crate::{
+    cli::UpdpkgsumsArgs,
+    create::fetch_sources,
+    error::{CliError, CliMultiError, CliMultiResult},
+    tui::{CreateUi, KocaCreateUi},
+};
+
+pub async fn run(args: UpdpkgsumsArgs) -> CliMultiResult<()> {
+    let mut ui: Box<dyn CreateUi> = Box::new(KocaCreateUi::new()?);
+
+    let result = run_inner(&args, ui.as_mut()).await;
+
+    ui.cleanup();
+
+    result
+}
+
+async fn run_inner(args: &UpdpkgsumsArgs, ui: &mut dyn CreateUi) -> CliMultiResult<()> {
+    let build_file_path = match &args.build_file {
+        Some(p) => p.clone(),
+        None => crate::discover::find_build_file()?,
+    };
+
+    // Sources may have been added or removed since the checksums were last written.
+    let build_file = BuildFile::parse_file_unchecked(&build_file_path)
+        .await
+        .map_err(|errs| {
+            CliMultiError(errs.into_iter().map(|err| CliError::Koca { err }).collect())
+        })?;
+
+    let mut raw = std::fs::read_to_string(&build_file_path)?;
+    let srcdir = Path::new("koca-build/src");
+
+    // Use the base array's algorithms for arch arrays that don't declare their own.
+    let base_kinds = build_file
+        .source_arrays()
+        .into_iter()
+        .find(|(key, _)| *key == koca::vars::SOURCE)
+        .map(|(_, sources)| declared_kinds(sources))
+        .unwrap_or_default();
+
+    for (key, sources) in build_file.source_arrays() {
+        if sources.is_empty() {
+            continue;
+        }
+
+        // Fetch without verifying: the old checksums are what we're replacing.
+        let unverified: Vec<_> = sources
+            .iter()
+            .cloned()
+            .map(|mut source| {
+                source.checksums.clear();
+                source
+            })
+            .collect();
+        fetch_sources(&unverified, ui).await?;
+
+        let mut kinds = declared_kinds(sources);
+        if kinds.is_empty() {
+            kinds = base_kinds.clone();
+        }
+        if kinds.is_empty() {
+            kinds.push(ChecksumKind::Sha256);
+        }
+
+        let suffix = &key[koca::vars::SOURCE.len()..];
+        for kind in kinds {
+            let mut sums = Vec::with_capacity(sources.len());
+            for source in sources {
+                let path = srcdir.join(source.dest_name());
+                sums.push(if source.is_vcs() || path.is_dir() {
+                    SKIP.to_string()
+                } else {
+                    kind.digest_file(&path)
+                        .map_err(|err| CliError::Io { err })?
+                });
+            }
+
+            let name = format!("{}{suffix}", kind.var_name());
+            raw = edit::set_array(&raw, &name, &sums, key).ok_or_else(|| {
+                std::io::Error::other(format!(
+                    "couldn't find a top-level '{key}' assignment to place '{name}' after"
+                ))
+            })?;
+        }
+    }
+
+    std::fs::write(&build_file_path, raw)?;
+    zolt::infoln!("Updated checksums in {}", build_file_path.display());
+
+    Ok(())
+}
+
+/// The checksum algorithms already declared for a source array, in [`ChecksumKind::ALL`] order.
+fn declared_kinds(sources: &[koca::source::Source]) -> Vec<ChecksumKind> {
+    ChecksumKind::ALL
+        .into_iter()
+        .filter(|kind| {
+            sources
+                .iter()
+                .any(|source| source.checksums.iter().any(|c| c.kind == *kind))
+        })
+        .collect()
+}
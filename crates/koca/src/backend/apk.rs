@@ -0,0 +1,510 @@
+//! The Alpine backend, driving `apk`.
+//!
+//! Installed packages are read straight from apk's database and world file.
+//! Plans come from `apk add --simulate`, and transactions stream the same
+//! `(n/m) Installing name (version)` lines that it prints.
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::process::Stdio;
+
+use super::transport::BackendSession;
+use super::types::{
+    ActionKind, ErrorCode, Event as ProtoEvent, InstallEvent as ProtoInstallEvent, InstalledStatus,
+    Message, MessageBody, PackageStatus, PlannedAction, ProtocolError,
+    RemoveEvent as ProtoRemoveEvent, ResultPayload,
+};
+use tokio::sync::mpsc;
+
+const INSTALLED_DB: &str = "/lib/apk/db/installed";
+const WORLD_FILE: &str = "/etc/apk/world";
+
+fn run_apk(args: &[&str]) -> Result<std::process::Output, ProtocolError> {
+    std::process::Command::new("apk")
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| ProtocolError {
+            code: ErrorCode::Internal,
+            message: format!("failed to run apk: {e}"),
+        })
+}
+
+/// NOTE: These error strings come from apk-tools' English output.
+fn classify_apk_error(output: &str) -> ErrorCode {
+    if output.contains("(no such package)") {
+        ErrorCode::PackageNotFound
+    } else if output.contains("Unable to lock database") {
+        ErrorCode::DatabaseLocked
+    } else if output.contains("conflicts:") || output.contains("breaks:") {
+        ErrorCode::DependencyConflict
+    } else {
+        ErrorCode::Internal
+    }
+}
+
+fn apk_error(output: &std::process::Output) -> ProtocolError {
+    // apk prints resolver errors on stdout and the summary on stderr.
+    let message = format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&output.stdout).trim(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+    .trim()
+    .to_string();
+    ProtocolError {
+        code: classify_apk_error(&message),
+        message,
+    }
+}
+
+fn check_preconditions() -> Result<(), ProtocolError> {
+    if !nix::unistd::geteuid().is_root() {
+        return Err(ProtocolError {
+            code: ErrorCode::NeedsElevation,
+            message: "must be run as root".into(),
+        });
+    }
+    Ok(())
+}
+
+// ── Installed DB ─────────────────────────────────────────────────────────
+
+/// Map installed package names to versions from apk's `installed` database,
+/// whose records are blank-line separated `K:value` lines.
+fn parse_installed_db(content: &str) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    for record in content.split("\n\n") {
+        let mut name = None;
+        let mut version = None;
+        for line in record.lines() {
+            if let Some(val) = line.strip_prefix("P:") {
+                name = Some(val.to_string());
+            } else if let Some(val) = line.strip_prefix("V:") {
+                version = Some(val.to_string());
+            }
+        }
+        if let (Some(name), Some(version)) = (name, version) {
+            result.insert(name, version);
+        }
+    }
+    result
+}
+
+/// Names in the world file, i.e. packages that were explicitly installed.
+/// Entries can carry constraints (`gcc>=13`) or repository tags (`gcc@edge`).
+fn parse_world(content: &str) -> HashSet<String> {
+    content
+        .split_whitespace()
+        .map(|entry| {
+            entry
+                .split(['<', '>', '=', '~', '@'])
+                .next()
+                .unwrap_or(entry)
+                .to_string()
+        })
+        .collect()
+}
+
+// ── Protocol handlers ────────────────────────────────────────────────────
+
+pub fn check_installed(packages: &[String]) -> Result<ResultPayload, ProtocolError> {
+    let installed = std::fs::read_to_string(INSTALLED_DB)
+        .map(|content| parse_installed_db(&content))
+        .unwrap_or_default();
+    let world = std::fs::read_to_string(WORLD_FILE)
+        .map(|content| parse_world(&content))
+        .unwrap_or_default();
+
+    let statuses = packages
+        .iter()
+        .map(|name| match installed.get(name) {
+            Some(version) => PackageStatus {
+                name: name.clone(),
+                status: InstalledStatus::Installed,
+                version: Some(version.clone()),
+                is_auto: Some(!world.contains(name)),
+            },
+            None => PackageStatus {
+                name: name.clone(),
+                status: InstalledStatus::Missing,
+                version: None,
+                is_auto: None,
+            },
+        })
+        .collect();
+
+    Ok(ResultPayload::CheckInstalled { packages: statuses })
+}
+
+pub fn install_plan(packages: &[String]) -> Result<(ResultPayload, Vec<String>), ProtocolError> {
+    let mut args: Vec<&str> = vec!["add", "--simulate", "--no-progress"];
+    args.extend(packages.iter().map(|s| s.as_str()));
+    let output = run_apk(&args)?;
+    if !output.status.success() {
+        return Err(apk_error(&output));
+    }
+
+    let steps: Vec<Step> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_step_line)
+        .collect();
+    let names: Vec<String> = steps.iter().map(|step| step.package.clone()).collect();
+    let sizes = query_installed_sizes(&names);
+
+    let actions: Vec<PlannedAction> = steps
+        .into_iter()
+        .filter_map(|step| {
+            let action = match step.verb.as_str() {
+                "Installing" => ActionKind::Install,
+                "Upgrading" => ActionKind::Upgrade,
+                "Downgrading" => ActionKind::Downgrade,
+                "Reinstalling" | "Replacing" => ActionKind::Reinstall,
+                _ => return None,
+            };
+            // Upgrades and downgrades print `old -> new`.
+            let (old_version, version) = match step.version.split_once(" -> ") {
+                Some((old, new)) => (Some(old.to_string()), new.to_string()),
+                None => (None, step.version),
+            };
+            Some(PlannedAction {
+                install_size: sizes.get(&step.package).copied().unwrap_or(0),
+                name: step.package,
+                version,
+                old_version,
+                action,
+                download_size: 0,
+            })
+        })
+        .collect();
+
+    Ok((
+        ResultPayload::InstallPlan {
+            total_download: 0,
+            total_install: actions.iter().map(|a| a.install_size).sum(),
+            actions,
+        },
+        names,
+    ))
+}
+
+/// Look up installed sizes with `apk info --size`, which prints each package as
+/// a `name-version installed size:` line followed by a size like `52 MiB`.
+fn query_installed_sizes(packages: &[String]) -> HashMap<String, u64> {
+    if packages.is_empty() {
+        return HashMap::new();
+    }
+    let mut args: Vec<&str> = vec!["info", "--size"];
+    args.extend(packages.iter().map(|s| s.as_str()));
+    let Ok(output) = run_apk(&args) else {
+        return HashMap::new();
+    };
+    parse_info_sizes(&String::from_utf8_lossy(&output.stdout), packages)
+}
+
+fn parse_info_sizes(output: &str, packages: &[String]) -> HashMap<String, u64> {
+    let mut result = HashMap::new();
+    let mut lines = output.lines();
+    while let Some(line) = lines.next() {
+        let Some(pkgver) = line.strip_suffix(" installed size:") else {
+            continue;
+        };
+        let Some(size) = lines.next() else {
+            break;
+        };
+        // `pkgver` is `name-version-rN`, and names can contain `-` too.
+        let Some(name) = packages
+            .iter()
+            .filter(|name| pkgver.starts_with(&format!("{name}-")))
+            .max_by_key(|name| name.len())
+        else {
+            continue;
+        };
+        let mut parts = size.split_whitespace();
+        let (Some(number), Some(unit)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let multiplier: u64 = match unit {
+            "B" | "bytes" => 1,
+            "KiB" => 1 << 10,
+            "MiB" => 1 << 20,
+            "GiB" => 1 << 30,
+            _ => continue,
+        };
+        if let Ok(number) = number.parse::<u64>() {
+            result.insert(name.clone(), number * multiplier);
+        }
+    }
+    result
+}
+
+// ── Progress parsing ─────────────────────────────────────────────────────
+
+/// One `(n/m) Verb name (version)` line from `apk add` or `apk del`.
+#[derive(Debug, PartialEq)]
+struct Step {
+    current: u32,
+    total: u32,
+    verb: String,
+    package: String,
+    version: String,
+}
+
+/// NOTE: These lines are human-oriented apk output, e.g.
+/// `(2/3) Installing libstdc++ (13.2.1_git20231014-r0)` or
+/// `(1/1) Upgrading musl (1.2.4-r1 -> 1.2.4-r2)`.
+fn parse_step_line(line: &str) -> Option<Step> {
+    let rest = line.trim().strip_prefix('(')?;
+    let (counter, rest) = rest.split_once(") ")?;
+    let (current, total) = counter.split_once('/')?;
+    let mut words = rest.splitn(3, ' ');
+    let verb = words.next()?;
+    let package = words.next()?;
+    let version = words.next()?.trim().strip_prefix('(')?.strip_suffix(')')?;
+    Some(Step {
+        current: current.parse().ok()?,
+        total: total.parse().ok()?,
+        verb: verb.to_string(),
+        package: package.to_string(),
+        version: version.to_string(),
+    })
+}
+
+/// `Executing busybox-1.36.1-r5.trigger` → `Some("busybox-1.36.1-r5.trigger")`
+fn parse_hook_line(line: &str) -> Option<&str> {
+    line.trim().strip_prefix("Executing ")
+}
+
+// ── commit (install or remove) ───────────────────────────────────────────
+
+/// Run `apk add` or `apk del`, turning its output into protocol events as it goes.
+fn run_apk_with_progress(
+    pkgs: &[String],
+    is_remove: bool,
+    event_tx: &mpsc::UnboundedSender<ProtoEvent>,
+) -> Result<Vec<String>, ProtocolError> {
+    let mut args: Vec<&str> = vec![if is_remove { "del" } else { "add" }, "--no-progress"];
+    args.extend(pkgs.iter().map(|s| s.as_str()));
+
+    let mut child = std::process::Command::new("apk")
+        .args(&args)
+        .env("LC_ALL", "C")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ProtocolError {
+            code: ErrorCode::Internal,
+            message: format!("failed to spawn apk: {e}"),
+        })?;
+
+    let stdout = child.stdout.take().expect("stdout should be piped");
+    let tx = event_tx.clone();
+    let stdout_handle = std::thread::spawn(move || {
+        let mut transcript = String::new();
+        let mut started = false;
+
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            transcript.push_str(&line);
+            transcript.push('\n');
+
+            if let Some(step) = parse_step_line(&line) {
+                if !started {
+                    started = true;
+                    let _ = tx.send(if is_remove {
+                        ProtoEvent::Remove {
+                            inner: ProtoRemoveEvent::Start {
+                                total_packages: step.total,
+                            },
+                        }
+                    } else {
+                        ProtoEvent::Install {
+                            inner: ProtoInstallEvent::Start {
+                                total_packages: step.total,
+                            },
+                        }
+                    });
+                }
+                let _ = tx.send(if is_remove {
+                    ProtoEvent::Remove {
+                        inner: ProtoRemoveEvent::ItemDone {
+                            package: step.package,
+                            current: step.current,
+                            total: step.total,
+                        },
+                    }
+                } else {
+                    ProtoEvent::Install {
+                        inner: ProtoInstallEvent::ItemDone {
+                            package: step.package,
+                            current: step.current,
+                            total: step.total,
+                        },
+                    }
+                });
+            } else if let Some(hook) = parse_hook_line(&line) {
+                if !is_remove {
+                    let _ = tx.send(ProtoEvent::Install {
+                        inner: ProtoInstallEvent::Hook {
+                            name: hook.to_string(),
+                            current: 0,
+                            total: 0,
+                        },
+                    });
+                }
+            }
+        }
+        (transcript, started)
+    });
+
+    let output = child.wait_with_output().map_err(|e| ProtocolError {
+        code: ErrorCode::Internal,
+        message: format!("failed to wait for apk: {e}"),
+    })?;
+    let (transcript, started) = stdout_handle.join().unwrap_or_default();
+
+    if !started {
+        // Nothing to do (e.g. everything was already installed).
+        let total_packages = 0;
+        let _ = event_tx.send(if is_remove {
+            ProtoEvent::Remove {
+                inner: ProtoRemoveEvent::Start { total_packages },
+            }
+        } else {
+            ProtoEvent::Install {
+                inner: ProtoInstallEvent::Start { total_packages },
+            }
+        });
+    }
+    let _ = event_tx.send(if is_remove {
+        ProtoEvent::Remove {
+            inner: ProtoRemoveEvent::Done,
+        }
+    } else {
+        ProtoEvent::Install {
+            inner: ProtoInstallEvent::Done,
+        }
+    });
+
+    if !output.status.success() {
+        let message = format!(
+            "{}\n{}",
+            transcript.trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .trim()
+        .to_string();
+        return Err(ProtocolError {
+            code: classify_apk_error(&message),
+            message,
+        });
+    }
+    Ok(pkgs.to_vec())
+}
+
+pub async fn commit_transaction(
+    msg_id: u64,
+    packages: Vec<String>,
+    is_remove: bool,
+    session: &mut BackendSession,
+) {
+    if let Err(error) = check_preconditions() {
+        let _ = session
+            .send(&Message {
+                id: msg_id,
+                body: MessageBody::Error { error },
+            })
+            .await;
+        return;
+    }
+
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<ProtoEvent>();
+    // apk fetches and installs each package in turn, so there's no separate
+    // download phase to report.
+    let join_handle =
+        tokio::task::spawn_blocking(move || run_apk_with_progress(&packages, is_remove, &event_tx));
+
+    while let Some(event) = event_rx.recv().await {
+        let _ = session
+            .send(&Message {
+                id: msg_id,
+                body: MessageBody::Event { event },
+            })
+            .await;
+    }
+
+    let body = match join_handle.await {
+        Ok(Ok(names)) => MessageBody::Result {
+            result: if is_remove {
+                ResultPayload::Remove {
+                    success: true,
+                    removed: names,
+                }
+            } else {
+                ResultPayload::Install {
+                    success: true,
+                    installed: names,
+                }
+            },
+        },
+        Ok(Err(error)) => MessageBody::Error { error },
+        Err(_) => MessageBody::Error {
+            error: ProtocolError {
+                code: ErrorCode::Internal,
+                message: "backend task panicked".into(),
+            },
+        },
+    };
+    let _ = session.send(&Message { id: msg_id, body }).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_simulated_add() {
+        let output = "\
+(1/3) Installing libgcc (13.2.1_git20231014-r0)
+(2/3) Upgrading musl (1.2.4-r1 -> 1.2.4-r2)
+(3/3) Installing gcc (13.2.1_git20231014-r0)
+Executing busybox-1.36.1-r5.trigger
+OK: 123 MiB in 40 packages
+";
+        let steps: Vec<Step> = output.lines().filter_map(parse_step_line).collect();
+        assert_eq!(steps.len(), 3);
+        assert_eq!(
+            steps[1],
+            Step {
+                current: 2,
+                total: 3,
+                verb: "Upgrading".into(),
+                package: "musl".into(),
+                version: "1.2.4-r1 -> 1.2.4-r2".into(),
+            }
+        );
+        assert_eq!(
+            parse_hook_line("Executing busybox-1.36.1-r5.trigger"),
+            Some("busybox-1.36.1-r5.trigger")
+        );
+    }
+
+    #[test]
+    fn parses_installed_db_and_world() {
+        let db = "C:Q1abc=\nP:musl\nV:1.2.4-r2\nA:x86_64\n\nC:Q1def=\nP:gcc\nV:13.2.1-r0\n";
+        let installed = parse_installed_db(db);
+        assert_eq!(installed.get("musl").map(String::as_str), Some("1.2.4-r2"));
+        assert_eq!(installed.get("gcc").map(String::as_str), Some("13.2.1-r0"));
+
+        let world = parse_world("alpine-base\ngcc>=13\nrust@edge\n");
+        assert!(world.contains("gcc"));
+        assert!(world.contains("rust"));
+        assert!(!world.contains("musl"));
+    }
+
+    #[test]
+    fn parses_info_sizes() {
+        let output = "gcc-13.2.1-r0 installed size:\n52 MiB\n\ngcc-gnat-13.2.1-r0 installed size:\n80 MiB\n\n";
+        let sizes = parse_info_sizes(output, &["gcc".into(), "gcc-gnat".into()]);
+        assert_eq!(sizes.get("gcc"), Some(&(52 << 20)));
+        assert_eq!(sizes.get("gcc-gnat"), Some(&(80 << 20)));
+    }
+}
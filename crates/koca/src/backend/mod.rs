@@ -1,6 +1,8 @@
 pub mod alpm;
+pub mod apk;
 pub mod apt;
 pub mod error;
+pub mod rpm;
 pub mod transport;
 pub mod types;
 
@@ -17,10 +19,13 @@ pub use types::{
 
 // ── BackendKind ──────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BackendKind {
     Apt,
     Alpm,
+    Dnf,
+    Zypper,
+    Apk,
 }
 
 // ── Backend (sudo subprocess with socket IPC) ────────────────────────────
@@ -89,7 +94,7 @@ impl Backend {
 
 // ── Shared backend loop (for subprocess side) ────────────────────────────
 
-/// Run the backend command loop. Called from `koca internal backend-{apt,alpm}`.
+/// Run the backend command loop. Called from `koca internal backend-{apt,alpm,dnf,zypper,apk}`.
 pub async fn run_backend_loop(socket: &str, kind: BackendKind) -> anyhow::Result<()> {
     let mut session = BackendSession::connect(socket).await?;
     let mut pending: Option<Vec<String>> = None;
@@ -181,6 +186,9 @@ pub fn dispatch_check_installed(
     match kind {
         BackendKind::Apt => apt::check_installed(packages),
         BackendKind::Alpm => alpm::check_installed(packages),
+        BackendKind::Dnf => rpm::check_installed(rpm::Frontend::Dnf, packages),
+        BackendKind::Zypper => rpm::check_installed(rpm::Frontend::Zypper, packages),
+        BackendKind::Apk => apk::check_installed(packages),
     }
 }
 
@@ -191,6 +199,9 @@ pub fn dispatch_install_plan(
     match kind {
         BackendKind::Apt => apt::install_plan(packages),
         BackendKind::Alpm => alpm::install_plan(packages),
+        BackendKind::Dnf => rpm::install_plan(rpm::Frontend::Dnf, packages),
+        BackendKind::Zypper => rpm::install_plan(rpm::Frontend::Zypper, packages),
+        BackendKind::Apk => apk::install_plan(packages),
     }
 }
 
@@ -204,6 +215,14 @@ async fn dispatch_commit(
     match kind {
         BackendKind::Apt => apt::commit_transaction(msg_id, packages, is_remove, session).await,
         BackendKind::Alpm => alpm::commit_transaction(msg_id, packages, is_remove, session).await,
+        BackendKind::Dnf => {
+            rpm::commit_transaction(rpm::Frontend::Dnf, msg_id, packages, is_remove, session).await
+        }
+        BackendKind::Zypper => {
+            rpm::commit_transaction(rpm::Frontend::Zypper, msg_id, packages, is_remove, session)
+                .await
+        }
+        BackendKind::Apk => apk::commit_transaction(msg_id, packages, is_remove, session).await,
     }
 }
 
@@ -219,6 +238,9 @@ fn spawn_process(
     let subcommand = match kind {
         BackendKind::Apt => "backend-apt",
         BackendKind::Alpm => "backend-alpm",
+        BackendKind::Dnf => "backend-dnf",
+        BackendKind::Zypper => "backend-zypper",
+        BackendKind::Apk => "backend-apk",
     };
 
     let mut cmd = if sudo && !nix::unistd::geteuid().is_root() {
@@ -0,0 +1,809 @@
+//! The RPM backend, driving `dnf` (Fedora, RHEL and rebuilds) or `zypper` (openSUSE).
+//!
+//! Both frontends sit on the same rpm database, so install checks query `rpm`
+//! directly. Planning and transactions go through the frontend's CLI, whose
+//! progress lines are parsed into protocol events.
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader};
+use std::process::Stdio;
+
+use super::transport::BackendSession;
+use super::types::{
+    ActionKind, DownloadEvent as ProtoDownloadEvent, ErrorCode, Event as ProtoEvent,
+    InstallEvent as ProtoInstallEvent, InstalledStatus, Message, MessageBody, PackageStatus,
+    PlannedAction, ProtocolError, RemoveEvent as ProtoRemoveEvent, ResultPayload,
+};
+use tokio::sync::mpsc;
+
+/// zypper's list of packages installed only as dependencies.
+const ZYPP_AUTO_INSTALLED: &str = "/var/lib/zypp/AutoInstalled";
+
+/// The command-line frontend used to resolve and install packages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frontend {
+    Dnf,
+    Zypper,
+}
+
+impl Frontend {
+    fn program(self) -> &'static str {
+        match self {
+            Frontend::Dnf => "dnf",
+            Frontend::Zypper => "zypper",
+        }
+    }
+
+    /// Arguments to install or remove packages without prompting.
+    fn commit_args(self, is_remove: bool) -> &'static [&'static str] {
+        match (self, is_remove) {
+            (Frontend::Dnf, false) => &["install", "-y"],
+            (Frontend::Dnf, true) => &["remove", "-y"],
+            (Frontend::Zypper, false) => {
+                &["--non-interactive", "install", "--auto-agree-with-licenses"]
+            }
+            (Frontend::Zypper, true) => &["--non-interactive", "remove"],
+        }
+    }
+}
+
+fn run_cmd(program: &str, args: &[&str]) -> Result<std::process::Output, ProtocolError> {
+    std::process::Command::new(program)
+        .args(args)
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| ProtocolError {
+            code: ErrorCode::Internal,
+            message: format!("failed to run {program}: {e}"),
+        })
+}
+
+fn check_preconditions() -> Result<(), ProtocolError> {
+    if !nix::unistd::geteuid().is_root() {
+        return Err(ProtocolError {
+            code: ErrorCode::NeedsElevation,
+            message: "must be run as root".into(),
+        });
+    }
+    Ok(())
+}
+
+/// NOTE: These strings come from dnf (4 and 5) and zypper's English output.
+fn classify_error(output: &str) -> ErrorCode {
+    if output.contains("No match for argument")
+        || output.contains("not found in package names")
+        || output.contains("No provider of")
+    {
+        ErrorCode::PackageNotFound
+    } else if output.contains("Waiting for process with pid")
+        || output.contains("System management is locked")
+    {
+        ErrorCode::DatabaseLocked
+    } else if output.contains("conflicts with") || output.contains("nothing provides") {
+        ErrorCode::DependencyConflict
+    } else {
+        ErrorCode::Internal
+    }
+}
+
+fn command_error(output: &std::process::Output) -> ProtocolError {
+    // zypper reports most failures on stdout.
+    let mut message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if message.is_empty() {
+        message = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    }
+    ProtocolError {
+        code: classify_error(&message),
+        message,
+    }
+}
+
+// ── rpm database ─────────────────────────────────────────────────────────
+
+/// The installed `[epoch:]version-release` of whatever provides `name`, if anything.
+///
+/// Going through `--whatprovides` lets makedepends name capabilities such as
+/// `pkgconfig(zlib)` as well as packages.
+fn query_rpm(name: &str) -> Option<String> {
+    let output = run_cmd(
+        "rpm",
+        &[
+            "-q",
+            "--whatprovides",
+            "--qf",
+            "%|EPOCH?{%{EPOCH}:}:{}|%{VERSION}-%{RELEASE}\\n",
+            name,
+        ],
+    )
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+}
+
+/// Names of the given packages that were installed only as dependencies, or
+/// [`None`] if the frontend can't tell us.
+fn query_auto_installed(frontend: Frontend, packages: &[String]) -> Option<HashSet<String>> {
+    match frontend {
+        Frontend::Dnf => {
+            let output = run_cmd(
+                "dnf",
+                &["repoquery", "--userinstalled", "--qf", "%{name}\\n"],
+            )
+            .ok()
+            .filter(|output| output.status.success())?;
+            let user: HashSet<String> = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|line| line.trim().to_string())
+                .collect();
+            Some(
+                packages
+                    .iter()
+                    .filter(|name| !user.contains(*name))
+                    .cloned()
+                    .collect(),
+            )
+        }
+        Frontend::Zypper => {
+            let content = std::fs::read_to_string(ZYPP_AUTO_INSTALLED).ok()?;
+            Some(
+                content
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string)
+                    .collect(),
+            )
+        }
+    }
+}
+
+// ── Protocol handlers ────────────────────────────────────────────────────
+
+pub fn check_installed(
+    frontend: Frontend,
+    packages: &[String],
+) -> Result<ResultPayload, ProtocolError> {
+    let auto = query_auto_installed(frontend, packages);
+    let statuses = packages
+        .iter()
+        .map(|name| match query_rpm(name) {
+            Some(version) => PackageStatus {
+                name: name.clone(),
+                status: InstalledStatus::Installed,
+                version: Some(version),
+                is_auto: auto.as_ref().map(|auto| auto.contains(name)),
+            },
+            None => PackageStatus {
+                name: name.clone(),
+                status: InstalledStatus::Missing,
+                version: None,
+                is_auto: None,
+            },
+        })
+        .collect();
+
+    Ok(ResultPayload::CheckInstalled { packages: statuses })
+}
+
+pub fn install_plan(
+    frontend: Frontend,
+    packages: &[String],
+) -> Result<(ResultPayload, Vec<String>), ProtocolError> {
+    let actions = match frontend {
+        Frontend::Dnf => dnf_plan(packages)?,
+        Frontend::Zypper => zypper_plan(packages)?,
+    };
+    let names = actions.iter().map(|action| action.name.clone()).collect();
+
+    Ok((
+        ResultPayload::InstallPlan {
+            total_download: actions.iter().map(|a| a.download_size).sum(),
+            total_install: actions.iter().map(|a| a.install_size).sum(),
+            actions,
+        },
+        names,
+    ))
+}
+
+/// Resolve a dnf transaction by answering "no" to its confirmation prompt.
+fn dnf_plan(packages: &[String]) -> Result<Vec<PlannedAction>, ProtocolError> {
+    let mut args: Vec<&str> = vec!["install", "--assumeno"];
+    args.extend(packages.iter().map(|s| s.as_str()));
+    let output = run_cmd("dnf", &args)?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let actions = parse_dnf_transaction(&stdout);
+
+    // Declining the transaction makes dnf exit non-zero, so only treat that as
+    // a failure when there was nothing to decline.
+    if !output.status.success() && actions.is_empty() {
+        return Err(command_error(&output));
+    }
+    Ok(actions)
+}
+
+/// NOTE: This parses the transaction table dnf prints before its confirmation
+/// prompt. It's human-oriented output, so keep parser coverage in unit tests.
+///
+/// Rows are `name arch version repository size unit` under headings such as
+/// `Installing dependencies:`. dnf 4 wraps long names onto their own line, and
+/// dnf 5 lists the version being upgraded from on a `replacing` row. dnf 4's
+/// size column is the download size; dnf 5's is the installed size.
+fn parse_dnf_transaction(output: &str) -> Vec<PlannedAction> {
+    let mut actions: Vec<PlannedAction> = Vec::new();
+    let mut section: Option<ActionKind> = None;
+    let mut wrapped_name: Option<String> = None;
+
+    for line in output.lines() {
+        if !line.starts_with(' ') {
+            let heading = line.trim_end();
+            section = heading.strip_suffix(':').and_then(|heading| {
+                let verb = heading.split_whitespace().next()?;
+                match verb {
+                    "Installing" => Some(ActionKind::Install),
+                    "Upgrading" => Some(ActionKind::Upgrade),
+                    "Downgrading" => Some(ActionKind::Downgrade),
+                    "Reinstalling" => Some(ActionKind::Reinstall),
+                    _ => None,
+                }
+            });
+            wrapped_name = None;
+            continue;
+        }
+        let Some(action) = section else {
+            continue;
+        };
+
+        if line.split_whitespace().count() == 1 {
+            wrapped_name = Some(line.trim().to_string());
+            continue;
+        }
+        let row = match wrapped_name.take() {
+            Some(name) => format!("{name} {line}"),
+            None => line.to_string(),
+        };
+        let fields: Vec<&str> = row.split_whitespace().collect();
+
+        if fields.first() == Some(&"replacing") {
+            if let (Some(last), Some(version)) = (actions.last_mut(), fields.get(3)) {
+                last.old_version = Some(version.to_string());
+            }
+            continue;
+        }
+        if fields.len() < 6 {
+            continue;
+        }
+        actions.push(PlannedAction {
+            name: fields[0].to_string(),
+            version: fields[2].to_string(),
+            old_version: None,
+            action,
+            download_size: parse_size(fields[4], fields[5]),
+            install_size: 0,
+        });
+    }
+
+    actions
+}
+
+/// Parse a size like `711 k` or `2.9 MiB` into bytes.
+fn parse_size(number: &str, unit: &str) -> u64 {
+    let Ok(number) = number.parse::<f64>() else {
+        return 0;
+    };
+    let multiplier: u64 = match unit.trim_end_matches("iB").trim_end_matches('B') {
+        "" => 1,
+        "k" | "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return 0,
+    };
+    (number * multiplier as f64) as u64
+}
+
+/// zypper won't resolve a transaction without root, even with `--dry-run`, so
+/// the plan only covers the requested packages. Their dependencies are
+/// resolved and installed when the transaction runs.
+fn zypper_plan(packages: &[String]) -> Result<Vec<PlannedAction>, ProtocolError> {
+    let mut args: Vec<&str> = vec![
+        "--non-interactive",
+        "--xmlout",
+        "search",
+        "--match-exact",
+        "--details",
+        "--type",
+        "package",
+    ];
+    args.extend(packages.iter().map(|s| s.as_str()));
+    let output = run_cmd("zypper", &args)?;
+    let solvables = parse_zypper_solvables(&String::from_utf8_lossy(&output.stdout));
+
+    let mut actions = Vec::new();
+    for name in packages {
+        let Some(solvable) = solvables.iter().find(|s| &s.name == name) else {
+            return Err(ProtocolError {
+                code: ErrorCode::PackageNotFound,
+                message: format!("package '{name}' not found in package names"),
+            });
+        };
+        actions.push(PlannedAction {
+            name: name.clone(),
+            version: solvable.edition.clone(),
+            old_version: None,
+            action: ActionKind::Install,
+            download_size: 0,
+            install_size: 0,
+        });
+    }
+    Ok(actions)
+}
+
+#[derive(Debug, PartialEq)]
+struct Solvable {
+    name: String,
+    edition: String,
+}
+
+/// Pull the not-yet-installed `<solvable .../>` entries out of zypper's XML output.
+fn parse_zypper_solvables(xml: &str) -> Vec<Solvable> {
+    let attr = |tag: &str, key: &str| -> Option<String> {
+        let start = tag.find(&format!(" {key}=\""))? + key.len() + 3;
+        let len = tag[start..].find('"')?;
+        Some(tag[start..start + len].to_string())
+    };
+
+    xml.split("<solvable ")
+        .skip(1)
+        .filter_map(|rest| {
+            // Keep the separating space so the first attribute matches too.
+            let tag = &format!(" {}", &rest[..rest.find('>')?]);
+            if attr(tag, "status").as_deref() == Some("installed") {
+                return None;
+            }
+            Some(Solvable {
+                name: attr(tag, "name")?,
+                edition: attr(tag, "edition")?,
+            })
+        })
+        .collect()
+}
+
+// ── Progress parsing ─────────────────────────────────────────────────────
+
+/// A step in a running transaction, parsed from the frontend's output.
+#[derive(Debug, PartialEq)]
+enum Progress {
+    /// A package finished downloading.
+    Downloaded(String),
+    /// A package is being installed or removed, as step `current` of `total`.
+    Step {
+        package: String,
+        current: u32,
+        total: u32,
+    },
+}
+
+/// NOTE: These lines are human-oriented output:
+/// - dnf 4: `(1/3): nano-7.2-6.fc40.x86_64.rpm  1.0 MB/s | ...` and
+///   `  Installing       : nano-7.2-6.fc40.x86_64     1/3`
+/// - dnf 5: `[2/5] Installing nano-0:7.2-6.fc40.x86_64  100% | ...`
+/// - zypper: `Retrieving: nano-7.2-1.1.x86_64 (repo-oss) (1/3), ...` and
+///   `(1/3) Installing: nano-7.2-1.1.x86_64 ...[done]`
+fn parse_progress_line(line: &str) -> Option<Progress> {
+    let line = line.trim();
+    const STEPS: [&str; 5] = [
+        "Installing",
+        "Upgrading",
+        "Downgrading",
+        "Removing",
+        "Erasing",
+    ];
+
+    if let Some(rest) = line.strip_prefix("Retrieving: ") {
+        let nevra = rest.split_whitespace().next()?;
+        return Some(Progress::Downloaded(package_name_from_nevra(nevra)));
+    }
+
+    // zypper and dnf 4 downloads both lead with a `(n/m)` counter.
+    if let Some(rest) = line.strip_prefix('(') {
+        let (counter, rest) = rest.split_once(')')?;
+        let (current, total) = parse_counter(counter)?;
+        let rest = rest.trim_start_matches(':').trim();
+        if let Some((verb, nevra)) = rest.split_once(": ") {
+            if STEPS.contains(&verb) {
+                let nevra = nevra.split_whitespace().next()?;
+                return Some(Progress::Step {
+                    package: package_name_from_nevra(nevra),
+                    current,
+                    total,
+                });
+            }
+        }
+        let file = rest.split_whitespace().next()?;
+        return file
+            .ends_with(".rpm")
+            .then(|| Progress::Downloaded(package_name_from_nevra(file)));
+    }
+
+    // dnf 5: `[n/m] Verb nevra ...`
+    if let Some(rest) = line.strip_prefix('[') {
+        let (counter, rest) = rest.split_once(']')?;
+        let (current, total) = parse_counter(counter)?;
+        let mut words = rest.split_whitespace();
+        let verb = words.next()?;
+        if !STEPS.contains(&verb) {
+            return None;
+        }
+        return Some(Progress::Step {
+            package: package_name_from_nevra(words.next()?),
+            current,
+            total,
+        });
+    }
+
+    // dnf 4: `Verb : nevra n/m`
+    let (verb, rest) = line.split_once(':')?;
+    if !STEPS.contains(&verb.trim()) {
+        return None;
+    }
+    let mut words = rest.split_whitespace();
+    let nevra = words.next()?;
+    let (current, total) = parse_counter(words.next()?)?;
+    Some(Progress::Step {
+        package: package_name_from_nevra(nevra),
+        current,
+        total,
+    })
+}
+
+fn parse_counter(counter: &str) -> Option<(u32, u32)> {
+    let (current, total) = counter.split_once('/')?;
+    Some((current.trim().parse().ok()?, total.trim().parse().ok()?))
+}
+
+/// Extract a package name from a NEVRA or rpm filename.
+/// e.g. `nano-0:7.2-6.fc40.x86_64` or `nano-7.2-6.fc40.x86_64.rpm` → `nano`
+///
+/// Strip the `.rpm` suffix and the `.arch`, then pop the release and version
+/// (the last two `-`-separated segments), leaving the name.
+fn package_name_from_nevra(nevra: &str) -> String {
+    let base = nevra.strip_suffix(".rpm").unwrap_or(nevra);
+    let Some((base, _arch)) = base.rsplit_once('.') else {
+        return nevra.to_string();
+    };
+    let mut rest = base;
+    for _ in 0..2 {
+        match rest.rfind('-') {
+            Some(pos) => rest = &rest[..pos],
+            None => return nevra.to_string(),
+        }
+    }
+    rest.to_string()
+}
+
+// ── commit (install or remove) ───────────────────────────────────────────
+
+/// Run the frontend, turning its output into protocol events as it goes.
+fn run_with_progress(
+    frontend: Frontend,
+    pkgs: &[String],
+    is_remove: bool,
+    n_pkgs: u32,
+    event_tx: &mpsc::UnboundedSender<ProtoEvent>,
+) -> Result<Vec<String>, ProtocolError> {
+    let mut args: Vec<&str> = frontend.commit_args(is_remove).to_vec();
+    args.extend(pkgs.iter().map(|s| s.as_str()));
+
+    let mut child = std::process::Command::new(frontend.program())
+        .args(&args)
+        .env("LC_ALL", "C")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ProtocolError {
+            code: ErrorCode::Internal,
+            message: format!("failed to spawn {}: {e}", frontend.program()),
+        })?;
+
+    let stdout = child.stdout.take().expect("stdout should be piped");
+    let tx = event_tx.clone();
+    let stdout_handle = std::thread::spawn(move || {
+        let mut transcript = String::new();
+        let mut installing = false;
+        let mut seen = HashSet::new();
+
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            transcript.push_str(&line);
+            transcript.push('\n');
+
+            match parse_progress_line(&line) {
+                Some(Progress::Downloaded(package)) if !is_remove => {
+                    let _ = tx.send(ProtoEvent::Download {
+                        inner: ProtoDownloadEvent::ItemDone { package },
+                    });
+                }
+                Some(Progress::Step { package, .. }) => {
+                    if !is_remove && !installing {
+                        installing = true;
+                        let _ = tx.send(ProtoEvent::Download {
+                            inner: ProtoDownloadEvent::Done,
+                        });
+                        let _ = tx.send(ProtoEvent::Install {
+                            inner: ProtoInstallEvent::Start {
+                                total_packages: n_pkgs,
+                            },
+                        });
+                    }
+                    // Upgrades list both the new and the old package, so only count names once.
+                    if !seen.insert(package.clone()) {
+                        continue;
+                    }
+                    let current = seen.len() as u32;
+                    let _ = tx.send(if is_remove {
+                        ProtoEvent::Remove {
+                            inner: ProtoRemoveEvent::ItemDone {
+                                package,
+                                current,
+                                total: n_pkgs,
+                            },
+                        }
+                    } else {
+                        ProtoEvent::Install {
+                            inner: ProtoInstallEvent::ItemDone {
+                                package,
+                                current,
+                                total: n_pkgs,
+                            },
+                        }
+                    });
+                }
+                _ => {}
+            }
+        }
+        (transcript, installing)
+    });
+
+    let output = child.wait_with_output().map_err(|e| ProtocolError {
+        code: ErrorCode::Internal,
+        message: format!("failed to wait for {}: {e}", frontend.program()),
+    })?;
+    let (transcript, installing) = stdout_handle.join().unwrap_or_default();
+    if !is_remove && !installing {
+        // Nothing printed an install step (e.g. everything was already installed).
+        let _ = event_tx.send(ProtoEvent::Download {
+            inner: ProtoDownloadEvent::Done,
+        });
+        let _ = event_tx.send(ProtoEvent::Install {
+            inner: ProtoInstallEvent::Start {
+                total_packages: n_pkgs,
+            },
+        });
+    }
+
+    if !output.status.success() {
+        let mut message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if message.is_empty() {
+            message = transcript.trim().to_string();
+        }
+        return Err(ProtocolError {
+            code: classify_error(&message),
+            message,
+        });
+    }
+
+    // Mark everything as a dependency so it can be cleaned up later. zypper has
+    // no command for this, and only records it for packages it pulled in itself.
+    if !is_remove && frontend == Frontend::Dnf {
+        for mark in ["dependency", "remove"] {
+            let mut mark_args: Vec<&str> = vec!["mark", mark];
+            mark_args.extend(pkgs.iter().map(|s| s.as_str()));
+            // dnf 5 spells it `mark dependency`, dnf 4 `mark remove`.
+            if run_cmd("dnf", &mark_args).is_ok_and(|output| output.status.success()) {
+                break;
+            }
+        }
+    }
+    Ok(pkgs.to_vec())
+}
+
+pub async fn commit_transaction(
+    frontend: Frontend,
+    msg_id: u64,
+    packages: Vec<String>,
+    is_remove: bool,
+    session: &mut BackendSession,
+) {
+    if let Err(error) = check_preconditions() {
+        let _ = session
+            .send(&Message {
+                id: msg_id,
+                body: MessageBody::Error { error },
+            })
+            .await;
+        return;
+    }
+
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel::<ProtoEvent>();
+    let pkgs = packages.clone();
+
+    let join_handle = tokio::task::spawn_blocking(move || {
+        if is_remove {
+            let n_pkgs = pkgs.len() as u32;
+            let _ = event_tx.send(ProtoEvent::Remove {
+                inner: ProtoRemoveEvent::Start {
+                    total_packages: n_pkgs,
+                },
+            });
+            let result = run_with_progress(frontend, &pkgs, true, n_pkgs, &event_tx);
+            let _ = event_tx.send(ProtoEvent::Remove {
+                inner: ProtoRemoveEvent::Done,
+            });
+            return result;
+        }
+
+        // Re-resolve so progress counts the dependencies too.
+        let plan: HashMap<String, u64> = match install_plan(frontend, &pkgs) {
+            Ok((ResultPayload::InstallPlan { actions, .. }, _)) => actions
+                .into_iter()
+                .map(|action| (action.name, action.download_size))
+                .collect(),
+            Ok(_) => HashMap::new(),
+            Err(error) => return Err(error),
+        };
+        let n_pkgs = plan.len().max(pkgs.len()) as u32;
+
+        let _ = event_tx.send(ProtoEvent::Download {
+            inner: ProtoDownloadEvent::Start {
+                total_bytes: plan.values().sum(),
+                total_packages: n_pkgs,
+            },
+        });
+        let result = run_with_progress(frontend, &pkgs, false, n_pkgs, &event_tx);
+        let _ = event_tx.send(ProtoEvent::Install {
+            inner: ProtoInstallEvent::Done,
+        });
+        result
+    });
+
+    while let Some(event) = event_rx.recv().await {
+        let _ = session
+            .send(&Message {
+                id: msg_id,
+                body: MessageBody::Event { event },
+            })
+            .await;
+    }
+
+    let body = match join_handle.await {
+        Ok(Ok(names)) => MessageBody::Result {
+            result: if is_remove {
+                ResultPayload::Remove {
+                    success: true,
+                    removed: names,
+                }
+            } else {
+                ResultPayload::Install {
+                    success: true,
+                    installed: names,
+                }
+            },
+        },
+        Ok(Err(error)) => MessageBody::Error { error },
+        Err(_) => MessageBody::Error {
+            error: ProtocolError {
+                code: ErrorCode::Internal,
+                message: "backend task panicked".into(),
+            },
+        },
+    };
+    let _ = session.send(&Message { id: msg_id, body }).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dnf4_transaction_table() {
+        let output = "\
+Dependencies resolved.
+================================================================================
+ Package              Architecture  Version              Repository      Size
+================================================================================
+Installing:
+ nano                 x86_64        7.2-6.fc40           updates        711 k
+Upgrading:
+ glibc                x86_64        2.39-22.fc40         updates        2.2 M
+Installing dependencies:
+ a-very-long-package-name-that-wraps
+                      noarch        1.0-1.fc40           fedora          10 k
+
+Transaction Summary
+================================================================================
+Install  2 Packages
+";
+        let actions = parse_dnf_transaction(output);
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[0].name, "nano");
+        assert_eq!(actions[0].version, "7.2-6.fc40");
+        assert_eq!(actions[0].action, ActionKind::Install);
+        assert_eq!(actions[0].download_size, 711 * 1024);
+        assert_eq!(actions[1].action, ActionKind::Upgrade);
+        assert_eq!(actions[2].name, "a-very-long-package-name-that-wraps");
+        assert_eq!(actions[2].download_size, 10 * 1024);
+    }
+
+    #[test]
+    fn parses_dnf5_replacing_rows() {
+        let output = "\
+Package           Arch   Version         Repository      Size
+Upgrading:
+ glibc            x86_64 2.39-22.fc40    updates      6.7 MiB
+   replacing glibc x86_64 2.39-17.fc40   updates      6.7 MiB
+";
+        let actions = parse_dnf_transaction(output);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].old_version.as_deref(), Some("2.39-17.fc40"));
+    }
+
+    #[test]
+    fn parses_zypper_solvables() {
+        let xml = r#"<solvable-list>
+<solvable status="installed" name="glibc" kind="package" edition="2.39-1.1" arch="x86_64" repository="(System Packages)"/>
+<solvable status="not-installed" name="nano" kind="package" edition="7.2-1.1" arch="x86_64" repository="repo-oss"/>
+</solvable-list>"#;
+        assert_eq!(
+            parse_zypper_solvables(xml),
+            [Solvable {
+                name: "nano".into(),
+                edition: "7.2-1.1".into()
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_progress_lines() {
+        let step = |package: &str, current, total| Progress::Step {
+            package: package.into(),
+            current,
+            total,
+        };
+        assert_eq!(
+            parse_progress_line("  Installing       : nano-7.2-6.fc40.x86_64     1/3"),
+            Some(step("nano", 1, 3))
+        );
+        assert_eq!(
+            parse_progress_line("[2/5] Installing nano-0:7.2-6.fc40.x86_64  100% | 1.0 MiB/s"),
+            Some(step("nano", 2, 5))
+        );
+        assert_eq!(
+            parse_progress_line("(1/3) Installing: nano-7.2-1.1.x86_64 ...[done]"),
+            Some(step("nano", 1, 3))
+        );
+        assert_eq!(
+            parse_progress_line("(1/3): nano-7.2-6.fc40.x86_64.rpm  1.0 MB/s | 711 kB  00:00"),
+            Some(Progress::Downloaded("nano".into()))
+        );
+        assert_eq!(
+            parse_progress_line("Retrieving: nano-7.2-1.1.x86_64 (repo-oss) (1/3), 711.2 KiB"),
+            Some(Progress::Downloaded("nano".into()))
+        );
+        assert_eq!(
+            parse_progress_line("  Verifying        : nano-7.2-6.fc40.x86_64     1/3"),
+            None
+        );
+    }
+
+    #[test]
+    fn extracts_names_from_nevras() {
+        assert_eq!(
+            package_name_from_nevra("gcc-c++-14.1.1-7.fc40.x86_64"),
+            "gcc-c++"
+        );
+        assert_eq!(package_name_from_nevra("nano-0:7.2-6.fc40.x86_64"), "nano");
+        assert_eq!(package_name_from_nevra("nano-7.2-1.1.x86_64.rpm"), "nano");
+    }
+}
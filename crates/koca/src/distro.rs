@@ -2,11 +2,42 @@ use std::{fs, str::FromStr};
 
 use crate::{backend::BackendKind, KocaError, KocaResult};
 
+/// Distro families by backend, with the `ID`s that belong to each.
+///
+/// An ID also matches its `-`-suffixed variants (`opensuse` covers
+/// `opensuse-tumbleweed`). Derivatives not listed here are matched through the
+/// `ID_LIKE` field of their os-release.
+const FAMILIES: &[(BackendKind, &str, &[&str])] = &[
+    (
+        BackendKind::Alpm,
+        "Arch",
+        &["arch", "manjaro", "endeavouros", "garuda"],
+    ),
+    (
+        BackendKind::Apt,
+        "Debian",
+        &["debian", "ubuntu", "linuxmint", "pop"],
+    ),
+    (
+        BackendKind::Dnf,
+        "Fedora/RHEL",
+        &["fedora", "rhel", "centos", "rocky", "almalinux"],
+    ),
+    (
+        BackendKind::Zypper,
+        "openSUSE",
+        &["opensuse", "suse", "sles"],
+    ),
+    (BackendKind::Apk, "Alpine", &["alpine", "postmarketos"]),
+];
+
 /// The detected (or user-specified) target distribution.
 #[derive(Debug, Clone)]
 pub struct Distro {
     pub id: String,
     pub version_id: Option<String>,
+    /// Distros this one is derived from, closest first (os-release `ID_LIKE`).
+    pub id_like: Vec<String>,
 }
 
 impl Distro {
@@ -19,6 +50,7 @@ impl Distro {
     fn parse_os_release(content: &str) -> KocaResult<Self> {
         let mut id: Option<String> = None;
         let mut version_id: Option<String> = None;
+        let mut id_like: Vec<String> = Vec::new();
 
         for line in content.lines() {
             let line = line.trim();
@@ -26,6 +58,11 @@ impl Distro {
                 id = Some(unquote(val).to_string());
             } else if let Some(val) = line.strip_prefix("VERSION_ID=") {
                 version_id = Some(unquote(val).to_string());
+            } else if let Some(val) = line.strip_prefix("ID_LIKE=") {
+                id_like = unquote(val)
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect();
             }
         }
 
@@ -36,16 +73,42 @@ impl Distro {
             ))
         })?;
 
-        Ok(Self { id, version_id })
+        Ok(Self {
+            id,
+            version_id,
+            id_like,
+        })
     }
 
-    /// Returns the backend kind to use for this distro.
-    pub fn backend_kind(&self) -> BackendKind {
-        match self.id.as_str() {
-            "arch" | "manjaro" | "endeavouros" | "garuda" => BackendKind::Alpm,
-            "debian" | "ubuntu" | "linuxmint" | "pop" => BackendKind::Apt,
-            _ => BackendKind::Alpm,
-        }
+    /// Returns the backend kind to use for this distro, trying `ID` and then
+    /// each `ID_LIKE` entry in turn.
+    ///
+    /// Returns [`KocaError::UnsupportedDistro`] if none of them belong to a supported family.
+    pub fn backend_kind(&self) -> KocaResult<BackendKind> {
+        std::iter::once(&self.id)
+            .chain(&self.id_like)
+            .find_map(|id| Self::family_backend(id))
+            .ok_or_else(|| KocaError::UnsupportedDistro {
+                id: self.id.clone(),
+                supported: FAMILIES
+                    .iter()
+                    .map(|(_, family, _)| *family)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+    }
+
+    fn family_backend(id: &str) -> Option<BackendKind> {
+        FAMILIES.iter().find_map(|(kind, _, ids)| {
+            ids.iter()
+                .any(|known| {
+                    id == *known
+                        || id
+                            .strip_prefix(known)
+                            .is_some_and(|rest| rest.starts_with('-'))
+                })
+                .then_some(*kind)
+        })
     }
 }
 
@@ -58,11 +121,13 @@ impl FromStr for Distro {
             Ok(Self {
                 id: id.to_string(),
                 version_id: Some(ver.to_string()),
+                id_like: Vec::new(),
             })
         } else {
             Ok(Self {
                 id: s.to_string(),
                 version_id: None,
+                id_like: Vec::new(),
             })
         }
     }
@@ -85,7 +150,7 @@ mod tests {
     fn parse_arch() {
         let d = Distro::parse_os_release("ID=arch\n").unwrap();
         assert_eq!(d.id, "arch");
-        assert!(matches!(d.backend_kind(), BackendKind::Alpm));
+        assert_eq!(d.backend_kind().unwrap(), BackendKind::Alpm);
     }
 
     #[test]
@@ -93,7 +158,7 @@ mod tests {
         let d = Distro::parse_os_release("ID=debian\nVERSION_ID=\"12\"\n").unwrap();
         assert_eq!(d.id, "debian");
         assert_eq!(d.version_id.as_deref(), Some("12"));
-        assert!(matches!(d.backend_kind(), BackendKind::Apt));
+        assert_eq!(d.backend_kind().unwrap(), BackendKind::Apt);
     }
 
     #[test]
@@ -103,6 +168,81 @@ mod tests {
         assert_eq!(d.version_id.as_deref(), Some("24.04"));
     }
 
+    #[test]
+    fn parse_rpm_distros() {
+        let d = Distro::parse_os_release("ID=\"rocky\"\nVERSION_ID=\"9.4\"\n").unwrap();
+        assert_eq!(d.backend_kind().unwrap(), BackendKind::Dnf);
+        let d = Distro::parse_os_release("ID=\"opensuse-tumbleweed\"\n").unwrap();
+        assert_eq!(d.backend_kind().unwrap(), BackendKind::Zypper);
+    }
+
+    #[test]
+    fn parse_alpine() {
+        let d = Distro::parse_os_release("ID=alpine\nVERSION_ID=3.20.3\n").unwrap();
+        assert_eq!(d.backend_kind().unwrap(), BackendKind::Apk);
+    }
+
+    #[test]
+    fn picks_backend_from_real_os_release() {
+        let cases = [
+            (
+                "kali",
+                "ID=kali\nID_LIKE=debian\nVERSION_ID=\"2024.3\"\n",
+                BackendKind::Apt,
+            ),
+            (
+                "zorin",
+                "ID=zorin\nID_LIKE=\"ubuntu debian\"\nVERSION_ID=\"17\"\n",
+                BackendKind::Apt,
+            ),
+            (
+                "elementary",
+                "ID=elementary\nID_LIKE=ubuntu\nVERSION_ID=\"7.1\"\n",
+                BackendKind::Apt,
+            ),
+            (
+                "cachyos",
+                "NAME=\"CachyOS Linux\"\nID=cachyos\nID_LIKE=arch\n",
+                BackendKind::Alpm,
+            ),
+            (
+                "rocky",
+                "ID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\nVERSION_ID=\"9.4\"\n",
+                BackendKind::Dnf,
+            ),
+            (
+                "nobara",
+                "ID=nobara\nID_LIKE=\"rhel centos fedora\"\nVERSION_ID=40\n",
+                BackendKind::Dnf,
+            ),
+            (
+                "opensuse-tumbleweed",
+                "ID=\"opensuse-tumbleweed\"\nID_LIKE=\"opensuse suse\"\n",
+                BackendKind::Zypper,
+            ),
+            ("alpine", "ID=alpine\nVERSION_ID=3.20.3\n", BackendKind::Apk),
+        ];
+
+        for (id, os_release, expected) in cases {
+            let d = Distro::parse_os_release(os_release).unwrap();
+            assert_eq!(d.id, id);
+            assert_eq!(d.backend_kind().unwrap(), expected, "{id}");
+        }
+    }
+
+    #[test]
+    fn unknown_distro_is_an_error() {
+        for os_release in ["ID=gentoo\n", "ID=nixos\nID_LIKE=\"\"\n", "ID=archery\n"] {
+            let d = Distro::parse_os_release(os_release).unwrap();
+            let err = d.backend_kind().unwrap_err();
+            assert!(
+                matches!(&err, KocaError::UnsupportedDistro { id, .. } if *id == d.id),
+                "{err}"
+            );
+            assert!(err.to_string().contains("Fedora/RHEL"));
+        }
+    }
+
     #[test]
     fn from_str_with_version() {
         let d: Distro = "debian:12".parse().unwrap();
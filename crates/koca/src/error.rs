@@ -1,4 +1,4 @@
-use crate::KocaFunction;
+use crate::{source::ChecksumKind, KocaFunction};
 pub use brush::Error as BrushError;
 use brush_parser::{ast::Assignment, ParseError as BrushParseError};
 use std::io;
@@ -58,6 +58,15 @@ pub enum KocaParserError {
     /// A conflict between single and split package declarations.
     #[error("{0}")]
     SplitPackageConflict(String),
+    /// An entry in a checksum array that isn't a valid digest or `SKIP`.
+    #[error("'{1}' is not a valid entry for '{0}' (expected a hex digest or 'SKIP')")]
+    InvalidChecksum(String, String),
+    /// A checksum other than `SKIP` for a version control source.
+    #[error("'{1}' is a version control source, so its '{0}' entry must be 'SKIP'")]
+    VcsChecksum(String, String),
+    /// A checksum array whose length doesn't match its source array.
+    #[error("the '{0}' array has {2} entries, but its source array has {1}")]
+    ChecksumCount(String, usize, usize),
 }
 
 /// Errors that can occur in the Koca library.
@@ -78,7 +87,42 @@ pub enum KocaError {
     /// An invalid relationship string (`depends`, `conflicts`, `provides`, ...).
     #[error("invalid relationship '{0}': {1}")]
     InvalidRelation(String, String),
+    /// Koca is running on an architecture it doesn't know about.
+    #[error("unsupported host architecture '{0}'")]
+    UnsupportedHostArch(String),
+    /// Packages can't be written for an architecture yet.
+    #[error("packages can't be written for architecture '{0}' yet")]
+    UnsupportedPackageArch(String),
+    /// `pkgver()` printed something that isn't a valid `pkgver`.
+    #[error("pkgver() printed '{0}', but a pkgver must start with a letter or digit and contain only letters, digits, '.', '+' and '~'")]
+    InvalidPkgverOutput(String),
+    /// A version can't be written in a package format's version syntax.
+    #[error("'{0}' can't be written as {1} version")]
+    UnsupportedPackageVersion(String, &'static str),
+    /// An architecture was selected that the build file doesn't declare.
+    #[error("architecture '{0}' isn't listed in the build file's 'arch' array")]
+    ArchNotDeclared(String),
+    /// No package-manager backend handles the host's distro or anything in its `ID_LIKE`.
+    #[error("unsupported distro '{id}' (supported families: {supported})")]
+    UnsupportedDistro { id: String, supported: String },
     /// An invalid source entry.
     #[error("invalid source: {0}")]
     InvalidSource(String),
+    /// A source could not be fetched.
+    #[error("failed to fetch source '{0}': {1}")]
+    SourceFetch(String, String),
+    /// There's no home directory to put the source cache in.
+    #[error("couldn't find the user's cache directory")]
+    NoCacheDir,
+    /// A fetched archive source couldn't be extracted.
+    #[error("failed to extract source '{0}'")]
+    Extract(String, #[source] io::Error),
+    /// A fetched source didn't match its declared checksum.
+    #[error("{kind} checksum mismatch for source '{entry}': expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        entry: String,
+        kind: ChecksumKind,
+        expected: String,
+        actual: String,
+    },
 }
@@ -0,0 +1,337 @@
+//! Writing Alpine `.apk` (v2) packages.
+//!
+//! An apk is two gzip streams concatenated together: a control tarball holding
+//! `.PKGINFO` and any maintainer scripts, followed by the data tarball. The
+//! control tarball has no end-of-archive marker, so apk reads straight on into
+//! the data, and every data entry carries its SHA-1 in a PAX header. Packages
+//! aren't signed, so they need `apk add --allow-untrusted`.
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+
+use flate2::{write::GzEncoder, Compression};
+use rfpm::relation::{Relation, VirtualPackage};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use super::{pkg_parent_dirs, PkgEntry};
+use crate::KocaResult;
+
+/// Package metadata written to `.PKGINFO`.
+pub(super) struct ApkInfo<'a> {
+    pub pkgname: &'a str,
+    /// The full apk version (`1.0-r1`).
+    pub pkgver: String,
+    pub pkgdesc: &'a str,
+    pub arch: &'a str,
+    /// The package base this was built from.
+    pub origin: &'a str,
+    pub maintainer: Option<&'a str>,
+    pub license: Option<&'a str>,
+    pub depends: &'a [Relation],
+    pub provides: &'a [VirtualPackage],
+    pub conflicts: &'a [Relation],
+    /// Maintainer scripts as `(file name, contents)` (e.g. `.post-install`).
+    pub scripts: Vec<(&'static str, String)>,
+    /// The build timestamp (`SOURCE_DATE_EPOCH`), also used as every entry's mtime.
+    pub builddate: u64,
+}
+
+/// Write an apk containing `entries` to `out`.
+///
+/// `.PKGINFO` holds the data stream's hash, so the data stream is written to a
+/// temporary file first, hashing it on the way, and copied after the control one.
+pub(super) fn write_apk(
+    info: &ApkInfo,
+    entries: &[PkgEntry],
+    out: &mut impl Write,
+) -> KocaResult<()> {
+    let data = HashingWriter {
+        inner: tempfile::tempfile()?,
+        hasher: Sha256::new(),
+    };
+    let mut data_gz = GzEncoder::new(data, Compression::best());
+    let installed_size = data_tar(entries, info.builddate, &mut data_gz)?;
+    let mut data = data_gz.finish()?;
+    let datahash = hex(&data.hasher.finalize());
+    data.inner.rewind()?;
+
+    let mut control = Vec::new();
+    let pkginfo = pkginfo(info, installed_size, &datahash);
+    push_entry(
+        &mut control,
+        &file_header(".PKGINFO", 0o644, pkginfo.len(), info.builddate),
+        pkginfo.as_bytes(),
+    )?;
+    for (name, script) in &info.scripts {
+        push_entry(
+            &mut control,
+            &file_header(name, 0o755, script.len(), info.builddate),
+            script.as_bytes(),
+        )?;
+    }
+
+    out.write_all(&gzip(&control)?)?;
+    io::copy(&mut data.inner, out)?;
+    Ok(())
+}
+
+/// Hashes everything written through it.
+struct HashingWriter {
+    inner: File,
+    hasher: Sha256,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Render `.PKGINFO`. apk shares pacman's `name>=1.0` relation syntax, and
+/// conflicts are dependencies prefixed with `!`.
+fn pkginfo(info: &ApkInfo, installed_size: u64, datahash: &str) -> String {
+    let mut fields = vec![
+        ("pkgname", info.pkgname.to_string()),
+        ("pkgver", info.pkgver.clone()),
+        ("pkgdesc", info.pkgdesc.replace('\n', " ")),
+        ("builddate", info.builddate.to_string()),
+        ("size", installed_size.to_string()),
+        ("arch", info.arch.to_string()),
+        ("origin", info.origin.to_string()),
+    ];
+    if let Some(maintainer) = info.maintainer {
+        fields.push(("maintainer", maintainer.to_string()));
+    }
+    if let Some(license) = info.license {
+        fields.push(("license", license.to_string()));
+    }
+    for dep in info.depends {
+        fields.push(("depend", dep.to_pacman()));
+    }
+    for conflict in info.conflicts {
+        fields.push(("depend", format!("!{}", conflict.to_pacman())));
+    }
+    for provide in info.provides {
+        fields.push(("provides", provide.to_pacman()));
+    }
+    fields.push(("datahash", datahash.to_string()));
+
+    let mut out = format!("# Generated by koca {}\n", env!("CARGO_PKG_VERSION"));
+    for (key, value) in fields {
+        out.push_str(&format!("{key} = {value}\n"));
+    }
+    out
+}
+
+/// Write the data tarball to `tar` with every entry's mtime set to `mtime`,
+/// returning the total size of its files.
+///
+/// Parent directories are added ahead of the entries that live in them. Each
+/// file is read twice: once for the checksum that goes ahead of it, then again
+/// to copy it into the tarball.
+fn data_tar(entries: &[PkgEntry], mtime: u64, tar: &mut impl Write) -> KocaResult<u64> {
+    let mut installed_size = 0;
+
+    let mut sorted: Vec<&PkgEntry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.dest().cmp(b.dest()));
+
+    for dir in pkg_parent_dirs(entries) {
+        let mut header = base_header(&format!("{dir}/"), 0o755, mtime)?;
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_cksum();
+        push_entry(tar, &header, io::empty())?;
+    }
+
+    for entry in sorted {
+        let path = entry.dest().trim_start_matches('/');
+        match entry {
+            PkgEntry::File { src, mode, .. } => {
+                let mut sha1 = Sha1::new();
+                let size = io::copy(&mut File::open(src)?, &mut sha1)?;
+                push_checksum(tar, &sha1.finalize(), mtime)?;
+                let mut header = base_header(path, *mode, mtime)?;
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(size);
+                header.set_cksum();
+                push_entry(tar, &header, File::open(src)?.take(size))?;
+                installed_size += size;
+            }
+            PkgEntry::Symlink { target, .. } => {
+                push_checksum(tar, &Sha1::digest(target.as_bytes()), mtime)?;
+                let mut header = base_header(path, 0o777, mtime)?;
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_link_name(target)?;
+                header.set_size(0);
+                header.set_cksum();
+                push_entry(tar, &header, io::empty())?;
+            }
+        }
+    }
+
+    // End-of-archive marker.
+    tar.write_all(&[0; 1024])?;
+    Ok(installed_size)
+}
+
+/// A root-owned ustar header; the caller sets type, size and checksum.
+fn base_header(path: &str, mode: u32, mtime: u64) -> KocaResult<tar::Header> {
+    let mut header = tar::Header::new_ustar();
+    header.set_path(path)?;
+    header.set_mode(mode);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_username("root")?;
+    header.set_groupname("root")?;
+    header.set_mtime(mtime);
+    Ok(header)
+}
+
+fn file_header(path: &str, mode: u32, size: usize, mtime: u64) -> tar::Header {
+    let mut header = base_header(path, mode, mtime).expect("control file names should be valid");
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_size(size as u64);
+    header.set_cksum();
+    header
+}
+
+/// Add the PAX header apk uses to verify the entry that follows it, whose SHA-1 is `sha1`.
+fn push_checksum(tar: &mut impl Write, sha1: &[u8], mtime: u64) -> KocaResult<()> {
+    let record = pax_record("APK-TOOLS.checksum.SHA1", &hex(sha1));
+    let mut header = base_header("PaxHeader", 0o644, mtime)?;
+    header.set_entry_type(tar::EntryType::XHeader);
+    header.set_size(record.len() as u64);
+    header.set_cksum();
+    push_entry(tar, &header, record.as_bytes())
+}
+
+/// Format a PAX record, whose leading length counts its own digits.
+fn pax_record(key: &str, value: &str) -> String {
+    let body = format!(" {key}={value}\n");
+    let mut len = body.len() + 1;
+    while len.to_string().len() + body.len() != len {
+        len = len.to_string().len() + body.len();
+    }
+    format!("{len}{body}")
+}
+
+/// Append a header and its data, padded to the 512-byte block size.
+fn push_entry(tar: &mut impl Write, header: &tar::Header, mut data: impl Read) -> KocaResult<()> {
+    tar.write_all(header.as_bytes())?;
+    let len = io::copy(&mut data, tar)?;
+    let padding = (512 - len % 512) % 512;
+    io::copy(&mut io::repeat(0).take(padding), tar)?;
+    Ok(())
+}
+
+fn gzip(data: &[u8]) -> KocaResult<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pax_record_counts_its_own_length() {
+        let record = pax_record("APK-TOOLS.checksum.SHA1", &"a".repeat(40));
+        let (len, _) = record.split_once(' ').unwrap();
+        assert_eq!(len.parse::<usize>().unwrap(), record.len());
+    }
+
+    #[test]
+    fn writes_control_then_data_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("foo");
+        std::fs::write(&src, b"payload").unwrap();
+        let entries = [
+            PkgEntry::File {
+                dest: "/usr/bin/foo".to_string(),
+                src,
+                mode: 0o755,
+                config: false,
+            },
+            PkgEntry::Symlink {
+                src: "/usr/bin/bar".to_string(),
+                target: "foo".to_string(),
+            },
+        ];
+        let depends = [Relation::parse("musl>=1.2").unwrap()];
+        let conflicts = [Relation::parse("oldfoo").unwrap()];
+        let info = ApkInfo {
+            pkgname: "foo",
+            pkgver: "1.0-r1".to_string(),
+            pkgdesc: "Foo",
+            arch: "x86_64",
+            origin: "foo",
+            maintainer: None,
+            license: Some("MIT"),
+            depends: &depends,
+            provides: &[],
+            conflicts: &conflicts,
+            scripts: vec![(".post-install", "#!/bin/sh\ntrue\n".to_string())],
+            builddate: 1_700_000_000,
+        };
+
+        let mut apk = Vec::new();
+        write_apk(&info, &entries, &mut apk).unwrap();
+
+        // A multi-member gzip reader sees one tar stream: control entries, then data.
+        let mut archive = tar::Archive::new(flate2::read::MultiGzDecoder::new(&apk[..]));
+        let mut paths = Vec::new();
+        let mut pkginfo = String::new();
+        let mut foo = String::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            assert_eq!(entry.header().mtime().unwrap(), 1_700_000_000);
+            let path = entry.path().unwrap().display().to_string();
+            match path.as_str() {
+                ".PKGINFO" => entry.read_to_string(&mut pkginfo).map(drop).unwrap(),
+                "usr/bin/foo" => entry.read_to_string(&mut foo).map(drop).unwrap(),
+                _ => {}
+            }
+            paths.push(path);
+        }
+        assert_eq!(
+            paths,
+            [
+                ".PKGINFO",
+                ".post-install",
+                "usr/",
+                "usr/bin/",
+                "usr/bin/bar",
+                "usr/bin/foo"
+            ]
+        );
+
+        assert_eq!(foo, "payload");
+
+        // `datahash` covers the data stream, which starts where the control one ends.
+        let mut data = &apk[..];
+        std::io::copy(
+            &mut flate2::bufread::GzDecoder::new(&mut data),
+            &mut std::io::sink(),
+        )
+        .unwrap();
+        let datahash = hex(&Sha256::digest(data));
+        assert!(
+            pkginfo.contains(&format!("datahash = {datahash}\n")),
+            "{pkginfo}"
+        );
+        assert!(pkginfo.contains("depend = musl>=1.2\n"));
+        assert!(pkginfo.contains("depend = !oldfoo\n"));
+        assert!(pkginfo.contains("size = 7\n"));
+    }
+}
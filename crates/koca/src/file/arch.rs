@@ -13,6 +13,16 @@ pub enum Arch {
     X64,
     /// 64-bit ARM. Accepts `arm64`, `aarch64`.
     Arm64,
+    /// 32-bit x86. Accepts `i686`, `i386`, `x86`.
+    I686,
+    /// 32-bit ARMv7 hard-float. Accepts `armv7h`, `armhf`, `armv7`.
+    Armv7,
+    /// 64-bit RISC-V. Accepts `riscv64`.
+    Riscv64,
+    /// 64-bit little-endian POWER. Accepts `ppc64le`, `ppc64el`.
+    Ppc64le,
+    /// 64-bit IBM Z. Accepts `s390x`.
+    S390x,
 }
 
 impl FromStr for Arch {
@@ -24,12 +34,41 @@ impl FromStr for Arch {
             "any" => Ok(Arch::Any),
             "x64" | "x86_64" | "amd64" => Ok(Arch::X64),
             "arm64" | "aarch64" => Ok(Arch::Arm64),
+            "i686" | "i386" | "x86" => Ok(Arch::I686),
+            "armv7h" | "armhf" | "armv7" => Ok(Arch::Armv7),
+            "riscv64" => Ok(Arch::Riscv64),
+            "ppc64le" | "ppc64el" => Ok(Arch::Ppc64le),
+            "s390x" => Ok(Arch::S390x),
             _ => Err(KocaParserError::InvalidArch(value.to_string()).into()),
         }
     }
 }
 
 impl Arch {
+    /// The architecture of the machine Koca is running on.
+    ///
+    /// Returns [`KocaError::UnsupportedHostArch`] if it isn't one of the variants above.
+    pub fn host() -> KocaResult<Self> {
+        match (std::env::consts::ARCH, cfg!(target_endian = "little")) {
+            ("x86_64", _) => Ok(Arch::X64),
+            ("aarch64", _) => Ok(Arch::Arm64),
+            ("x86", _) => Ok(Arch::I686),
+            ("arm", _) => Ok(Arch::Armv7),
+            ("riscv64", _) => Ok(Arch::Riscv64),
+            ("powerpc64", true) => Ok(Arch::Ppc64le),
+            ("s390x", _) => Ok(Arch::S390x),
+            (other, _) => Err(KocaError::UnsupportedHostArch(other.to_string())),
+        }
+    }
+
+    /// Resolve [`Arch::Any`] to the host's architecture, leaving every other arch as-is.
+    fn resolve(&self) -> KocaResult<Self> {
+        match self {
+            Arch::Any => Self::host(),
+            other => Ok(other.clone()),
+        }
+    }
+
     /// Canonical display string.
     pub fn get_string(&self) -> &'static str {
         match self {
@@ -37,48 +76,113 @@ impl Arch {
             Arch::Any => "any",
             Arch::X64 => "x64",
             Arch::Arm64 => "arm64",
+            Arch::I686 => "i686",
+            Arch::Armv7 => "armv7h",
+            Arch::Riscv64 => "riscv64",
+            Arch::Ppc64le => "ppc64le",
+            Arch::S390x => "s390x",
         }
     }
 
     /// Debian-style architecture string.
-    pub fn get_deb_string(&self) -> &'static str {
-        match self {
+    pub fn get_deb_string(&self) -> KocaResult<&'static str> {
+        Ok(match self.resolve()? {
             Arch::All => "all",
-            Arch::Any => match std::env::consts::ARCH {
-                "x86_64" => "amd64",
-                "aarch64" => "arm64",
-                other => panic!("unsupported architecture: {other}"),
-            },
+            Arch::Any => unreachable!("any should be resolved to the host arch"),
             Arch::X64 => "amd64",
             Arch::Arm64 => "arm64",
-        }
+            Arch::I686 => "i386",
+            Arch::Armv7 => "armhf",
+            Arch::Riscv64 => "riscv64",
+            Arch::Ppc64le => "ppc64el",
+            Arch::S390x => "s390x",
+        })
     }
 
     /// RPM-style architecture string.
-    pub fn get_rpm_string(&self) -> &'static str {
-        match self {
+    pub fn get_rpm_string(&self) -> KocaResult<&'static str> {
+        Ok(match self.resolve()? {
             Arch::All => "noarch",
-            Arch::Any => match std::env::consts::ARCH {
-                "x86_64" => "x86_64",
-                "aarch64" => "aarch64",
-                other => panic!("unsupported architecture: {other}"),
-            },
+            Arch::Any => unreachable!("any should be resolved to the host arch"),
             Arch::X64 => "x86_64",
             Arch::Arm64 => "aarch64",
-        }
+            Arch::I686 => "i686",
+            Arch::Armv7 => "armv7hl",
+            Arch::Riscv64 => "riscv64",
+            Arch::Ppc64le => "ppc64le",
+            Arch::S390x => "s390x",
+        })
+    }
+
+    /// Arch Linux-style architecture string.
+    pub fn get_pacman_string(&self) -> KocaResult<&'static str> {
+        Ok(match self.resolve()? {
+            Arch::All => "any",
+            Arch::Any => unreachable!("any should be resolved to the host arch"),
+            Arch::X64 => "x86_64",
+            Arch::Arm64 => "aarch64",
+            Arch::I686 => "i686",
+            Arch::Armv7 => "armv7h",
+            Arch::Riscv64 => "riscv64",
+            Arch::Ppc64le => "powerpc64le",
+            Arch::S390x => "s390x",
+        })
+    }
+
+    /// Alpine-style architecture string.
+    pub fn get_apk_string(&self) -> KocaResult<&'static str> {
+        Ok(match self.resolve()? {
+            Arch::All => "noarch",
+            Arch::Any => unreachable!("any should be resolved to the host arch"),
+            Arch::X64 => "x86_64",
+            Arch::Arm64 => "aarch64",
+            Arch::I686 => "x86",
+            Arch::Armv7 => "armv7",
+            Arch::Riscv64 => "riscv64",
+            Arch::Ppc64le => "ppc64le",
+            Arch::S390x => "s390x",
+        })
     }
 
     /// Convert to the rfpm [`Arch`](rfpm::Arch) type.
-    pub fn to_rfpm(&self) -> rfpm::Arch {
+    ///
+    /// Returns [`KocaError::UnsupportedPackageArch`] for arches rfpm doesn't know.
+    pub fn to_rfpm(&self) -> KocaResult<rfpm::Arch> {
+        match self.resolve()? {
+            Arch::All => Ok(rfpm::Arch::All),
+            Arch::X64 => Ok(rfpm::Arch::Amd64),
+            Arch::Arm64 => Ok(rfpm::Arch::Arm64),
+            Arch::I686 => Ok(rfpm::Arch::I386),
+            Arch::Armv7 => Ok(rfpm::Arch::Armhf),
+            other => Err(KocaError::UnsupportedPackageArch(
+                other.get_string().to_string(),
+            )),
+        }
+    }
+
+    /// Whether packages for this arch can be built on the current host.
+    pub fn matches_host(&self) -> bool {
         match self {
-            Arch::All => rfpm::Arch::All,
-            Arch::Any => match std::env::consts::ARCH {
-                "x86_64" => rfpm::Arch::Amd64,
-                "aarch64" => rfpm::Arch::Arm64,
-                other => panic!("unsupported architecture: {other}"),
-            },
-            Arch::X64 => rfpm::Arch::Amd64,
-            Arch::Arm64 => rfpm::Arch::Arm64,
+            Arch::All | Arch::Any => true,
+            _ => Self::host().is_ok_and(|host| &host == self),
+        }
+    }
+
+    /// The `uname -m`-style name exported to build functions as `$CARCH`.
+    ///
+    /// Architecture-agnostic arches use the host's name.
+    pub fn carch(&self) -> &'static str {
+        match self {
+            Arch::All | Arch::Any => Self::host()
+                .map(|host| host.carch())
+                .unwrap_or(std::env::consts::ARCH),
+            Arch::X64 => "x86_64",
+            Arch::Arm64 => "aarch64",
+            Arch::I686 => "i686",
+            Arch::Armv7 => "armv7h",
+            Arch::Riscv64 => "riscv64",
+            Arch::Ppc64le => "ppc64le",
+            Arch::S390x => "s390x",
         }
     }
 
@@ -89,6 +193,81 @@ impl Arch {
             Arch::Any => &["any"],
             Arch::X64 => &["x64", "x86_64", "amd64"],
             Arch::Arm64 => &["arm64", "aarch64"],
+            Arch::I686 => &["i686", "i386", "x86"],
+            Arch::Armv7 => &["armv7h", "armhf", "armv7"],
+            Arch::Riscv64 => &["riscv64"],
+            Arch::Ppc64le => &["ppc64le", "ppc64el"],
+            Arch::S390x => &["s390x"],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPECIFIC: [Arch; 7] = [
+        Arch::X64,
+        Arch::Arm64,
+        Arch::I686,
+        Arch::Armv7,
+        Arch::Riscv64,
+        Arch::Ppc64le,
+        Arch::S390x,
+    ];
+
+    #[test]
+    fn suffixes_round_trip() {
+        for arch in SPECIFIC.iter().chain([&Arch::All, &Arch::Any]) {
+            for suffix in arch.suffixes() {
+                assert_eq!(&Arch::from_str(suffix).unwrap(), arch, "{suffix}");
+            }
+            assert!(arch.suffixes().contains(&arch.get_string()));
+        }
+    }
+
+    #[test]
+    fn maps_to_distro_strings() {
+        let expected = [
+            (Arch::X64, "amd64", "x86_64"),
+            (Arch::Arm64, "arm64", "aarch64"),
+            (Arch::I686, "i386", "i686"),
+            (Arch::Armv7, "armhf", "armv7hl"),
+            (Arch::Riscv64, "riscv64", "riscv64"),
+            (Arch::Ppc64le, "ppc64el", "ppc64le"),
+            (Arch::S390x, "s390x", "s390x"),
+            (Arch::All, "all", "noarch"),
+        ];
+        for (arch, deb, rpm) in expected {
+            assert_eq!(arch.get_deb_string().unwrap(), deb);
+            assert_eq!(arch.get_rpm_string().unwrap(), rpm);
+        }
+        assert_eq!(Arch::Armv7.get_pacman_string().unwrap(), "armv7h");
+        assert_eq!(Arch::All.get_pacman_string().unwrap(), "any");
+        assert_eq!(Arch::I686.get_apk_string().unwrap(), "x86");
+        assert_eq!(Arch::All.get_apk_string().unwrap(), "noarch");
+    }
+
+    #[test]
+    fn rfpm_rejects_unsupported_arches() {
+        assert_eq!(Arch::Armv7.to_rfpm().unwrap(), rfpm::Arch::Armhf);
+        assert!(matches!(
+            Arch::S390x.to_rfpm(),
+            Err(KocaError::UnsupportedPackageArch(_))
+        ));
+    }
+
+    #[test]
+    fn any_resolves_to_host() {
+        match Arch::host() {
+            Ok(host) => {
+                assert!(host.matches_host());
+                assert_eq!(
+                    Arch::Any.get_deb_string().unwrap(),
+                    host.get_deb_string().unwrap()
+                );
+            }
+            Err(_) => assert!(Arch::Any.get_deb_string().is_err()),
         }
     }
 }
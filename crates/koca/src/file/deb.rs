@@ -0,0 +1,356 @@
+//! Writing `.deb` packages.
+//!
+//! A deb is an ar archive of three members: `debian-binary` holding the format
+//! version, `control.tar.gz` holding the `control` file, `md5sums`, `conffiles`
+//! and maintainer scripts, and `data.tar.xz` holding the payload. rfpm's writer
+//! only knows a few arches and builds the payload in memory, so this streams it
+//! into a temporary file instead, then writes the control tarball once the
+//! payload's checksums and size are known. Package metadata still comes from the
+//! same [`rfpm::Package`] the rpm writer uses.
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+
+use flate2::{write::GzEncoder, Compression};
+use md5::{Digest, Md5};
+use rfpm::relation::{Relation, VirtualPackage};
+
+use super::{pkg_parent_dirs, PkgEntry};
+use crate::KocaResult;
+
+/// Write a deb of `pkg`'s metadata with `entries` as its payload to `out`.
+///
+/// `version` is the full deb version (`1:1.0-2`) and `arch` the deb arch string.
+/// `scripts` are the `preinst`, `postinst`, `prerm` and `postrm` scripts.
+pub(super) fn write_deb(
+    pkg: &rfpm::Package,
+    version: &str,
+    arch: &str,
+    scripts: [Option<String>; 4],
+    entries: &[PkgEntry],
+    source_date: u64,
+    out: &mut impl Write,
+) -> KocaResult<()> {
+    let mut data = tempfile::tempfile()?;
+    let (md5sums, installed_size) = data_tar(entries, source_date, &mut data)?;
+    data.rewind()?;
+
+    let control = control(pkg, version, arch, installed_size);
+    let mut files = vec![("control", control.into_bytes(), 0o644)];
+    files.push(("md5sums", md5sums.into_bytes(), 0o644));
+    let conffiles: String = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            PkgEntry::File {
+                dest, config: true, ..
+            } => Some(format!("{dest}\n")),
+            _ => None,
+        })
+        .collect();
+    if !conffiles.is_empty() {
+        files.push(("conffiles", conffiles.into_bytes(), 0o644));
+    }
+    for (name, script) in ["preinst", "postinst", "prerm", "postrm"]
+        .into_iter()
+        .zip(scripts)
+    {
+        if let Some(script) = script {
+            files.push((name, script.into_bytes(), 0o755));
+        }
+    }
+
+    let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (name, contents, mode) in files {
+        let mut header = base_header(mode, source_date);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(contents.len() as u64);
+        tar.append_data(&mut header, name, contents.as_slice())?;
+    }
+    let control_tar = tar.into_inner()?.finish()?;
+
+    let data_len = data.metadata()?.len();
+    let mut ar = ar::Builder::new(out);
+    append_member(&mut ar, "debian-binary", 4, &b"2.0\n"[..], source_date)?;
+    append_member(
+        &mut ar,
+        "control.tar.gz",
+        control_tar.len() as u64,
+        control_tar.as_slice(),
+        source_date,
+    )?;
+    append_member(&mut ar, "data.tar.xz", data_len, data, source_date)
+}
+
+fn append_member(
+    ar: &mut ar::Builder<impl Write>,
+    name: &str,
+    len: u64,
+    contents: impl Read,
+    mtime: u64,
+) -> KocaResult<()> {
+    let mut header = ar::Header::new(name.as_bytes().to_vec(), len);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    Ok(ar.append(&header, contents)?)
+}
+
+/// Render the `control` file.
+fn control(pkg: &rfpm::Package, version: &str, arch: &str, installed_size: u64) -> String {
+    let mut fields = vec![
+        ("Package", pkg.name.clone()),
+        ("Version", version.to_string()),
+        ("Priority", "optional".to_string()),
+        ("Architecture", arch.to_string()),
+    ];
+    if let Some(maintainer) = &pkg.maintainer {
+        fields.push(("Maintainer", maintainer.clone()));
+    }
+    // dpkg counts the installed size in KiB.
+    fields.push(("Installed-Size", installed_size.div_ceil(1024).to_string()));
+    for (name, relations) in [
+        (
+            "Provides",
+            pkg.provides.iter().map(VirtualPackage::to_deb).collect(),
+        ),
+        (
+            "Depends",
+            pkg.depends.iter().map(Relation::to_deb).collect(),
+        ),
+        (
+            "Conflicts",
+            pkg.conflicts
+                .iter()
+                .map(Relation::to_deb)
+                .collect::<Vec<_>>(),
+        ),
+    ] {
+        if !relations.is_empty() {
+            fields.push((name, relations.join(", ")));
+        }
+    }
+    fields.push(("Description", description(&pkg.description)));
+
+    fields
+        .into_iter()
+        .map(|(key, value)| format!("{key}: {value}\n"))
+        .collect()
+}
+
+/// Format a description as a synopsis line followed by indented continuation
+/// lines, where blank lines become ` .`.
+fn description(desc: &str) -> String {
+    let mut lines = desc.lines().map(str::trim);
+    let mut out = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        out.push_str("\n ");
+        out.push_str(if line.is_empty() { "." } else { line });
+    }
+    out
+}
+
+/// Stream the xz-compressed data tarball into `out`, returning the `md5sums`
+/// file and the total size of the payload's files.
+///
+/// dpkg doesn't create missing parent directories, so each one gets an entry
+/// ahead of its contents.
+fn data_tar(entries: &[PkgEntry], mtime: u64, out: &mut File) -> KocaResult<(String, u64)> {
+    // Single-threaded xz is the slowest part of packaging large payloads.
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get() as u32);
+    let stream = liblzma::stream::MtStreamBuilder::new()
+        .preset(6)
+        .threads(threads)
+        .check(liblzma::stream::Check::Crc64)
+        .encoder()
+        .map_err(io::Error::other)?;
+    let mut tar = tar::Builder::new(liblzma::write::XzEncoder::new_stream(out, stream));
+
+    let mut sorted: Vec<&PkgEntry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.dest().cmp(b.dest()));
+
+    for dir in pkg_parent_dirs(entries) {
+        let mut header = base_header(0o755, mtime);
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        tar.append_data(&mut header, format!("{dir}/"), io::empty())?;
+    }
+
+    let mut md5sums = String::new();
+    let mut installed_size = 0;
+    for entry in sorted {
+        let path = entry.dest().trim_start_matches('/');
+        match entry {
+            PkgEntry::File { src, mode, .. } => {
+                let file = File::open(src)?;
+                let size = file.metadata()?.len();
+                let mut header = base_header(*mode, mtime);
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_size(size);
+                let mut reader = Md5Reader {
+                    inner: file,
+                    hasher: Md5::new(),
+                };
+                tar.append_data(&mut header, path, &mut reader)?;
+                md5sums.push_str(&format!("{}  {path}\n", hex(&reader.hasher.finalize())));
+                installed_size += size;
+            }
+            PkgEntry::Symlink { target, .. } => {
+                let mut header = base_header(0o777, mtime);
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                tar.append_link(&mut header, path, target)?;
+            }
+        }
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok((md5sums, installed_size))
+}
+
+/// A root-owned GNU header, so long paths fit; the caller sets type and size.
+fn base_header(mode: u32, mtime: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(mode);
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+        .set_username("root")
+        .expect("'root' should fit a tar header");
+    header
+        .set_groupname("root")
+        .expect("'root' should fit a tar header");
+    header.set_mtime(mtime);
+    header
+}
+
+/// Hashes a file as the tarball reads it, so it's only read once.
+struct Md5Reader {
+    inner: File,
+    hasher: Md5,
+}
+
+impl Read for Md5Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ar members of `deb`, by name.
+    fn members(deb: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut archive = ar::Archive::new(deb);
+        let mut members = Vec::new();
+        while let Some(entry) = archive.next_entry() {
+            let mut entry = entry.unwrap();
+            let name = String::from_utf8(entry.header().identifier().to_vec()).unwrap();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            members.push((name, contents));
+        }
+        members
+    }
+
+    /// The files in a tarball, as `(path, contents)`.
+    fn tar_files(tarball: impl Read) -> Vec<(String, String)> {
+        let mut archive = tar::Archive::new(tarball);
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                assert_eq!(entry.header().mtime().unwrap(), 1_700_000_000);
+                let path = entry.path().unwrap().display().to_string();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                (path, contents)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn writes_arches_rfpm_lacks() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("foo");
+        std::fs::write(&src, b"payload").unwrap();
+        let conf = dir.path().join("foo.conf");
+        std::fs::write(&conf, b"key=value\n").unwrap();
+        let entries = [
+            PkgEntry::File {
+                dest: "/usr/bin/foo".to_string(),
+                src,
+                mode: 0o755,
+                config: false,
+            },
+            PkgEntry::File {
+                dest: "/etc/foo.conf".to_string(),
+                src: conf,
+                mode: 0o644,
+                config: true,
+            },
+            PkgEntry::Symlink {
+                src: "/usr/bin/bar".to_string(),
+                target: "foo".to_string(),
+            },
+        ];
+        let mut pkg = rfpm::Package::new("foo", "1.0", rfpm::Arch::All, "Foo\nDoes foo.");
+        pkg.depends = vec![Relation::parse("libc6>=2.36").unwrap()];
+        let scripts = [None, Some("#!/bin/sh\ntrue\n".to_string()), None, None];
+
+        let mut deb = Vec::new();
+        write_deb(
+            &pkg,
+            "1:1.0-2",
+            "riscv64",
+            scripts,
+            &entries,
+            1_700_000_000,
+            &mut deb,
+        )
+        .unwrap();
+
+        let members = members(&deb);
+        let names: Vec<_> = members.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["debian-binary", "control.tar.gz", "data.tar.xz"]);
+        assert_eq!(members[0].1, b"2.0\n");
+
+        let control = tar_files(flate2::read::GzDecoder::new(&members[1].1[..]));
+        let paths: Vec<_> = control.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, ["control", "md5sums", "conffiles", "postinst"]);
+        assert_eq!(
+            control[0].1,
+            "Package: foo\nVersion: 1:1.0-2\nPriority: optional\nArchitecture: riscv64\n\
+             Installed-Size: 1\nDepends: libc6 (>= 2.36)\nDescription: Foo\n Does foo.\n"
+        );
+        assert_eq!(
+            control[1].1,
+            format!(
+                "{}  etc/foo.conf\n{}  usr/bin/foo\n",
+                hex(&Md5::digest(b"key=value\n")),
+                hex(&Md5::digest(b"payload"))
+            )
+        );
+        assert_eq!(control[2].1, "/etc/foo.conf\n");
+
+        let data = tar_files(liblzma::read::XzDecoder::new(&members[2].1[..]));
+        let paths: Vec<_> = data.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "etc/",
+                "usr/",
+                "usr/bin/",
+                "etc/foo.conf",
+                "usr/bin/bar",
+                "usr/bin/foo"
+            ]
+        );
+        assert_eq!(data[5].1, "payload");
+    }
+}
@@ -0,0 +1,189 @@
+//! In-place edits of a build file's raw text.
+//!
+//! These only touch the top-level assignment being rewritten, so comments,
+//! blank lines and the formatting of everything else in the file survive.
+
+use std::ops::Range;
+
+/// Replace the top-level array assignment `name=(...)` with `values`, or insert it
+/// on the line after the `after` assignment if `name` isn't assigned yet.
+///
+/// Returns [`None`] if `name` isn't assigned and `after` can't be found either.
+pub fn set_array(raw: &str, name: &str, values: &[String], after: &str) -> Option<String> {
+    let rendered = render_array(name, values);
+
+    if let Some(span) = find_assignment(raw, name) {
+        let mut out = raw.to_string();
+        out.replace_range(span, &rendered);
+        return Some(out);
+    }
+
+    let anchor = find_assignment(raw, after)?;
+    let mut out = raw.to_string();
+    out.insert_str(anchor.end, &format!("\n{rendered}"));
+    Some(out)
+}
+
+/// Replace the value of the top-level assignment `name=value`, leaving `value`
+/// unquoted if the shell doesn't need it quoted.
+///
+/// Returns [`None`] if `name` isn't assigned.
+pub fn set_string(raw: &str, name: &str, value: &str) -> Option<String> {
+    let span = find_assignment(raw, name)?;
+    let bare = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '+' | '-' | ':'));
+    let rendered = if bare {
+        format!("{name}={value}")
+    } else {
+        format!("{name}={}", quote(value))
+    };
+
+    let mut out = raw.to_string();
+    out.replace_range(span, &rendered);
+    Some(out)
+}
+
+/// Render an array in the makepkg style, one value per line aligned under the first.
+fn render_array(name: &str, values: &[String]) -> String {
+    let indent = " ".repeat(name.len() + 2);
+    let body = values
+        .iter()
+        .map(|value| quote(value))
+        .collect::<Vec<_>>()
+        .join(&format!("\n{indent}"));
+    format!("{name}=({body})")
+}
+
+/// Single-quote `value` for the shell.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Find the byte range of a top-level `name=value` or `name=(...)` assignment.
+///
+/// Only assignments starting at the beginning of a line are considered, so
+/// variables set inside function bodies are left alone.
+fn find_assignment(raw: &str, name: &str) -> Option<Range<usize>> {
+    let prefix = format!("{name}=");
+    let mut offset = 0;
+
+    for line in raw.split_inclusive('\n') {
+        if line.starts_with(&prefix) {
+            let value_start = offset + prefix.len();
+            let end = value_end(raw, value_start)?;
+            return Some(offset..end);
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+/// Find where the assignment value starting at `start` ends, skipping over quoting,
+/// escapes and comments so a `)` inside a string doesn't end an array early.
+fn value_end(raw: &str, start: usize) -> Option<usize> {
+    let bytes = raw.as_bytes();
+    let is_array = bytes.get(start) == Some(&b'(');
+    let mut i = if is_array { start + 1 } else { start };
+    let mut at_word_start = true;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                // Skip the escaped byte too, which a trailing backslash doesn't have.
+                i += 2;
+                at_word_start = false;
+                continue;
+            }
+            b'\'' => i += raw[i + 1..].find('\'')? + 1,
+            b'"' => {
+                i += 1;
+                while bytes.get(i)? != &b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'#' if at_word_start => {
+                let rest = &raw[i..];
+                i += rest.find('\n').unwrap_or(rest.len());
+                continue;
+            }
+            b')' if is_array => return Some(i + 1),
+            b'\n' if !is_array => return Some(i),
+            b' ' | b'\t' if !is_array => return Some(i),
+            _ => {}
+        }
+        at_word_start = matches!(bytes[i], b' ' | b'\t' | b'\n' | b'(');
+        i += 1;
+    }
+
+    (!is_array).then_some(bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "\
+# Maintainer: Test <test@example.com>
+pkgname=foo
+source=('a.tar.gz' # upstream tarball
+        'fix).patch')
+sha256sums=('old'
+            'old')
+
+package() {
+    sha256sums=('untouched')
+}
+";
+
+    #[test]
+    fn replaces_string() {
+        let out = set_string(FILE, "pkgname", "r12.abc123").unwrap();
+        assert!(out.contains("\npkgname=r12.abc123\nsource=("));
+        let out = set_string(FILE, "pkgname", "two words").unwrap();
+        assert!(out.contains("\npkgname='two words'\n"));
+        assert!(set_string(FILE, "pkgver", "1").is_none());
+    }
+
+    #[test]
+    fn replaces_existing_array() {
+        let out = set_array(
+            FILE,
+            "sha256sums",
+            &["new1".to_string(), "new2".to_string()],
+            "source",
+        )
+        .unwrap();
+        assert!(out.contains("sha256sums=('new1'\n            'new2')\n\npackage()"));
+        assert!(out.contains("# upstream tarball"));
+        assert!(out.contains("sha256sums=('untouched')"));
+        assert!(!out.contains("'old'"));
+    }
+
+    #[test]
+    fn inserts_after_anchor() {
+        let out = set_array(FILE, "b2sums", &["x".to_string()], "source").unwrap();
+        assert!(out.contains("'fix).patch')\nb2sums=('x')\nsha256sums=('old'"));
+    }
+
+    #[test]
+    fn missing_anchor_is_none() {
+        assert!(set_array(FILE, "b2sums", &["x".to_string()], "source_x86_64").is_none());
+    }
+
+    #[test]
+    fn trailing_backslash_is_not_a_panic() {
+        assert_eq!(find_assignment("pkgver=1\\", "pkgver"), Some(0..9));
+        assert_eq!(find_assignment("source=(a \\", "source"), None);
+    }
+
+    #[test]
+    fn quotes_single_quotes() {
+        assert_eq!(quote("it's"), r"'it'\''s'");
+    }
+}
@@ -1,8 +1,17 @@
+mod apk;
 mod arch;
+mod deb;
+pub mod edit;
+mod pacman;
 mod parser;
+mod rpm;
+mod scripts;
 mod version;
 
-use crate::{KocaError, KocaMultiResult, KocaParserError, KocaResult};
+use crate::{
+    source::{Checksum, ChecksumKind, SourceKind},
+    KocaError, KocaMultiResult, KocaParserError, KocaResult,
+};
 pub use arch::Arch;
 use brush::{CreateOptions, Shell, ShellVariable};
 use brush_parser::{
@@ -11,6 +20,7 @@ use brush_parser::{
 };
 use itertools::Itertools;
 use parser::DeclValue;
+pub use scripts::{ScriptHook, Scripts};
 use std::{
     collections::HashMap,
     env, fmt,
@@ -31,6 +41,10 @@ pub enum BundleFormat {
     Deb,
     /// A `.rpm` package.
     Rpm,
+    /// An Arch Linux `.pkg.tar.zst` package, installable with `pacman -U`.
+    Pacman,
+    /// An Alpine `.apk` package, installable with `apk add --allow-untrusted`.
+    Apk,
 }
 
 impl BundleFormat {
@@ -39,43 +53,74 @@ impl BundleFormat {
         match self {
             BundleFormat::Deb => "deb",
             BundleFormat::Rpm => "rpm",
+            BundleFormat::Pacman => "pkg.tar.zst",
+            BundleFormat::Apk => "apk",
         }
     }
 
     /// Return the architecture string appropriate for this format.
-    pub fn arch_string(&self, arch: &Arch) -> &'static str {
+    ///
+    /// Returns [`KocaError::UnsupportedHostArch`] if `arch` is `any` on an unknown host.
+    pub fn arch_string(&self, arch: &Arch) -> KocaResult<&'static str> {
         match self {
             BundleFormat::Deb => arch.get_deb_string(),
             BundleFormat::Rpm => arch.get_rpm_string(),
+            BundleFormat::Pacman => arch.get_pacman_string(),
+            BundleFormat::Apk => arch.get_apk_string(),
         }
     }
 
     /// Build the output filename for a package in this format.
-    pub fn output_filename(&self, pkgname: &str, version: &str, arch: &Arch) -> String {
-        format!(
-            "{}_{}_{}.{}",
-            pkgname,
-            version,
-            self.arch_string(arch),
+    ///
+    /// Pacman packages follow makepkg's `name-version-arch` naming, and apk packages
+    /// abuild's `name-version-rN` (see [`Version::to_apk`]); the rest use `name_version_arch`.
+    ///
+    /// Returns [`KocaError::UnsupportedPackageVersion`] if `version` can't be written in
+    /// this format's syntax (see [`Version::to_deb`] and [`Version::to_apk`]).
+    pub fn output_filename(
+        &self,
+        pkgname: &str,
+        version: &Version,
+        arch: &Arch,
+    ) -> KocaResult<String> {
+        let (separator, version) = match self {
+            BundleFormat::Apk => return Ok(format!("{pkgname}-{}.apk", version.to_apk()?)),
+            BundleFormat::Deb => ("_", version.to_deb()?),
+            // `.PKGINFO` always has a pkgrel, so the name does too.
+            BundleFormat::Pacman => ("-", version.full()),
+            BundleFormat::Rpm => ("_", version.to_string()),
+        };
+        Ok(format!(
+            "{pkgname}{separator}{version}{separator}{}.{}",
+            self.arch_string(arch)?,
             self.extension()
-        )
+        ))
     }
 }
 
 /// A Koca build file function.
 #[derive(Debug)]
 pub enum KocaFunction {
+    /// The `prepare` function.
+    Prepare,
     /// The `build` function.
     Build,
+    /// The `check` function.
+    Check,
     /// The `package` function.
     Package,
+    /// The `pkgver` function.
+    Pkgver,
 }
 
 impl fmt::Display for KocaFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            KocaFunction::Prepare => write!(f, "prepare"),
             KocaFunction::Build => write!(f, "build"),
+            KocaFunction::Check => write!(f, "check"),
             KocaFunction::Package => write!(f, "package"),
+            KocaFunction::Pkgver => write!(f, "pkgver"),
         }
     }
 }
@@ -118,6 +163,17 @@ impl PackageKind {
     }
 }
 
+/// Metadata a package function sets for its own package, overriding the global
+/// value (e.g. `depends=(...)` inside `package:foo-dev()`).
+#[derive(Clone, Debug, Default)]
+struct PackageOverrides {
+    pkgdesc: Option<String>,
+    depends: Option<Vec<rfpm::relation::Relation>>,
+    provides: Option<Vec<rfpm::relation::VirtualPackage>>,
+    conflicts: Option<Vec<rfpm::relation::Relation>>,
+    backup: Option<Vec<String>>,
+}
+
 /// A package's Koca build file.
 pub struct BuildFile {
     /// The [`Shell`] instance to use.
@@ -130,6 +186,10 @@ pub struct BuildFile {
     var_version: Version,
     /// The package's architecture.
     var_arch: Vec<Arch>,
+    /// The architecture from `var_arch` currently being built and bundled.
+    target_arch: Arch,
+    /// SHA-256 of the build file's text, recorded in pacman `.BUILDINFO` files.
+    build_file_sha256: String,
     /// The package's description.
     var_pkgdesc: String,
     /// The package's runtime dependencies.
@@ -148,13 +208,44 @@ pub struct BuildFile {
     var_backup: Vec<String>,
     /// The package's maintainer from `# Maintainer:` comment (optional).
     var_maintainer: Option<String>,
+    /// The package's `prepare` function (optional).
+    prepare_func: Option<FunctionDefinition>,
     /// The package's `build` function (optional).
     build_func: Option<FunctionDefinition>,
+    /// The package's `check` function (optional).
+    check_func: Option<FunctionDefinition>,
+    /// The package's `pkgver` function, computing the version from the fetched sources (optional).
+    pkgver_func: Option<FunctionDefinition>,
+    /// The package's maintainer script functions (`post_install`, etc.).
+    scripts: Scripts,
     /// Package functions keyed by package name.
     /// Single: one entry. Split: one per pkgname element.
     package_funcs: Vec<(String, FunctionDefinition)>,
+    /// Metadata overrides from each package function, keyed by package name.
+    package_overrides: HashMap<String, PackageOverrides>,
+    /// Pass the whole caller environment through rather than just [`ENV_ALLOWLIST`].
+    keep_env: bool,
+    /// Extra variables to export to build functions, applied after the inherited ones.
+    extra_env: Vec<(String, String)>,
+    /// The timestamp builds are pinned to, exported as `SOURCE_DATE_EPOCH`.
+    source_date_epoch: u64,
 }
 
+/// Variables inherited from the caller's environment by build functions.
+///
+/// Everything else (`CFLAGS`, `GOPATH`, `LANG`, ...) is dropped so a developer's
+/// shell can't change the output.
+pub const ENV_ALLOWLIST: &[&str] = &["PATH", "HOME", "USER", "LOGNAME", "SHELL", "TERM", "TMPDIR"];
+
+/// Variables fakeroot sets up to fake root ownership, inherited only by `package()`.
+///
+/// `koca internal package` runs under fakeroot, which preloads its library
+/// through `LD_PRELOAD`/`LD_LIBRARY_PATH` and finds its daemon through
+/// `FAKEROOTKEY`. Without them, files `package()` installs would be owned by
+/// the building user. Other functions never get them, so a stray `LD_PRELOAD`
+/// can't change what they build.
+pub const FAKEROOT_ENV: &[&str] = &["FAKEROOTKEY", "FAKED_MODE", "LD_PRELOAD", "LD_LIBRARY_PATH"];
+
 impl BuildFile {
     /// Get the [`CreateOptions`].
     fn create_options() -> CreateOptions {
@@ -197,7 +288,7 @@ impl BuildFile {
             .unwrap()
             .into_iter()
             .exactly_one()
-            .expect("Word parser should not return 2+ elements for a string")
+            .map_err(|_| KocaParserError::InvalidExpansion(var_name.to_string()))?
             .piece;
 
         Self::get_piece_string(var_name, piece)
@@ -213,14 +304,16 @@ impl BuildFile {
             .iter()
             .map(|word| &word.value)
         {
-            let piece = brush_parser::word::parse(string_value, &Default::default())
+            let Ok(piece) = brush_parser::word::parse(string_value, &Default::default())
                 .unwrap()
                 .into_iter()
                 .exactly_one()
-                .expect("Word parser should not return 2+ elements for a string")
-                .piece;
+            else {
+                errs.push(KocaParserError::InvalidExpansion(var_name.to_string()).into());
+                continue;
+            };
 
-            match Self::get_piece_string(var_name, piece) {
+            match Self::get_piece_string(var_name, piece.piece) {
                 Ok(s) => result.push(s),
                 Err(err) => errs.push(err),
             }
@@ -275,28 +368,13 @@ impl BuildFile {
         let mut errs = vec![];
         let mut result = vec![];
 
-        for string_value in value
+        for word in value
             .as_array()
             .ok_or(vec![KocaParserError::NotArray(var_name.to_string()).into()])?
-            .iter()
-            .map(|word| &word.value)
         {
-            let pieces = brush_parser::word::parse(string_value, &Default::default()).unwrap();
-            // May have multiple pieces (e.g. "text${var}text"), concatenate them all.
-            let mut s = String::new();
-            let mut piece_err = false;
-            for piece_item in pieces {
-                match Self::expand_piece(var_name, piece_item.piece, vars) {
-                    Ok(expanded) => s.push_str(&expanded),
-                    Err(err) => {
-                        errs.push(err);
-                        piece_err = true;
-                        break;
-                    }
-                }
-            }
-            if !piece_err {
-                result.push(s);
+            match Self::expand_word(var_name, &word.value, vars) {
+                Ok(s) => result.push(s),
+                Err(err) => errs.push(err),
             }
         }
 
@@ -306,12 +384,41 @@ impl BuildFile {
         Ok(result)
     }
 
+    /// Like `get_decl_string` but resolves `$var`/`${var}` from `vars`.
+    fn expand_string(
+        var_name: &str,
+        value: &DeclValue,
+        vars: &HashMap<String, String>,
+    ) -> KocaResult<String> {
+        let word = value
+            .as_word()
+            .ok_or(KocaParserError::NotString(var_name.to_string()))?;
+        Self::expand_word(var_name, &word.value, vars)
+    }
+
+    fn expand_word(
+        var_name: &str,
+        word: &str,
+        vars: &HashMap<String, String>,
+    ) -> KocaResult<String> {
+        let pieces = brush_parser::word::parse(word, &Default::default()).unwrap();
+        // May have multiple pieces (e.g. "text${var}text"), concatenate them all.
+        let mut s = String::new();
+        for piece_item in pieces {
+            s.push_str(&Self::expand_piece(var_name, piece_item.piece, vars)?);
+        }
+        Ok(s)
+    }
+
     /// Parse a string array into `Vec<Relation>`, propagating parse errors.
     fn parse_relation_array(
         var_name: &str,
         value: &DeclValue,
     ) -> KocaMultiResult<Vec<rfpm::relation::Relation>> {
-        let strings = Self::parse_string_array(var_name, value)?;
+        Self::parse_relations(Self::parse_string_array(var_name, value)?)
+    }
+
+    fn parse_relations(strings: Vec<String>) -> KocaMultiResult<Vec<rfpm::relation::Relation>> {
         let mut errs = vec![];
         let mut result = vec![];
         for s in strings {
@@ -332,7 +439,12 @@ impl BuildFile {
         var_name: &str,
         value: &DeclValue,
     ) -> KocaMultiResult<Vec<rfpm::relation::VirtualPackage>> {
-        let strings = Self::parse_string_array(var_name, value)?;
+        Self::parse_virtuals(Self::parse_string_array(var_name, value)?)
+    }
+
+    fn parse_virtuals(
+        strings: Vec<String>,
+    ) -> KocaMultiResult<Vec<rfpm::relation::VirtualPackage>> {
         let mut errs = vec![];
         let mut result = vec![];
         for s in strings {
@@ -374,7 +486,11 @@ impl BuildFile {
     }
 
     fn parse_backup(value: &DeclValue) -> KocaMultiResult<Vec<String>> {
-        let paths = Self::parse_string_array(vars::BACKUP, value)?;
+        Self::check_backup(Self::parse_string_array(vars::BACKUP, value)?)
+    }
+
+    /// Make sure every `backup` path is absolute.
+    fn check_backup(paths: Vec<String>) -> KocaMultiResult<Vec<String>> {
         let mut errs = vec![];
         for path in &paths {
             if !path.starts_with('/') {
@@ -387,6 +503,78 @@ impl BuildFile {
         Ok(paths)
     }
 
+    /// Parse the metadata overrides assigned at the top of a package function.
+    ///
+    /// Values can reference the static variables in `static_vars`, like `depends=("libfoo=$pkgver")`.
+    fn parse_overrides(
+        func: &FunctionDefinition,
+        static_vars: &HashMap<String, String>,
+    ) -> KocaMultiResult<PackageOverrides> {
+        let assignments = parser::get_func_assignments(
+            func,
+            &[
+                vars::PKGDESC,
+                vars::DEPENDS,
+                vars::PROVIDES,
+                vars::CONFLICTS,
+                vars::BACKUP,
+            ],
+        );
+        let mut overrides = PackageOverrides::default();
+        let mut errs = vec![];
+
+        for (key, value) in &assignments {
+            if key == vars::PKGDESC {
+                match Self::expand_string(key, value, static_vars) {
+                    Ok(pkgdesc) => overrides.pkgdesc = Some(pkgdesc),
+                    Err(err) => errs.push(err),
+                }
+                continue;
+            }
+
+            let strings = match Self::expand_string_array(key, value, static_vars) {
+                Ok(strings) => strings,
+                Err(expand_errs) => {
+                    errs.extend(expand_errs);
+                    continue;
+                }
+            };
+            let result = match key.as_str() {
+                vars::DEPENDS => {
+                    Self::parse_relations(strings).map(|depends| overrides.depends = Some(depends))
+                }
+                vars::PROVIDES => Self::parse_virtuals(strings)
+                    .map(|provides| overrides.provides = Some(provides)),
+                vars::CONFLICTS => Self::parse_relations(strings)
+                    .map(|conflicts| overrides.conflicts = Some(conflicts)),
+                vars::BACKUP => {
+                    Self::check_backup(strings).map(|paths| overrides.backup = Some(paths))
+                }
+                _ => unreachable!("only override names are collected"),
+            };
+            if let Err(parse_errs) = result {
+                errs.extend(parse_errs);
+            }
+        }
+
+        if !errs.is_empty() {
+            return Err(errs);
+        }
+        Ok(overrides)
+    }
+
+    /// Split a checksum array name like `sha256sums_x86_64` into its algorithm and arch.
+    ///
+    /// Returns [`None`] if `key` isn't a checksum array at all.
+    fn parse_checksum_key(key: &str) -> Option<KocaResult<(ChecksumKind, Option<Arch>)>> {
+        if let Some(kind) = ChecksumKind::from_var_name(key) {
+            return Some(Ok((kind, None)));
+        }
+        let (base, suffix) = key.split_once('_')?;
+        let kind = ChecksumKind::from_var_name(base)?;
+        Some(Arch::from_str(suffix).map(|arch| (kind, Some(arch))))
+    }
+
     /// Extract and validate the `# Maintainer:` comment from raw file text.
     ///
     /// Returns `Ok(Some(maintainer))` if found and valid, `Ok(None)` if absent,
@@ -414,7 +602,14 @@ impl BuildFile {
     /// Parse a Koca build script from the reader.
     ///
     /// Returns a [`KocaError::Parser`] error if the input is an invalid script.
-    pub async fn parse<R: Read>(mut reader: R) -> KocaMultiResult<Self> {
+    pub async fn parse<R: Read>(reader: R) -> KocaMultiResult<Self> {
+        Self::parse_with(reader, true).await
+    }
+
+    /// Parse a Koca build script, checking that each checksum array is as long as
+    /// its source array if `check_counts` is set. When it isn't, a mismatched array
+    /// only records which algorithm the sources use, as if every entry were `SKIP`.
+    async fn parse_with<R: Read>(mut reader: R, check_counts: bool) -> KocaMultiResult<Self> {
         // Read raw text to extract comments before brush strips them.
         let mut raw = String::new();
         reader
@@ -423,6 +618,9 @@ impl BuildFile {
 
         // Extract and validate # Maintainer: (brush strips comments).
         let opt_maintainer = Self::parse_maintainer(&raw);
+        let build_file_sha256 = ChecksumKind::Sha256
+            .digest_reader(raw.as_bytes())
+            .map_err(|e| vec![KocaError::from(e)])?;
 
         // Pass text to brush via Cursor.
         let cursor = std::io::Cursor::new(raw.as_bytes());
@@ -452,7 +650,11 @@ impl BuildFile {
         let mut opt_license: Option<String> = None;
         let mut opt_backup: Vec<String> = vec![];
 
+        let mut opt_prepare_func: Option<FunctionDefinition> = None;
         let mut opt_build_func: Option<FunctionDefinition> = None;
+        let mut opt_check_func: Option<FunctionDefinition> = None;
+        let mut opt_pkgver_func: Option<FunctionDefinition> = None;
+        let mut scripts = Scripts::default();
         let mut single_package_func: Option<FunctionDefinition> = None;
         let mut split_package_funcs: HashMap<String, FunctionDefinition> = HashMap::new();
 
@@ -590,15 +792,104 @@ impl BuildFile {
             }
         }
 
+        // Pass 3: attach checksum arrays to the source arrays they describe.
+        for (key, value) in &decl_items.vars {
+            let (kind, arch_key) = match Self::parse_checksum_key(key) {
+                Some(Ok(parsed)) => parsed,
+                Some(Err(err)) => {
+                    errs.push(err);
+                    continue;
+                }
+                None => continue,
+            };
+            let entries = match Self::parse_string_array(key, value) {
+                Ok(entries) => entries,
+                Err(arr_errs) => {
+                    errs.extend(arr_errs);
+                    continue;
+                }
+            };
+            let sources = opt_source.entry(arch_key).or_default();
+            if sources.len() != entries.len() && !check_counts {
+                for source in sources.iter_mut() {
+                    source.checksums.push(Checksum {
+                        kind,
+                        expected: None,
+                    });
+                }
+                continue;
+            }
+            if sources.len() != entries.len() {
+                errs.push(
+                    KocaParserError::ChecksumCount(key.clone(), sources.len(), entries.len())
+                        .into(),
+                );
+                continue;
+            }
+            for (source, entry) in sources.iter_mut().zip(entries) {
+                match Checksum::parse(kind, &entry) {
+                    Ok(checksum) if checksum.expected.is_some() && source.is_vcs() => {
+                        let err = KocaParserError::VcsChecksum(key.clone(), source.raw.clone());
+                        errs.push(err.into());
+                    }
+                    Ok(checksum) => source.checksums.push(checksum),
+                    Err(err) => errs.push(err),
+                }
+            }
+        }
+
+        // Mark sources listed in `noextract` so they're left as-is after fetching.
+        if let Some(value) = decl_items.vars.get(vars::NOEXTRACT) {
+            match Self::parse_string_array(vars::NOEXTRACT, value) {
+                Ok(names) => {
+                    for source in opt_source.values_mut().flatten() {
+                        source.noextract = names.contains(&source.dest_name());
+                    }
+                }
+                Err(arr_errs) => errs.extend(arr_errs),
+            }
+        }
+
+        // Hand `validpgpkeys` to signed git sources, which are checked against it.
+        let validpgpkeys = match decl_items.vars.get(vars::VALIDPGPKEYS) {
+            Some(value) => {
+                Self::parse_string_array(vars::VALIDPGPKEYS, value).unwrap_or_else(|arr_errs| {
+                    errs.extend(arr_errs);
+                    vec![]
+                })
+            }
+            None => vec![],
+        };
+        for source in opt_source.values_mut().flatten() {
+            if let SourceKind::Git { signed: true, .. } = source.kind {
+                if validpgpkeys.is_empty() {
+                    errs.push(KocaError::InvalidSource(format!(
+                        "'{}' is signed, but '{}' is empty",
+                        source.raw,
+                        vars::VALIDPGPKEYS
+                    )));
+                }
+                source.validpgpkeys = validpgpkeys.clone();
+            }
+        }
+
         // Extract functions.
         for func in decl_items.funcs {
             let fname = func.fname.value.as_str();
-            if fname == funcs::BUILD {
+            if fname == funcs::PREPARE {
+                opt_prepare_func = Some(func);
+            } else if fname == funcs::BUILD {
                 opt_build_func = Some(func);
+            } else if fname == funcs::CHECK {
+                opt_check_func = Some(func);
+            } else if fname == funcs::PKGVER {
+                opt_pkgver_func = Some(func);
             } else if fname == funcs::PACKAGE {
                 single_package_func = Some(func);
             } else if let Some(pkg_name) = fname.strip_prefix(funcs::PACKAGE_PREFIX) {
                 split_package_funcs.insert(pkg_name.to_string(), func);
+            } else if let Some(hook) = ScriptHook::from_func_name(fname) {
+                scripts.insert(hook, func);
             }
         }
 
@@ -654,6 +945,18 @@ impl BuildFile {
                     .into(),
                 );
             }
+            // Maintainer scripts only go in the package named pkgbase, so there must be one.
+            if let Some(base) = &opt_pkgbase {
+                if !scripts.is_empty() && !names.contains(base) {
+                    errs.push(
+                        KocaParserError::SplitPackageConflict(format!(
+                            "maintainer scripts are only added to the package named pkgbase, \
+                             but '{base}' is not in pkgname"
+                        ))
+                        .into(),
+                    );
+                }
+            }
             opt_packages = opt_pkgbase.map(|base| PackageKind::Split { base, names });
         } else if let Some(name) = opt_pkgname_single {
             // Single mode: require package(), forbid pkgbase, forbid package:NAME().
@@ -685,6 +988,17 @@ impl BuildFile {
             opt_packages = None;
         }
 
+        // Collect per-package metadata overrides from each package function.
+        let mut package_overrides = HashMap::new();
+        for (name, func) in &package_funcs {
+            match Self::parse_overrides(func, &static_vars) {
+                Ok(overrides) => {
+                    package_overrides.insert(name.clone(), overrides);
+                }
+                Err(override_errs) => errs.extend(override_errs),
+            }
+        }
+
         // TODO: We need to handle this better so the user knows if the epoch/pkgrel itself is invalid.
         let parsed_version = if let Some(mut pkgver) = opt_pkgver {
             if let Some(epoch) = opt_epoch {
@@ -709,12 +1023,21 @@ impl BuildFile {
             return Err(errs);
         }
 
+        let var_arch = opt_arch.expect("arch should be set");
+        let target_arch = var_arch
+            .iter()
+            .find(|arch| arch.matches_host())
+            .unwrap_or(&var_arch[0])
+            .clone();
+
         Ok(Self {
             shell,
             vars: decl_items.vars,
             packages: opt_packages.expect("packages should be set"),
             var_version: parsed_version.expect("version should be valid by this point"),
-            var_arch: opt_arch.expect("arch should be set"),
+            var_arch,
+            target_arch,
+            build_file_sha256,
             var_pkgdesc: opt_pkgdesc.expect("pkgdesc should be set"),
             var_depends: opt_depends,
             var_makedepends: opt_makedepends,
@@ -724,8 +1047,21 @@ impl BuildFile {
             var_license: opt_license,
             var_backup: opt_backup,
             var_maintainer: opt_maintainer,
+            prepare_func: opt_prepare_func,
             build_func: opt_build_func,
+            check_func: opt_check_func,
+            pkgver_func: opt_pkgver_func,
+            scripts,
             package_funcs,
+            package_overrides,
+            keep_env: false,
+            extra_env: Vec::new(),
+            source_date_epoch: env_source_date_epoch().unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            }),
         })
     }
 
@@ -734,15 +1070,57 @@ impl BuildFile {
     /// Returns a:
     /// - [`KocaError::Parser`] error if the input is an invalid script.
     /// - [`KocaError::IO`] error if the input file can't be read.
+    ///
+    /// Unless `SOURCE_DATE_EPOCH` is set, the build is pinned to the time of the
+    /// last git commit touching the file, if there is one.
     pub async fn parse_file<P: AsRef<Path>>(path: P) -> KocaMultiResult<Self> {
-        let file = File::open(path).map_err(|err| vec![err.into()])?;
-        Self::parse(file).await
+        Self::parse_file_with(path, true).await
+    }
+
+    /// Like [`Self::parse_file`], but a checksum array that doesn't match the length
+    /// of its source array isn't an error. This is for rewriting the checksums after
+    /// sources were added or removed, so none of the sources are verified against it.
+    pub async fn parse_file_unchecked<P: AsRef<Path>>(path: P) -> KocaMultiResult<Self> {
+        Self::parse_file_with(path, false).await
     }
 
-    /// Add environment variables to the environment.
-    fn add_vars(&mut self) {
-        // Add inherited vars.
-        for (key, var) in env::vars() {
+    async fn parse_file_with<P: AsRef<Path>>(path: P, check_counts: bool) -> KocaMultiResult<Self> {
+        let file = File::open(&path).map_err(|err| vec![err.into()])?;
+        let mut build_file = Self::parse_with(file, check_counts).await?;
+        if env_source_date_epoch().is_none() {
+            if let Some(time) = git_commit_time(path.as_ref()) {
+                build_file.source_date_epoch = time;
+            }
+        }
+        Ok(build_file)
+    }
+
+    /// Add environment variables to the environment `function_kind` runs in, picking
+    /// which of `inherited` (normally Koca's own environment) to pass on.
+    fn add_vars(&mut self, function_kind: &KocaFunction, inherited: Vec<(String, String)>) {
+        // Only pass the fakeroot variables when we're actually running under fakeroot.
+        let fakeroot = matches!(function_kind, KocaFunction::Package)
+            && inherited.iter().any(|(key, _)| key == "FAKEROOTKEY");
+        if !fakeroot && !self.keep_env {
+            for key in FAKEROOT_ENV {
+                self.shell.env.unset(key).ok();
+            }
+        }
+
+        // Add inherited vars, then any set by the caller.
+        let inherited = inherited.into_iter().filter(|(key, _)| {
+            self.keep_env
+                || ENV_ALLOWLIST.contains(&key.as_str())
+                || (fakeroot && FAKEROOT_ENV.contains(&key.as_str()))
+        });
+        let source_date_epoch = (
+            "SOURCE_DATE_EPOCH".to_string(),
+            self.source_date_epoch.to_string(),
+        );
+        for (key, var) in inherited
+            .chain([source_date_epoch])
+            .chain(self.extra_env.iter().cloned())
+        {
             let mut shell_var = ShellVariable::new(var);
             shell_var.export();
             self.shell
@@ -789,6 +1167,72 @@ impl BuildFile {
             .await
     }
 
+    /// Run `prepare()` with a callback, like [`run_build_with_output`](Self::run_build_with_output).
+    ///
+    /// Panics if no `prepare()` function is defined — check `has_prepare()` first.
+    pub async fn run_prepare_with_output(
+        &mut self,
+        callback: impl FnMut(Option<BuildOutputLine>),
+    ) -> KocaResult<()> {
+        let func = self
+            .prepare_func
+            .clone()
+            .expect("prepare() should be defined");
+        self.run_function_with_output(KocaFunction::Prepare, func, vec![], callback)
+            .await
+    }
+
+    /// Run `check()` with a callback, like [`run_build_with_output`](Self::run_build_with_output).
+    ///
+    /// Panics if no `check()` function is defined — check `has_check()` first.
+    pub async fn run_check_with_output(
+        &mut self,
+        callback: impl FnMut(Option<BuildOutputLine>),
+    ) -> KocaResult<()> {
+        let func = self.check_func.clone().expect("check() should be defined");
+        self.run_function_with_output(KocaFunction::Check, func, vec![], callback)
+            .await
+    }
+
+    /// Run `pkgver()` with a callback, like [`run_build_with_output`](Self::run_build_with_output),
+    /// and make the version it prints the package's `pkgver`.
+    ///
+    /// The last non-empty line `pkgver()` writes to stdout is the version; only
+    /// its stderr reaches the callback. Later functions see the new `$pkgver`.
+    ///
+    /// Returns a:
+    /// - [`KocaError::Func`] if the `pkgver` function failed to execute.
+    /// - [`KocaError::InvalidPkgverOutput`] if it printed something that isn't a valid `pkgver`,
+    ///   like `1.0-2` or a blank line.
+    ///
+    /// Panics if no `pkgver()` function is defined — check `has_pkgver()` first.
+    pub async fn run_pkgver_with_output(
+        &mut self,
+        mut callback: impl FnMut(Option<BuildOutputLine>),
+    ) -> KocaResult<PkgVersion> {
+        let func = self
+            .pkgver_func
+            .clone()
+            .expect("pkgver() should be defined");
+        let mut stdout = Vec::new();
+        self.run_function_with_output(KocaFunction::Pkgver, func, vec![], |line| match line {
+            Some(line) if line.stream == BuildOutputStream::Stdout => stdout.push(line.line),
+            other => callback(other),
+        })
+        .await?;
+
+        let output = stdout
+            .iter()
+            .rev()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        let pkgver = PkgVersion::from_str(output)
+            .map_err(|_| KocaError::InvalidPkgverOutput(output.to_string()))?;
+        self.set_pkgver(pkgver.clone());
+        Ok(pkgver)
+    }
+
     /// Run a named `package` (or `package:NAME`) function.
     pub async fn run_package_for(&mut self, pkg_name: &str) -> KocaResult<()> {
         self.run_package_for_with_output(pkg_name, |_| {}).await
@@ -806,7 +1250,13 @@ impl BuildFile {
             .ok_or_else(|| KocaError::FuncError(KocaFunction::Package))?;
         let func = func.clone();
 
+        // Start from an empty `$pkgdir`, so nothing an earlier run (say, for another
+        // arch) installed ends up in this package.
         let pkg_dir = dirs::pkg_for(pkg_name);
+        match fs::remove_dir_all(&pkg_dir) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
         fs::create_dir_all(&pkg_dir)?;
         let absolute_pkgdir = path::absolute(&pkg_dir)
             .expect("directory should be valid")
@@ -830,8 +1280,11 @@ impl BuildFile {
         extra_env: Vec<(&str, String)>,
         mut callback: impl FnMut(Option<BuildOutputLine>),
     ) -> KocaResult<()> {
+        self.shell.undefine_func(funcs::PREPARE);
         self.shell.undefine_func(funcs::BUILD);
+        self.shell.undefine_func(funcs::CHECK);
         self.shell.undefine_func(funcs::PACKAGE);
+        self.shell.undefine_func(funcs::PKGVER);
         // Also undefine any split package functions.
         for (name, _) in &self.package_funcs {
             self.shell
@@ -840,7 +1293,7 @@ impl BuildFile {
         self.shell
             .define_func(function.fname.value.clone(), function.clone());
 
-        self.add_vars();
+        self.add_vars(&function_kind, env::vars().collect());
         fs::create_dir_all(dirs::SRC)?;
 
         let absolute_srcdir = path::absolute(dirs::SRC)
@@ -851,6 +1304,10 @@ impl BuildFile {
             .set_env_global("srcdir", ShellVariable::new(absolute_srcdir))
             .expect("setting environment variable shouldn't fail");
 
+        self.shell
+            .set_env_global("CARCH", ShellVariable::new(self.target_arch.carch()))
+            .expect("setting environment variable shouldn't fail");
+
         for (name, value) in extra_env {
             self.shell
                 .set_env_global(name, ShellVariable::new(value))
@@ -932,13 +1389,19 @@ impl BuildFile {
     /// Bundle the named package into the given file format.
     pub fn bundle(&self, pkg_name: &str, format: BundleFormat, out_file: &Path) -> KocaResult<()> {
         let pkg_dir = dirs::pkg_for(pkg_name);
-        let rfpm_arch = self.var_arch[0].to_rfpm();
-
+        match format {
+            BundleFormat::Apk => return self.bundle_apk(pkg_name, Path::new(&pkg_dir), out_file),
+            BundleFormat::Pacman => {
+                return self.bundle_pacman(pkg_name, Path::new(&pkg_dir), out_file)
+            }
+            BundleFormat::Deb | BundleFormat::Rpm => {}
+        }
+        // Both writers take the arch as a string, since rfpm only knows a few of them.
         let mut pkg = rfpm::Package::new(
             pkg_name,
             self.var_version.pkgver.to_string(),
-            rfpm_arch,
-            &self.var_pkgdesc,
+            rfpm::Arch::All,
+            self.pkgdesc_for(pkg_name),
         );
 
         pkg.release = self
@@ -949,48 +1412,129 @@ impl BuildFile {
         pkg.epoch = self.var_version.epoch;
         pkg.maintainer = self.var_maintainer.clone();
         pkg.license = self.var_license.clone();
-        pkg.depends = self.var_depends.clone();
-        pkg.provides = self.var_provides.clone();
-        pkg.conflicts = self.var_conflicts.clone();
-
-        // Walk the package directory and add files. Ownership defaults to root:root.
-        let backup_set: std::collections::HashSet<&str> =
-            self.var_backup.iter().map(|p| p.as_str()).collect();
-
-        for entry in collect_pkg_entries(Path::new(&pkg_dir), &backup_set)? {
-            match entry {
-                PkgEntry::Symlink { src, target } => pkg.add_symlink(src, target),
-                PkgEntry::File {
-                    dest,
-                    src,
-                    mode,
-                    config,
-                } => {
-                    let file = File::open(&src)?;
-                    let opts = rfpm::FileOptions {
-                        mode,
-                        ..Default::default()
-                    };
-                    if config {
-                        pkg.add_config_with(dest, file, opts);
-                    } else {
-                        pkg.add_file_with(dest, file, opts);
-                    }
-                }
+        pkg.depends = self.depends_for(pkg_name).to_vec();
+        pkg.provides = self.provides_for(pkg_name).to_vec();
+        pkg.conflicts = self.conflicts_for(pkg_name).to_vec();
+
+        // Walk the package directory. Every entry is owned by root:root.
+        let backup_set: std::collections::HashSet<&str> = self
+            .backup_for(pkg_name)
+            .iter()
+            .map(|p| p.as_str())
+            .collect();
+
+        let entries = collect_pkg_entries(Path::new(&pkg_dir), &backup_set)?;
+        match format {
+            BundleFormat::Deb => {
+                let version = self.var_version.to_deb()?;
+                let mut out = File::create(out_file)?;
+                deb::write_deb(
+                    &pkg,
+                    &version,
+                    self.target_arch.get_deb_string()?,
+                    self.scripts_for(pkg_name).to_deb(&version),
+                    &entries,
+                    self.source_date_epoch,
+                    &mut out,
+                )
+            }
+            BundleFormat::Rpm => rpm::write_rpm(
+                &pkg,
+                self.target_arch.get_rpm_string()?,
+                self.scripts_for(pkg_name).to_rpm(&self.var_version.full()),
+                &entries,
+                self.source_date_epoch,
+                &mut File::create(out_file)?,
+            ),
+            BundleFormat::Pacman | BundleFormat::Apk => {
+                unreachable!("pacman and apk packages are bundled above")
             }
         }
+    }
 
+    /// Bundle the named package as an apk, which rfpm can't write, so it has its own writer.
+    fn bundle_apk(&self, pkg_name: &str, pkg_dir: &Path, out_file: &Path) -> KocaResult<()> {
+        let info = apk::ApkInfo {
+            pkgname: pkg_name,
+            pkgver: self.var_version.to_apk()?,
+            pkgdesc: self.pkgdesc_for(pkg_name),
+            arch: self.target_arch.get_apk_string()?,
+            origin: self.pkgbase(),
+            maintainer: self.var_maintainer.as_deref(),
+            license: self.var_license.as_deref(),
+            depends: self.depends_for(pkg_name),
+            provides: self.provides_for(pkg_name),
+            conflicts: self.conflicts_for(pkg_name),
+            scripts: self.scripts_for(pkg_name).to_apk(),
+            builddate: self.source_date_epoch,
+        };
+
+        // apk keeps modified files under /etc on its own, so `backup` needs no marking.
+        let entries = collect_pkg_entries(pkg_dir, &Default::default())?;
         let mut out = File::create(out_file)?;
-        match format {
-            BundleFormat::Deb => pkg
-                .write_deb(&mut out)
-                .map_err(|e| std::io::Error::other(e.to_string()))?,
-            BundleFormat::Rpm => pkg
-                .write_rpm(&mut out)
-                .map_err(|e| std::io::Error::other(e.to_string()))?,
-        }
+        apk::write_apk(&info, &entries, &mut out)
+    }
 
-        Ok(())
+    /// Bundle the named package for pacman. rfpm's writer has no room for `.BUILDINFO`
+    /// and only knows a few arches, so pacman has its own writer too.
+    fn bundle_pacman(&self, pkg_name: &str, pkg_dir: &Path, out_file: &Path) -> KocaResult<()> {
+        let pkgver = self.var_version.full();
+        let info = pacman::PacmanInfo {
+            pkgname: pkg_name,
+            pkgbase: self.pkgbase(),
+            buildinfo: self.pacman_buildinfo(pkg_name, &pkgver)?,
+            pkgver,
+            pkgdesc: self.pkgdesc_for(pkg_name),
+            arch: self.target_arch.get_pacman_string()?,
+            packager: self.var_maintainer.as_deref().unwrap_or("Unknown Packager"),
+            license: self.var_license.as_deref(),
+            depends: self.depends_for(pkg_name),
+            provides: self.provides_for(pkg_name),
+            conflicts: self.conflicts_for(pkg_name),
+            install: self.scripts_for(pkg_name).to_pacman(),
+            builddate: self.source_date_epoch,
+        };
+
+        let backup_set: std::collections::HashSet<&str> = self
+            .backup_for(pkg_name)
+            .iter()
+            .map(|p| p.as_str())
+            .collect();
+        let entries = collect_pkg_entries(pkg_dir, &backup_set)?;
+        let mut out = File::create(out_file)?;
+        pacman::write_pacman(&info, &entries, &mut out)
+    }
+
+    /// Render the `.BUILDINFO` file (format 2) recording how a pacman package was built.
+    ///
+    /// `builddir` and `startdir` are the fixed paths Arch's reproducible builds use
+    /// rather than the checkout's, so building from another directory gives the
+    /// same package.
+    fn pacman_buildinfo(&self, pkg_name: &str, pkgver: &str) -> KocaResult<String> {
+        let fields = [
+            ("format", "2".to_string()),
+            ("pkgname", pkg_name.to_string()),
+            ("pkgbase", self.pkgbase().to_string()),
+            ("pkgver", pkgver.to_string()),
+            ("pkgarch", self.target_arch.get_pacman_string()?.to_string()),
+            ("pkgbuild_sha256sum", self.build_file_sha256.clone()),
+            (
+                "packager",
+                self.var_maintainer
+                    .clone()
+                    .unwrap_or_else(|| "Unknown Packager".to_string()),
+            ),
+            ("builddate", self.source_date_epoch.to_string()),
+            ("builddir", "/build".to_string()),
+            ("startdir", "/startdir".to_string()),
+            ("buildtool", "koca".to_string()),
+            ("buildtoolver", env!("CARGO_PKG_VERSION").to_string()),
+        ];
+
+        Ok(fields
+            .into_iter()
+            .map(|(key, value)| format!("{key} = {value}\n"))
+            .collect())
     }
 
     /// Get the package kind (single vs split).
@@ -1013,11 +1557,64 @@ impl BuildFile {
         &self.var_version
     }
 
+    /// Replace the package's `pkgver`, e.g. with one [`run_pkgver_with_output`](Self::run_pkgver_with_output)
+    /// computed in another process.
+    pub fn set_pkgver(&mut self, pkgver: PkgVersion) {
+        if let Some(DeclValue::String(word)) = self.vars.get_mut(vars::PKGVER) {
+            word.value = pkgver.to_string();
+        }
+        self.var_version.pkgver = pkgver;
+    }
+
     /// Get the package's architecture.
     pub fn arch(&self) -> &[Arch] {
         &self.var_arch
     }
 
+    /// Get the architecture being built and bundled.
+    ///
+    /// Defaults to the declared architecture matching the host, or the first
+    /// declared one if none do.
+    pub fn target_arch(&self) -> &Arch {
+        &self.target_arch
+    }
+
+    /// Select which declared architecture to build and bundle.
+    ///
+    /// Returns [`KocaError::ArchNotDeclared`] if `arch` isn't in the `arch` array.
+    pub fn set_target_arch(&mut self, arch: &Arch) -> KocaResult<()> {
+        if !self.var_arch.contains(arch) {
+            return Err(KocaError::ArchNotDeclared(arch.get_string().to_string()));
+        }
+        self.target_arch = arch.clone();
+        Ok(())
+    }
+
+    /// Pass the caller's whole environment to build functions, instead of just
+    /// the variables in [`ENV_ALLOWLIST`].
+    pub fn set_keep_env(&mut self, keep_env: bool) {
+        self.keep_env = keep_env;
+    }
+
+    /// Export `key=value` to build functions, overriding any inherited value.
+    pub fn set_env(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.extra_env.push((key.into(), value.into()));
+    }
+
+    /// The timestamp builds are pinned to: `SOURCE_DATE_EPOCH` if set, else the
+    /// last git commit of the build file, else the time it was parsed.
+    ///
+    /// It's exported to build functions, used as the build date in package
+    /// metadata, and caps file mtimes in the payload.
+    pub fn source_date_epoch(&self) -> u64 {
+        self.source_date_epoch
+    }
+
+    /// Pin the build to `epoch` instead; see [`source_date_epoch`](Self::source_date_epoch).
+    pub fn set_source_date_epoch(&mut self, epoch: u64) {
+        self.source_date_epoch = epoch;
+    }
+
     /// Get the package's description.
     pub fn pkgdesc(&self) -> &str {
         &self.var_pkgdesc
@@ -1028,11 +1625,42 @@ impl BuildFile {
         &self.var_depends
     }
 
+    /// Whether a `prepare()` function is defined.
+    pub fn has_prepare(&self) -> bool {
+        self.prepare_func.is_some()
+    }
+
     /// Whether a `build()` function is defined.
     pub fn has_build(&self) -> bool {
         self.build_func.is_some()
     }
 
+    /// Whether a `check()` function is defined.
+    pub fn has_check(&self) -> bool {
+        self.check_func.is_some()
+    }
+
+    /// Whether a `pkgver()` function is defined.
+    pub fn has_pkgver(&self) -> bool {
+        self.pkgver_func.is_some()
+    }
+
+    /// Get the package's maintainer scripts.
+    pub fn scripts(&self) -> &Scripts {
+        &self.scripts
+    }
+
+    /// Get the maintainer scripts of the named package. In split builds they belong to the
+    /// package named `pkgbase`, so the others get none.
+    pub fn scripts_for(&self, pkg_name: &str) -> &Scripts {
+        static NO_SCRIPTS: Scripts = Scripts::EMPTY;
+        if pkg_name == self.pkgbase() {
+            &self.scripts
+        } else {
+            &NO_SCRIPTS
+        }
+    }
+
     /// Get the package's build-time dependencies.
     pub fn makedepends(&self) -> &[rfpm::relation::Relation] {
         &self.var_makedepends
@@ -1058,11 +1686,74 @@ impl BuildFile {
         &self.var_backup
     }
 
+    fn overrides(&self, pkg_name: &str) -> Option<&PackageOverrides> {
+        self.package_overrides.get(pkg_name)
+    }
+
+    /// Get the description of the named package, including any override in its package function.
+    pub fn pkgdesc_for(&self, pkg_name: &str) -> &str {
+        self.overrides(pkg_name)
+            .and_then(|o| o.pkgdesc.as_deref())
+            .unwrap_or(&self.var_pkgdesc)
+    }
+
+    /// Get the runtime dependencies of the named package, including any override in its package
+    /// function.
+    pub fn depends_for(&self, pkg_name: &str) -> &[rfpm::relation::Relation] {
+        self.overrides(pkg_name)
+            .and_then(|o| o.depends.as_deref())
+            .unwrap_or(&self.var_depends)
+    }
+
+    /// Get the virtual packages the named package provides, including any override in its
+    /// package function.
+    pub fn provides_for(&self, pkg_name: &str) -> &[rfpm::relation::VirtualPackage] {
+        self.overrides(pkg_name)
+            .and_then(|o| o.provides.as_deref())
+            .unwrap_or(&self.var_provides)
+    }
+
+    /// Get the packages the named package conflicts with, including any override in its package
+    /// function.
+    pub fn conflicts_for(&self, pkg_name: &str) -> &[rfpm::relation::Relation] {
+        self.overrides(pkg_name)
+            .and_then(|o| o.conflicts.as_deref())
+            .unwrap_or(&self.var_conflicts)
+    }
+
+    /// Get the backup/conffiles paths of the named package, including any override in its
+    /// package function.
+    pub fn backup_for(&self, pkg_name: &str) -> &[String] {
+        self.overrides(pkg_name)
+            .and_then(|o| o.backup.as_deref())
+            .unwrap_or(&self.var_backup)
+    }
+
     /// Get the package's maintainer.
     pub fn maintainer(&self) -> Option<&str> {
         self.var_maintainer.as_deref()
     }
 
+    /// Get every declared source array, keyed by its variable name (`source`,
+    /// `source_x86_64`, ...) and sorted by it.
+    pub fn source_arrays(&self) -> Vec<(&str, &[crate::source::Source])> {
+        let mut arrays: Vec<_> = self
+            .vars
+            .keys()
+            .filter_map(|key| {
+                let arch_key = if key == vars::SOURCE {
+                    None
+                } else {
+                    Some(Arch::from_str(key.strip_prefix("source_")?).ok()?)
+                };
+                let sources = self.var_source.get(&arch_key)?;
+                Some((key.as_str(), sources.as_slice()))
+            })
+            .collect();
+        arrays.sort_by_key(|(key, _)| *key);
+        arrays
+    }
+
     /// Get the source entries for a given target arch.
     ///
     /// Returns `source_$ARCH` if defined, otherwise falls back to `source`.
@@ -1121,11 +1812,19 @@ pub mod vars {
     pub const LICENSE: &str = "license";
     pub const BACKUP: &str = "backup";
     pub const SOURCE: &str = "source";
+    pub const SHA256SUMS: &str = "sha256sums";
+    pub const SHA512SUMS: &str = "sha512sums";
+    pub const B2SUMS: &str = "b2sums";
+    pub const NOEXTRACT: &str = "noextract";
+    pub const VALIDPGPKEYS: &str = "validpgpkeys";
 }
 
 /// A mapping of `const` function names to their stringified values.
 pub mod funcs {
+    pub const PREPARE: &str = "prepare";
     pub const BUILD: &str = "build";
+    pub const CHECK: &str = "check";
+    pub const PKGVER: &str = "pkgver";
     pub const PACKAGE: &str = "package";
     /// Prefix for split package functions (e.g. `package:koca`).
     pub const PACKAGE_PREFIX: &str = "package:";
@@ -1142,6 +1841,42 @@ mod dirs {
     }
 }
 
+/// `SOURCE_DATE_EPOCH` from the environment, if it's set to a valid timestamp.
+fn env_source_date_epoch() -> Option<u64> {
+    env::var("SOURCE_DATE_EPOCH").ok()?.trim().parse().ok()
+}
+
+/// The commit time of the last commit touching `path`, if it's in a git repository.
+fn git_commit_time(path: &Path) -> Option<u64> {
+    let path = fs::canonicalize(path).ok()?;
+    let repo = git2::Repository::discover(path.parent()?).ok()?;
+    let rel_path = path.strip_prefix(repo.workdir()?).ok()?;
+
+    let mut revwalk = repo.revwalk().ok()?;
+    revwalk.push_head().ok()?;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid.ok()?).ok()?;
+        let blob = |commit: &git2::Commit| {
+            commit
+                .tree()
+                .ok()?
+                .get_path(rel_path)
+                .ok()
+                .map(|entry| entry.id())
+        };
+        // Untracked at this commit means nothing older touched it either.
+        let current = Some(blob(&commit)?);
+        let changed = match commit.parents().next() {
+            Some(parent) => blob(&parent) != current,
+            None => true,
+        };
+        if changed {
+            return u64::try_from(commit.time().seconds()).ok();
+        }
+    }
+    None
+}
+
 /// A package payload entry discovered by walking a built `$pkgdir`.
 enum PkgEntry {
     /// A regular file to copy from `src` into the package at `dest`.
@@ -1155,6 +1890,29 @@ enum PkgEntry {
     Symlink { src: String, target: String },
 }
 
+impl PkgEntry {
+    /// Where the entry goes in the package, e.g. `/usr/bin/foo`.
+    fn dest(&self) -> &str {
+        match self {
+            PkgEntry::File { dest, .. } => dest,
+            PkgEntry::Symlink { src, .. } => src,
+        }
+    }
+}
+
+/// Every directory containing one of `entries`, without leading or trailing slashes and
+/// parents first, since the `$pkgdir` walk only yields files and symlinks.
+fn pkg_parent_dirs(entries: &[PkgEntry]) -> std::collections::BTreeSet<String> {
+    entries
+        .iter()
+        .flat_map(|entry| {
+            let dest = entry.dest().trim_start_matches('/');
+            dest.match_indices('/')
+                .map(move |(idx, _)| dest[..idx].to_string())
+        })
+        .collect()
+}
+
 /// Walk a built package directory into a flat list of payload entries.
 ///
 /// Symlinks are recorded as links (never dereferenced) so they survive into the
@@ -1164,7 +1922,8 @@ fn collect_pkg_entries(
     backup_set: &std::collections::HashSet<&str>,
 ) -> KocaResult<Vec<PkgEntry>> {
     let mut entries = Vec::new();
-    for res_entry in WalkDir::new(pkg_dir) {
+    // Sorted so the payload doesn't depend on the filesystem's directory order.
+    for res_entry in WalkDir::new(pkg_dir).sort_by_file_name() {
         let entry = res_entry.map_err(|e| std::io::Error::other(e.to_string()))?;
         let file_type = entry.file_type();
         if file_type.is_dir() {
@@ -1209,9 +1968,53 @@ fn collect_pkg_entries(
 
 #[cfg(test)]
 mod tests {
-    use super::{collect_pkg_entries, PkgEntry};
+    use super::{collect_pkg_entries, Arch, BuildFile, BundleFormat, KocaFunction, PkgEntry};
     use std::collections::HashSet;
 
+    const MINIMAL: &str = "pkgname=foo\npkgver=1\narch=('any')\npkgdesc='Foo'\npackage() { :; }\n";
+
+    /// An inherited environment holding `extra` on top of `PATH`.
+    fn inherited(extra: &[(&str, &str)]) -> Vec<(String, String)> {
+        [("PATH", "/usr/bin")]
+            .iter()
+            .chain(extra)
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn only_allowlisted_env_reaches_functions() {
+        let env = inherited(&[("CARGO_MANIFEST_DIR", "/src")]);
+
+        let mut build_file = BuildFile::parse(MINIMAL.as_bytes()).await.unwrap();
+        build_file.set_env("CFLAGS", "-O2");
+        build_file.add_vars(&KocaFunction::Build, env.clone());
+        assert!(build_file.shell.env_str("CARGO_MANIFEST_DIR").is_none());
+        assert!(build_file.shell.env_str("PATH").is_some());
+        assert_eq!(build_file.shell.env_str("CFLAGS").as_deref(), Some("-O2"));
+
+        let mut build_file = BuildFile::parse(MINIMAL.as_bytes()).await.unwrap();
+        build_file.set_keep_env(true);
+        build_file.add_vars(&KocaFunction::Build, env);
+        assert!(build_file.shell.env_str("CARGO_MANIFEST_DIR").is_some());
+    }
+
+    #[tokio::test]
+    async fn only_package_gets_fakeroot_env() {
+        let env = inherited(&[("FAKEROOTKEY", "1234")]);
+
+        let mut build_file = BuildFile::parse(MINIMAL.as_bytes()).await.unwrap();
+        build_file.add_vars(&KocaFunction::Build, env.clone());
+        assert!(build_file.shell.env_str("FAKEROOTKEY").is_none());
+        build_file.add_vars(&KocaFunction::Package, env.clone());
+        assert_eq!(
+            build_file.shell.env_str("FAKEROOTKEY").as_deref(),
+            Some("1234")
+        );
+        build_file.add_vars(&KocaFunction::Check, env);
+        assert!(build_file.shell.env_str("FAKEROOTKEY").is_none());
+    }
+
     #[test]
     fn collects_symlinks_as_links_not_files() {
         let dir = tempfile::tempdir().unwrap();
@@ -1261,4 +2064,30 @@ mod tests {
         });
         assert_eq!(config, Some(true), "backup path marked as config");
     }
+
+    #[test]
+    fn names_outputs_per_format() {
+        let name = |format: BundleFormat| {
+            format
+                .output_filename("foo", &"1:1.0-2".parse().unwrap(), &Arch::X64)
+                .unwrap()
+        };
+        assert_eq!(name(BundleFormat::Deb), "foo_1:1.0-2_amd64.deb");
+        assert_eq!(name(BundleFormat::Rpm), "foo_1:1.0-2_x86_64.rpm");
+        assert_eq!(name(BundleFormat::Pacman), "foo-1:1.0-2-x86_64.pkg.tar.zst");
+        assert_eq!(name(BundleFormat::Apk), "foo-1.0-r2.apk");
+    }
+
+    #[tokio::test]
+    async fn names_pacman_outputs_with_the_default_pkgrel() {
+        let build_file = BuildFile::parse(MINIMAL.as_bytes()).await.unwrap();
+        assert_eq!(build_file.version().pkgrel, None);
+
+        let name = BundleFormat::Pacman
+            .output_filename("foo", build_file.version(), &Arch::X64)
+            .unwrap();
+        assert_eq!(name, "foo-1-1-x86_64.pkg.tar.zst");
+        // The same version `.PKGINFO` gets.
+        assert_eq!(build_file.version().full(), "1-1");
+    }
 }
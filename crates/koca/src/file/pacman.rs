@@ -0,0 +1,451 @@
+//! Writing Arch Linux `.pkg.tar.zst` packages.
+//!
+//! A pacman package is a zstd-compressed tarball with its metadata at the top:
+//! `.PKGINFO`, `.BUILDINFO`, a gzipped `.MTREE` listing everything in the
+//! package, and `.INSTALL` if there are maintainer scripts. pacman reads those
+//! and installs everything after them.
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use flate2::{write::GzEncoder, Compression};
+use md5::Md5;
+use rfpm::relation::{Relation, VirtualPackage};
+use sha2::{Digest, Sha256};
+
+use super::{pkg_parent_dirs, PkgEntry};
+use crate::KocaResult;
+
+/// Package metadata written to `.PKGINFO`.
+pub(super) struct PacmanInfo<'a> {
+    pub pkgname: &'a str,
+    pub pkgbase: &'a str,
+    /// The full pacman version (`1:1.0-2`).
+    pub pkgver: String,
+    pub pkgdesc: &'a str,
+    pub arch: &'a str,
+    pub packager: &'a str,
+    pub license: Option<&'a str>,
+    pub depends: &'a [Relation],
+    pub provides: &'a [VirtualPackage],
+    pub conflicts: &'a [Relation],
+    /// The contents of `.BUILDINFO`.
+    pub buildinfo: String,
+    /// The contents of `.INSTALL`, if there are maintainer scripts.
+    pub install: Option<String>,
+    /// The build timestamp (`SOURCE_DATE_EPOCH`), also used as every entry's mtime.
+    pub builddate: u64,
+}
+
+/// Write a pacman package containing `entries` to `out`.
+///
+/// Files are read once to hash them for `.MTREE`, which comes first, then streamed
+/// into the package, so only their paths and checksums are kept in memory.
+pub(super) fn write_pacman(
+    info: &PacmanInfo,
+    entries: &[PkgEntry],
+    out: &mut impl Write,
+) -> KocaResult<()> {
+    let mut sorted: Vec<&PkgEntry> = entries.iter().collect();
+    sorted.sort_by(|a, b| a.dest().cmp(b.dest()));
+
+    let mut payload = Vec::new();
+    for dir in pkg_parent_dirs(entries) {
+        payload.push((dir, Payload::Dir));
+    }
+    let mut installed_size = 0;
+    for entry in sorted {
+        let path = entry.dest().trim_start_matches('/').to_string();
+        match entry {
+            PkgEntry::File {
+                src, mode, config, ..
+            } => {
+                let digests = Digests::of(File::open(src)?)?;
+                installed_size += digests.size;
+                payload.push((
+                    path,
+                    Payload::File {
+                        src,
+                        digests,
+                        mode: *mode,
+                        config: *config,
+                    },
+                ));
+            }
+            PkgEntry::Symlink { target, .. } => {
+                payload.push((path, Payload::Symlink(target.clone())))
+            }
+        }
+    }
+
+    let pkginfo = pkginfo(info, installed_size, &payload);
+    let mut metadata = vec![
+        (".PKGINFO", pkginfo.into_bytes()),
+        (".BUILDINFO", info.buildinfo.clone().into_bytes()),
+    ];
+    if let Some(install) = &info.install {
+        metadata.push((".INSTALL", install.clone().into_bytes()));
+    }
+    let mtree = mtree(info.builddate, &metadata, &payload)?;
+
+    // makepkg compresses with zstd's default level on every core (`zstd -T0`).
+    let mut encoder = zstd::Encoder::new(out, 0)?;
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get() as u32);
+    encoder.multithread(threads)?;
+    let mut tar = tar::Builder::new(encoder);
+    for (name, data) in &metadata[..2] {
+        append_file(
+            &mut tar,
+            name,
+            0o644,
+            data.len() as u64,
+            &data[..],
+            info.builddate,
+        )?;
+    }
+    append_file(
+        &mut tar,
+        ".MTREE",
+        0o644,
+        mtree.len() as u64,
+        &mtree[..],
+        info.builddate,
+    )?;
+    for (name, data) in &metadata[2..] {
+        append_file(
+            &mut tar,
+            name,
+            0o644,
+            data.len() as u64,
+            &data[..],
+            info.builddate,
+        )?;
+    }
+
+    for (path, item) in &payload {
+        match item {
+            Payload::Dir => {
+                let mut header = base_header(info.builddate);
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                tar.append_data(&mut header, format!("{path}/"), io::empty())?;
+            }
+            Payload::File {
+                src, digests, mode, ..
+            } => append_file(
+                &mut tar,
+                path,
+                *mode,
+                digests.size,
+                File::open(src)?,
+                info.builddate,
+            )?,
+            Payload::Symlink(target) => {
+                let mut header = base_header(info.builddate);
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_mode(0o777);
+                header.set_size(0);
+                tar.append_link(&mut header, path, target)?;
+            }
+        }
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// An entry in the package payload, after the metadata files.
+enum Payload<'a> {
+    Dir,
+    File {
+        src: &'a Path,
+        digests: Digests,
+        mode: u32,
+        config: bool,
+    },
+    Symlink(String),
+}
+
+/// A file's size and the checksums `.MTREE` records for it.
+struct Digests {
+    size: u64,
+    md5: String,
+    sha256: String,
+}
+
+impl Digests {
+    /// Hash everything `reader` yields.
+    fn of(mut reader: impl Read) -> io::Result<Self> {
+        let (mut md5, mut sha256) = (Md5::new(), Sha256::new());
+        let mut buf = vec![0; 64 * 1024];
+        let mut size = 0;
+        loop {
+            let len = reader.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            md5.update(&buf[..len]);
+            sha256.update(&buf[..len]);
+            size += len as u64;
+        }
+        Ok(Self {
+            size,
+            md5: hex(&md5.finalize()),
+            sha256: hex(&sha256.finalize()),
+        })
+    }
+}
+
+/// Render `.PKGINFO`, listing config files as `backup` entries.
+fn pkginfo(info: &PacmanInfo, installed_size: u64, payload: &[(String, Payload)]) -> String {
+    let mut fields = vec![
+        ("pkgname", info.pkgname.to_string()),
+        ("pkgbase", info.pkgbase.to_string()),
+        ("pkgver", info.pkgver.clone()),
+        ("pkgdesc", info.pkgdesc.replace('\n', " ")),
+        ("builddate", info.builddate.to_string()),
+        ("packager", info.packager.to_string()),
+        ("size", installed_size.to_string()),
+        ("arch", info.arch.to_string()),
+    ];
+    if let Some(license) = info.license {
+        fields.push(("license", license.to_string()));
+    }
+    for conflict in info.conflicts {
+        fields.push(("conflict", conflict.to_pacman()));
+    }
+    for provide in info.provides {
+        fields.push(("provides", provide.to_pacman()));
+    }
+    for (path, item) in payload {
+        if let Payload::File { config: true, .. } = item {
+            fields.push(("backup", path.clone()));
+        }
+    }
+    for dep in info.depends {
+        fields.push(("depend", dep.to_pacman()));
+    }
+
+    let mut out = format!("# Generated by koca {}\n", env!("CARGO_PKG_VERSION"));
+    for (key, value) in fields {
+        out.push_str(&format!("{key} = {value}\n"));
+    }
+    out
+}
+
+/// Render the gzipped `.MTREE`, which pacman checks installed files against.
+fn mtree(
+    time: u64,
+    metadata: &[(&str, Vec<u8>)],
+    payload: &[(String, Payload)],
+) -> KocaResult<Vec<u8>> {
+    let file = |path: &str, mode: u32, digests: &Digests| {
+        format!(
+            "./{} time={time}.0 mode={mode:o} size={} type=file md5digest={} sha256digest={}\n",
+            mtree_escape(path),
+            digests.size,
+            digests.md5,
+            digests.sha256,
+        )
+    };
+
+    let mut lines = String::from("#mtree\n");
+    for (name, data) in metadata {
+        lines.push_str(&file(name, 0o644, &Digests::of(&data[..])?));
+    }
+    for (path, item) in payload {
+        lines.push_str(&match item {
+            Payload::Dir => format!("./{} time={time}.0 mode=755 type=dir\n", mtree_escape(path)),
+            Payload::File { digests, mode, .. } => file(path, *mode, digests),
+            Payload::Symlink(target) => format!(
+                "./{} time={time}.0 mode=777 type=link link={}\n",
+                mtree_escape(path),
+                mtree_escape(target)
+            ),
+        });
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(lines.as_bytes())?;
+    Ok(encoder.finish()?)
+}
+
+/// Escape a path for `.MTREE` like libarchive does (see mtree(5)): backslashes are
+/// doubled, and whitespace, `#`, `=` and every byte outside printable ASCII become
+/// octal escapes (`\040` for a space).
+fn mtree_escape(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'!'..=b'~' if !matches!(byte, b'#' | b'=') => out.push(char::from(byte)),
+            _ => out.push_str(&format!("\\{byte:03o}")),
+        }
+    }
+    out
+}
+
+/// A root-owned ustar header; the caller sets type, mode and size.
+fn base_header(mtime: u64) -> tar::Header {
+    let mut header = tar::Header::new_ustar();
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+        .set_username("root")
+        .expect("'root' should fit a tar header");
+    header
+        .set_groupname("root")
+        .expect("'root' should fit a tar header");
+    header.set_mtime(mtime);
+    header
+}
+
+/// Append a regular file of `size` bytes read from `data`.
+fn append_file(
+    tar: &mut tar::Builder<impl Write>,
+    path: &str,
+    mode: u32,
+    size: u64,
+    data: impl Read,
+    mtime: u64,
+) -> KocaResult<()> {
+    let mut header = base_header(mtime);
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(mode);
+    header.set_size(size);
+    Ok(tar.append_data(&mut header, path, data)?)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_metadata_before_the_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("foo");
+        std::fs::write(&src, b"payload").unwrap();
+        let conf = dir.path().join("foo.conf");
+        std::fs::write(&conf, b"key=value\n").unwrap();
+        let entries = [
+            PkgEntry::File {
+                dest: "/usr/bin/foo".to_string(),
+                src,
+                mode: 0o755,
+                config: false,
+            },
+            PkgEntry::File {
+                dest: "/etc/foo.conf".to_string(),
+                src: conf,
+                mode: 0o644,
+                config: true,
+            },
+        ];
+        let depends = [Relation::parse("glibc>=2.38").unwrap()];
+        let info = PacmanInfo {
+            pkgname: "foo",
+            pkgbase: "foo",
+            pkgver: "1.0-1".to_string(),
+            pkgdesc: "Foo",
+            arch: "riscv64",
+            packager: "Unknown Packager",
+            license: Some("MIT"),
+            depends: &depends,
+            provides: &[],
+            conflicts: &[],
+            buildinfo: "format = 2\n".to_string(),
+            install: Some("post_install() {\n    true\n}\n".to_string()),
+            builddate: 1_700_000_000,
+        };
+
+        let mut pkg = Vec::new();
+        write_pacman(&info, &entries, &mut pkg).unwrap();
+
+        let mut archive = tar::Archive::new(zstd::Decoder::new(&pkg[..]).unwrap());
+        let mut paths = Vec::new();
+        let mut mtree = Vec::new();
+        let mut pkginfo = String::new();
+        let mut foo = String::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().display().to_string();
+            if path == ".MTREE" {
+                std::io::copy(&mut flate2::read::GzDecoder::new(&mut entry), &mut mtree).unwrap();
+            } else if path == ".PKGINFO" {
+                entry.read_to_string(&mut pkginfo).unwrap();
+            } else if path == "usr/bin/foo" {
+                entry.read_to_string(&mut foo).unwrap();
+            }
+            paths.push(path);
+        }
+        assert_eq!(
+            paths,
+            [
+                ".PKGINFO",
+                ".BUILDINFO",
+                ".MTREE",
+                ".INSTALL",
+                "etc/",
+                "usr/",
+                "usr/bin/",
+                "etc/foo.conf",
+                "usr/bin/foo"
+            ]
+        );
+
+        let mtree = String::from_utf8(mtree).unwrap();
+        assert!(mtree.contains("./.BUILDINFO time=1700000000.0 mode=644 size=11 type=file"));
+        assert!(mtree.contains("./usr/bin/foo time=1700000000.0 mode=755 size=7 type=file"));
+
+        assert_eq!(foo, "payload");
+        assert!(pkginfo.contains("arch = riscv64\n"));
+        assert!(pkginfo.contains("backup = etc/foo.conf\n"));
+        assert!(pkginfo.contains("depend = glibc>=2.38\n"));
+        assert!(pkginfo.contains("size = 17\n"));
+    }
+
+    #[test]
+    fn escapes_mtree_paths() {
+        let src = Path::new("x");
+        let payload = [
+            ("usr/share/my docs".to_string(), Payload::Dir),
+            (
+                "usr/share/my docs/#1 café".to_string(),
+                Payload::File {
+                    src,
+                    digests: Digests::of(&b"x"[..]).unwrap(),
+                    mode: 0o644,
+                    config: false,
+                },
+            ),
+            (
+                "usr/share/a\\b".to_string(),
+                Payload::Symlink("my docs/#1 café".to_string()),
+            ),
+        ];
+        let mtree = mtree(0, &[], &payload).unwrap();
+        let mut lines = String::new();
+        flate2::read::GzDecoder::new(&mtree[..])
+            .read_to_string(&mut lines)
+            .unwrap();
+
+        assert!(lines.contains("\n./usr/share/my\\040docs time=0.0 mode=755 type=dir\n"));
+        assert!(lines.contains("\n./usr/share/my\\040docs/\\0431\\040caf\\303\\251 time=0.0 "));
+        assert!(lines.contains(
+            "\n./usr/share/a\\\\b time=0.0 mode=777 type=link link=my\\040docs/\\0431\\040caf\\303\\251\n"
+        ));
+        // Every line still splits into a path and its keywords on spaces.
+        for line in lines.lines().skip(1) {
+            let fields: Vec<_> = line.split(' ').collect();
+            assert!(
+                fields[1..].iter().all(|field| field.contains('=')),
+                "{line}"
+            );
+        }
+    }
+}
@@ -6,8 +6,8 @@
 use std::collections::HashMap;
 
 use brush_parser::ast::{
-    AssignmentName, AssignmentValue, Command, CommandPrefixOrSuffixItem, CompoundListItem,
-    FunctionDefinition, Program, Word,
+    AssignmentName, AssignmentValue, Command, CommandPrefixOrSuffixItem, CompoundCommand,
+    CompoundListItem, FunctionDefinition, Program, Word,
 };
 
 use crate::{KocaError, KocaParserError, KocaResult};
@@ -175,3 +175,66 @@ pub fn get_decls(program: &Program) -> KocaResult<DeclItems> {
 
     Ok(DeclItems { vars, funcs })
 }
+
+/// Get the variables in `names` assigned at the top level of a function's body.
+///
+/// Only plain `var=value` and `var=(...)` lines count; any other command (and
+/// anything nested in an `if`, loop, etc.) is left for the shell to run, as are
+/// other variables and forms like `var[0]=value`. A later assignment replaces an
+/// earlier one, like it would in the shell.
+pub fn get_func_assignments(
+    func: &FunctionDefinition,
+    names: &[&str],
+) -> HashMap<String, DeclValue> {
+    let mut vars = HashMap::new();
+    let CompoundCommand::BraceGroup(group) = &func.body.0 else {
+        return vars;
+    };
+
+    for item in &group.list.0 {
+        let pipeline = &item.0.first;
+        if !item.0.additional.is_empty() || pipeline.bang || pipeline.timed.is_some() {
+            continue;
+        }
+        let [Command::Simple(cmd)] = pipeline.seq.as_slice() else {
+            continue;
+        };
+        if cmd.word_or_name.is_some() || cmd.suffix.is_some() {
+            continue;
+        }
+        let Some(prefix) = &cmd.prefix else {
+            continue;
+        };
+        if !prefix
+            .0
+            .iter()
+            .all(|item| matches!(item, CommandPrefixOrSuffixItem::AssignmentWord(..)))
+        {
+            continue;
+        }
+
+        for prefix_item in &prefix.0 {
+            let CommandPrefixOrSuffixItem::AssignmentWord(assignment, _) = prefix_item else {
+                continue;
+            };
+            let AssignmentName::VariableName(name) = &assignment.name else {
+                continue;
+            };
+            if !names.contains(&name.as_str()) {
+                continue;
+            }
+
+            let value = match &assignment.value {
+                AssignmentValue::Scalar(word) => DeclValue::String(word.clone()),
+                // Associative arrays aren't anything Koca reads.
+                AssignmentValue::Array(array) if array.iter().all(|var| var.0.is_none()) => {
+                    DeclValue::Array(array.iter().map(|var| var.1.clone()).collect())
+                }
+                AssignmentValue::Array(_) => continue,
+            };
+            vars.insert(name.clone(), value);
+        }
+    }
+
+    vars
+}
@@ -0,0 +1,134 @@
+//! Writing `.rpm` packages.
+//!
+//! rfpm stamps RPMs with the time they're written, which makes every build
+//! differ. This drives the rpm crate directly instead, with the build time and
+//! file mtimes clamped to `SOURCE_DATE_EPOCH`. Package metadata still comes from
+//! the same [`rfpm::Package`] the other formats use.
+use std::io::Write;
+
+use rfpm::relation::{Op, Relation, VirtualPackage};
+
+use super::PkgEntry;
+use crate::KocaResult;
+
+/// Write an rpm of `pkg`'s metadata with `entries` as its payload to `out`.
+///
+/// `arch` is the rpm arch string, since rfpm's own arch only covers a few of them.
+/// `scripts` are the `%pre`, `%post`, `%preun` and `%postun` scriptlets.
+pub(super) fn write_rpm(
+    pkg: &rfpm::Package,
+    arch: &str,
+    scripts: [Option<String>; 4],
+    entries: &[PkgEntry],
+    source_date: u64,
+    out: &mut impl Write,
+) -> KocaResult<()> {
+    let summary = pkg.description.lines().next().unwrap_or_default();
+    let mut builder = rpm::PackageBuilder::new(
+        &pkg.name,
+        &pkg.version,
+        pkg.license.as_deref().unwrap_or("Unknown"),
+        arch,
+        summary,
+    )
+    .description(&pkg.description)
+    .release(&pkg.release)
+    .source_date(u32::try_from(source_date).unwrap_or(u32::MAX))
+    .compression(rpm::CompressionWithLevel::Gzip(9));
+
+    if let Some(epoch) = pkg.epoch {
+        builder = builder.epoch(epoch);
+    }
+    if let Some(maintainer) = &pkg.maintainer {
+        builder = builder.packager(maintainer);
+    }
+    for dep in &pkg.depends {
+        builder = builder.requires(relation(dep));
+    }
+    for provide in &pkg.provides {
+        builder = builder.provides(virtual_package(provide));
+    }
+    for conflict in &pkg.conflicts {
+        builder = builder.conflicts(relation(conflict));
+    }
+
+    let [pre, post, preun, postun] = scripts;
+    if let Some(script) = pre {
+        builder = builder.pre_install_script(script);
+    }
+    if let Some(script) = post {
+        builder = builder.post_install_script(script);
+    }
+    if let Some(script) = preun {
+        builder = builder.pre_uninstall_script(script);
+    }
+    if let Some(script) = postun {
+        builder = builder.post_uninstall_script(script);
+    }
+
+    // The rpm crate reads every entry from a file on disk, so symlinks borrow an
+    // empty one. Its mtime is clamped like the rest.
+    let empty = tempfile::NamedTempFile::new()?;
+    for entry in entries {
+        builder = match entry {
+            PkgEntry::File {
+                dest,
+                src,
+                mode,
+                config,
+            } => {
+                let mut opts = rpm::FileOptions::new(dest.as_str())
+                    .mode(rpm::FileMode::regular(mode_u16(*mode)?))
+                    .user("root")
+                    .group("root");
+                if *config {
+                    opts = opts.is_config_noreplace();
+                }
+                builder.with_file(src, opts).map_err(rpm_error)?
+            }
+            PkgEntry::Symlink { src, target } => {
+                let opts = rpm::FileOptions::new(src.as_str())
+                    .mode(rpm::FileMode::symbolic_link(0o777))
+                    .symlink(target.as_str())
+                    .user("root")
+                    .group("root");
+                builder.with_file(empty.path(), opts).map_err(rpm_error)?
+            }
+        };
+    }
+
+    let package = builder.build().map_err(rpm_error)?;
+    package.write(out).map_err(rpm_error)?;
+    Ok(())
+}
+
+fn relation(rel: &Relation) -> rpm::Dependency {
+    let Some(constraint) = &rel.constraint else {
+        return rpm::Dependency::any(&rel.name);
+    };
+    let (name, ver) = (&rel.name, &constraint.version);
+    match constraint.op {
+        Op::GreaterEqual => rpm::Dependency::greater_eq(name, ver),
+        Op::LessEqual => rpm::Dependency::less_eq(name, ver),
+        Op::Greater => rpm::Dependency::greater(name, ver),
+        Op::Less => rpm::Dependency::less(name, ver),
+        Op::Equal => rpm::Dependency::eq(name, ver),
+    }
+}
+
+fn virtual_package(provide: &VirtualPackage) -> rpm::Dependency {
+    match &provide.version {
+        Some(ver) => rpm::Dependency::eq(&provide.name, ver),
+        None => rpm::Dependency::any(&provide.name),
+    }
+}
+
+fn mode_u16(mode: u32) -> KocaResult<u16> {
+    u16::try_from(mode).map_err(|_| {
+        std::io::Error::other(format!("file mode {mode:#o} doesn't fit an rpm")).into()
+    })
+}
+
+fn rpm_error(err: rpm::Error) -> crate::KocaError {
+    std::io::Error::other(err.to_string()).into()
+}
@@ -0,0 +1,305 @@
+//! Maintainer scripts run by the package manager on install, upgrade and removal.
+//!
+//! These are declared as `pre_install()`, `post_upgrade()`, etc. functions in the
+//! build file, the same names makepkg uses for `.install` files. Debian and RPM
+//! only have four scriptlets each and tell install and upgrade apart through
+//! their arguments, so each hook is wrapped in a check on those arguments.
+//!
+//! Hooks get makepkg's arguments in every format: the package's version, then the
+//! version being replaced for `pre_upgrade()` and `post_upgrade()`. rpm doesn't
+//! tell its scriptlets the old version, so there the upgrade hooks only get the
+//! new one.
+use std::fmt;
+
+use brush_parser::ast::FunctionDefinition;
+
+use super::BundleFormat;
+
+/// A point in the package lifecycle that a maintainer script can hook into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScriptHook {
+    /// Before the package's files are installed for the first time.
+    PreInstall,
+    /// After the package's files are installed for the first time.
+    PostInstall,
+    /// Before an older version of the package is replaced.
+    PreUpgrade,
+    /// After an older version of the package is replaced.
+    PostUpgrade,
+    /// Before the package's files are removed.
+    PreRemove,
+    /// After the package's files are removed.
+    PostRemove,
+}
+
+impl ScriptHook {
+    /// All hooks, in lifecycle order.
+    pub const ALL: [ScriptHook; 6] = [
+        ScriptHook::PreInstall,
+        ScriptHook::PostInstall,
+        ScriptHook::PreUpgrade,
+        ScriptHook::PostUpgrade,
+        ScriptHook::PreRemove,
+        ScriptHook::PostRemove,
+    ];
+
+    /// The build file function defining this hook (e.g. `post_install`).
+    pub fn func_name(&self) -> &'static str {
+        match self {
+            ScriptHook::PreInstall => "pre_install",
+            ScriptHook::PostInstall => "post_install",
+            ScriptHook::PreUpgrade => "pre_upgrade",
+            ScriptHook::PostUpgrade => "post_upgrade",
+            ScriptHook::PreRemove => "pre_remove",
+            ScriptHook::PostRemove => "post_remove",
+        }
+    }
+
+    /// Find the hook for a build file function name.
+    pub fn from_func_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|hook| hook.func_name() == name)
+    }
+}
+
+impl fmt::Display for ScriptHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.func_name())
+    }
+}
+
+/// The maintainer script functions defined in a build file.
+#[derive(Clone, Debug, Default)]
+pub struct Scripts {
+    hooks: Vec<(ScriptHook, FunctionDefinition)>,
+}
+
+/// Which hooks run from a single scriptlet, each guarded by a shell condition on
+/// the arguments the package manager passes in, and called with the version plus
+/// any arguments forwarded from the scriptlet's own.
+type Dispatch = [(ScriptHook, &'static str, &'static str)];
+
+// dpkg calls `preinst install|upgrade [<old-version>]`, `postinst configure
+// [<old-version>]`, `prerm remove|upgrade` and `postrm remove|purge|upgrade|...`.
+const DEB_PREINST: &Dispatch = &[
+    (ScriptHook::PreInstall, r#"[ "$1" = install ]"#, ""),
+    (ScriptHook::PreUpgrade, r#"[ "$1" = upgrade ]"#, r#" "$2""#),
+];
+const DEB_POSTINST: &Dispatch = &[
+    (
+        ScriptHook::PostInstall,
+        r#"[ "$1" = configure ] && [ -z "$2" ]"#,
+        "",
+    ),
+    (
+        ScriptHook::PostUpgrade,
+        r#"[ "$1" = configure ] && [ -n "$2" ]"#,
+        r#" "$2""#,
+    ),
+];
+const DEB_PRERM: &Dispatch = &[(ScriptHook::PreRemove, r#"[ "$1" = remove ]"#, "")];
+const DEB_POSTRM: &Dispatch = &[(ScriptHook::PostRemove, r#"[ "$1" = remove ]"#, "")];
+
+// RPM passes the number of versions installed once the transaction completes.
+const RPM_PRE: &Dispatch = &[
+    (ScriptHook::PreInstall, r#"[ "$1" -eq 1 ]"#, ""),
+    (ScriptHook::PreUpgrade, r#"[ "$1" -gt 1 ]"#, ""),
+];
+const RPM_POST: &Dispatch = &[
+    (ScriptHook::PostInstall, r#"[ "$1" -eq 1 ]"#, ""),
+    (ScriptHook::PostUpgrade, r#"[ "$1" -gt 1 ]"#, ""),
+];
+const RPM_PREUN: &Dispatch = &[(ScriptHook::PreRemove, r#"[ "$1" -eq 0 ]"#, "")];
+const RPM_POSTUN: &Dispatch = &[(ScriptHook::PostRemove, r#"[ "$1" -eq 0 ]"#, "")];
+
+impl Scripts {
+    /// No maintainer scripts at all.
+    pub(crate) const EMPTY: Scripts = Scripts { hooks: Vec::new() };
+
+    /// Record the function for `hook`, replacing any earlier definition.
+    pub(crate) fn insert(&mut self, hook: ScriptHook, func: FunctionDefinition) {
+        self.hooks.retain(|(h, _)| *h != hook);
+        self.hooks.push((hook, func));
+    }
+
+    /// Whether no maintainer scripts are defined.
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Whether a function is defined for `hook`.
+    pub fn has(&self, hook: ScriptHook) -> bool {
+        self.get(hook).is_some()
+    }
+
+    fn get(&self, hook: ScriptHook) -> Option<&FunctionDefinition> {
+        self.hooks
+            .iter()
+            .find_map(|(h, func)| (*h == hook).then_some(func))
+    }
+
+    /// Render the scriptlets for `format`, passing `version` (`1:1.0-2`) to the hooks.
+    pub fn to_rfpm(&self, format: BundleFormat, version: &str) -> rfpm::Scripts {
+        let dispatches = match format {
+            BundleFormat::Deb => [DEB_PREINST, DEB_POSTINST, DEB_PRERM, DEB_POSTRM],
+            BundleFormat::Rpm => [RPM_PRE, RPM_POST, RPM_PREUN, RPM_POSTUN],
+            // pacman and apk packages aren't written by rfpm; see `to_pacman` and `to_apk`.
+            BundleFormat::Pacman | BundleFormat::Apk => return rfpm::Scripts::default(),
+        };
+        let [pre_install, post_install, pre_remove, post_remove] =
+            dispatches.map(|dispatch| self.render(dispatch, version).map(rfpm::Content::from));
+
+        rfpm::Scripts {
+            pre_install,
+            post_install,
+            pre_remove,
+            post_remove,
+        }
+    }
+
+    /// Render the `preinst`, `postinst`, `prerm` and `postrm` scripts, for the deb writer.
+    pub(crate) fn to_deb(&self, version: &str) -> [Option<String>; 4] {
+        [DEB_PREINST, DEB_POSTINST, DEB_PRERM, DEB_POSTRM]
+            .map(|dispatch| self.render(dispatch, version))
+    }
+
+    /// Render the `%pre`, `%post`, `%preun` and `%postun` scriptlets, for the rpm writer.
+    pub(crate) fn to_rpm(&self, version: &str) -> [Option<String>; 4] {
+        [RPM_PRE, RPM_POST, RPM_PREUN, RPM_POSTUN].map(|dispatch| self.render(dispatch, version))
+    }
+
+    /// Render pacman's `.INSTALL` file, or [`None`] if there are no maintainer scripts.
+    ///
+    /// pacman calls each hook by its function name, which is the name it has in the
+    /// build file, so the functions are copied over as they are.
+    pub(crate) fn to_pacman(&self) -> Option<String> {
+        if self.hooks.is_empty() {
+            return None;
+        }
+        Some(
+            self.hooks
+                .iter()
+                .map(|(_, func)| format!("{func}\n"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    /// Render the apk control scripts as `(file name, contents)`.
+    ///
+    /// apk has a script per hook, so each is just the function and a call to it.
+    /// apk already passes makepkg's arguments (in its own version syntax), so
+    /// they're forwarded as they are.
+    pub(crate) fn to_apk(&self) -> Vec<(&'static str, String)> {
+        self.hooks
+            .iter()
+            .map(|(hook, func)| {
+                let name = match hook {
+                    ScriptHook::PreInstall => ".pre-install",
+                    ScriptHook::PostInstall => ".post-install",
+                    ScriptHook::PreUpgrade => ".pre-upgrade",
+                    ScriptHook::PostUpgrade => ".post-upgrade",
+                    ScriptHook::PreRemove => ".pre-deinstall",
+                    ScriptHook::PostRemove => ".post-deinstall",
+                };
+                (
+                    name,
+                    format!("#!/bin/sh\nset -e\n\n{func}\n\n{hook} \"$@\"\n"),
+                )
+            })
+            .collect()
+    }
+
+    /// Render a standalone scriptlet running the defined hooks in `dispatch`, or
+    /// [`None`] if none of them are defined.
+    fn render(&self, dispatch: &Dispatch, version: &str) -> Option<String> {
+        let defined: Vec<_> = dispatch
+            .iter()
+            .filter_map(|(hook, cond, args)| self.get(*hook).map(|func| (hook, cond, args, func)))
+            .collect();
+        if defined.is_empty() {
+            return None;
+        }
+
+        let mut script = String::from("#!/bin/bash\nset -e\n");
+        for (_, _, _, func) in &defined {
+            script.push_str(&format!("\n{func}\n"));
+        }
+        script.push('\n');
+        // Versions are only ever letters, digits and `.+~:-`, so they're safe to quote.
+        for (hook, cond, args, _) in &defined {
+            script.push_str(&format!(
+                "if {cond}; then\n    {hook} '{version}'{args}\nfi\n"
+            ));
+        }
+        Some(script)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::parser;
+
+    fn scripts(src: &str) -> Scripts {
+        let program = brush_parser::Parser::new(
+            std::io::Cursor::new(src),
+            &Default::default(),
+            &Default::default(),
+        )
+        .parse_program()
+        .unwrap();
+
+        let mut scripts = Scripts::default();
+        for func in parser::get_decls(&program).unwrap().funcs {
+            let hook = ScriptHook::from_func_name(&func.fname.value).unwrap();
+            scripts.insert(hook, func);
+        }
+        scripts
+    }
+
+    #[test]
+    fn renders_only_defined_hooks() {
+        let s = scripts("post_install() {\n    systemctl daemon-reload\n}\n");
+        let rendered = s.render(DEB_POSTINST, "1.0-1").unwrap();
+
+        assert!(rendered.starts_with("#!/bin/bash\nset -e\n"));
+        assert!(rendered.contains("systemctl daemon-reload"));
+        assert!(rendered.contains(
+            "if [ \"$1\" = configure ] && [ -z \"$2\" ]; then\n    post_install '1.0-1'\nfi"
+        ));
+        assert!(!rendered.contains("post_upgrade"));
+        assert!(s.render(DEB_PRERM, "1.0-1").is_none());
+    }
+
+    #[test]
+    fn dispatches_install_and_upgrade_for_rpm() {
+        let s = scripts("pre_install() {\n    true\n}\npre_upgrade() {\n    true\n}\n");
+        let rendered = s.render(RPM_PRE, "1.0-1").unwrap();
+
+        assert!(rendered.contains("if [ \"$1\" -eq 1 ]; then\n    pre_install '1.0-1'\nfi"));
+        assert!(rendered.contains("if [ \"$1\" -gt 1 ]; then\n    pre_upgrade '1.0-1'\nfi"));
+    }
+
+    #[test]
+    fn passes_new_and_old_versions_on_deb_upgrade() {
+        let s = scripts("post_upgrade() {\n    echo \"$1 $2\"\n}\n");
+        let rendered = s.render(DEB_POSTINST, "2:1.1-1").unwrap();
+
+        // dpkg runs `postinst configure <old-version>` on an upgrade.
+        let output = std::process::Command::new("bash")
+            .args(["-c", &rendered, "postinst", "configure", "2:1.0-3"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2:1.1-1 2:1.0-3\n");
+    }
+
+    #[test]
+    fn maps_function_names() {
+        assert_eq!(
+            ScriptHook::from_func_name("post_remove"),
+            Some(ScriptHook::PostRemove)
+        );
+        assert_eq!(ScriptHook::from_func_name("package"), None);
+    }
+}
@@ -5,14 +5,26 @@ static EPOCH_SEPARATOR: &str = ":";
 static PKGREL_SEPARATOR: &str = "-";
 
 /// A package's `pkgver` component.
-#[derive(Clone, PartialEq)]
-pub struct PkgVersion {
-    /// The major version component.
-    pub major: u32,
-    /// The minor version component.
-    pub minor: u32,
-    /// The patch release component.
-    pub patch: u32,
+///
+/// Any upstream version starting with a letter or digit and made of alphanumeric
+/// segments separated by `.` or `+` is accepted (`1.2`, `2024.10.01.1`, `1.0rc1`,
+/// `5.15.0+git20240101`, makepkg's VCS-style `r123.abcdef`), as is `~` (`1.0~rc1`).
+/// Those are the characters both deb and rpm allow in a version. Formats that are
+/// stricter reject a version when it's written in their syntax, like deb does
+/// with ones not starting with a digit (see [`Version::to_deb`]).
+///
+/// Versions are ordered like dpkg orders them: only `~` marks a pre-release
+/// (`1.0~rc1` < `1.0`), and any other trailing characters a post-release
+/// (`1.0` < `1.0rc1`, `1.0` < `1.0+git1`). Numbers compare by value, so `1.01`
+/// and `1.1` are equal.
+#[derive(Clone, Debug)]
+pub struct PkgVersion(String);
+
+impl PkgVersion {
+    /// The version as written in the build file.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
 impl FromStr for PkgVersion {
@@ -22,48 +34,104 @@ impl FromStr for PkgVersion {
     ///
     /// Returns [`KocaParserError::InvalidVersion`] if the string is not a valid `pkgver`.
     fn from_str(pkgver: &str) -> KocaResult<Self> {
-        let version_err = || Err(KocaParserError::InvalidVersion(pkgver.to_string()).into());
+        let valid_start = pkgver.starts_with(|c: char| c.is_ascii_alphanumeric());
+        let valid_chars = pkgver
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '~'));
 
-        if pkgver.chars().filter(|c| c == &'.').count() != 2 {
-            return version_err();
-        }
-
-        let mut sections = vec![];
-        for part in pkgver.split('.') {
-            match part.parse::<u32>() {
-                Ok(value) => sections.push(value),
-                Err(_) => return version_err(),
-            }
+        if !valid_start || !valid_chars {
+            return Err(KocaParserError::InvalidVersion(pkgver.to_string()).into());
         }
 
-        Ok(Self {
-            major: sections[0],
-            minor: sections[1],
-            patch: sections[2],
-        })
+        Ok(Self(pkgver.to_string()))
     }
 }
 
 impl fmt::Display for PkgVersion {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+        write!(f, "{}", self.0)
     }
 }
 
 impl PartialOrd for PkgVersion {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        if self.major != other.major {
-            self.major.partial_cmp(&other.major)
-        } else if self.minor != other.minor {
-            self.minor.partial_cmp(&other.minor)
-        } else {
-            self.patch.partial_cmp(&other.patch)
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PkgVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PkgVersion {}
+
+impl Ord for PkgVersion {
+    /// Compare with dpkg's `verrevcmp`: alternating runs of non-digits and digits,
+    /// where non-digits compare character by character and digits by value.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (mut a, mut b) = (self.0.as_bytes(), other.0.as_bytes());
+        let is_digit = |c: &u8| c.is_ascii_digit();
+
+        while !a.is_empty() || !b.is_empty() {
+            let a_len = a.iter().position(is_digit).unwrap_or(a.len());
+            let b_len = b.iter().position(is_digit).unwrap_or(b.len());
+            let (a_str, a_rest) = a.split_at(a_len);
+            let (b_str, b_rest) = b.split_at(b_len);
+            for i in 0..a_len.max(b_len) {
+                let ord = dpkg_order(a_str.get(i)).cmp(&dpkg_order(b_str.get(i)));
+                if ord.is_ne() {
+                    return ord;
+                }
+            }
+
+            let a_len = a_rest
+                .iter()
+                .position(|c| !is_digit(c))
+                .unwrap_or(a_rest.len());
+            let b_len = b_rest
+                .iter()
+                .position(|c| !is_digit(c))
+                .unwrap_or(b_rest.len());
+            let (a_num, a_rest) = a_rest.split_at(a_len);
+            let (b_num, b_rest) = b_rest.split_at(b_len);
+            let a_num = strip_zeros(a_num);
+            let b_num = strip_zeros(b_num);
+            let ord = a_num.len().cmp(&b_num.len()).then_with(|| a_num.cmp(b_num));
+            if ord.is_ne() {
+                return ord;
+            }
+
+            (a, b) = (a_rest, b_rest);
         }
+
+        Ordering::Equal
     }
 }
 
+/// The weight dpkg gives a non-digit character: `~` sorts before the end of the
+/// string, and letters before any other character.
+fn dpkg_order(c: Option<&u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_alphabetic() => i32::from(*c),
+        Some(c) => i32::from(*c) + 256,
+    }
+}
+
+/// Strip a run of digits' leading zeros, so it compares by value.
+fn strip_zeros(digits: &[u8]) -> &[u8] {
+    let start = digits
+        .iter()
+        .position(|c| *c != b'0')
+        .unwrap_or(digits.len());
+    &digits[start..]
+}
+
 /// A package's version.
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Version {
     /// The version's package version segment (`1.0.0` in `1.0.0-2`).
     pub pkgver: PkgVersion,
@@ -125,6 +193,101 @@ impl FromStr for Version {
     }
 }
 
+/// The suffixes apk sorts before the bare version, which dpkg only does after a `~`.
+const APK_PRE_SUFFIXES: [&str; 4] = ["alpha", "beta", "pre", "rc"];
+
+/// The suffixes apk sorts after the bare version, which dpkg does after anything but a `~`.
+const APK_POST_SUFFIXES: [&str; 5] = ["cvs", "svn", "git", "hg", "p"];
+
+impl Version {
+    /// Format the version with the default pkgrel filled in (`1:1.0-2`, or `1.0-1`
+    /// with no epoch or pkgrel), as rpm and pacman write it.
+    pub fn full(&self) -> String {
+        let version = format!(
+            "{}{PKGREL_SEPARATOR}{}",
+            self.pkgver,
+            self.pkgrel.unwrap_or(1)
+        );
+        match self.epoch {
+            Some(epoch) => format!("{epoch}{EPOCH_SEPARATOR}{version}"),
+            None => version,
+        }
+    }
+
+    /// Format the version in deb's syntax, which is [`Version::full`]'s.
+    ///
+    /// dpkg requires upstream versions to start with a digit, so returns
+    /// [`KocaError::UnsupportedPackageVersion`] for ones like `r123.abcdef`.
+    pub fn to_deb(&self) -> KocaResult<String> {
+        if !self
+            .pkgver
+            .as_str()
+            .starts_with(|c: char| c.is_ascii_digit())
+        {
+            return Err(KocaError::UnsupportedPackageVersion(
+                self.pkgver.to_string(),
+                "a deb",
+            ));
+        }
+        Ok(self.full())
+    }
+
+    /// Format the version in apk's syntax (`1.0_rc1-r2`).
+    ///
+    /// apk has no epoch, so it's dropped, and only allows one letter after the
+    /// numbers followed by known suffixes: `1.0~rc1` becomes `1.0_rc1` and
+    /// `1.0+git20240101` becomes `1.0_git20240101`. A suffix is only mapped if apk
+    /// orders it against the bare version the same way dpkg does, so `1.0rc1` (newer
+    /// than `1.0`, but `1.0_rc1` is older) isn't. Returns
+    /// [`KocaError::UnsupportedPackageVersion`] for anything else, like `1.0+dfsg`.
+    pub fn to_apk(&self) -> KocaResult<String> {
+        let pkgver = self.pkgver.as_str();
+        let unsupported = || KocaError::UnsupportedPackageVersion(pkgver.to_string(), "an apk");
+        let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+
+        // The numbers, e.g. `1.2.3`.
+        let mut end = digits(pkgver);
+        while pkgver[end..].starts_with('.') && digits(&pkgver[end + 1..]) > 0 {
+            end += 1 + digits(&pkgver[end + 1..]);
+        }
+        if end == 0 {
+            return Err(unsupported());
+        }
+        let mut out = pkgver[..end].to_string();
+        let mut rest = &pkgver[end..];
+
+        // An optional letter, e.g. the `a` in `1.0a`.
+        let word_len = |s: &str| {
+            s.find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(s.len())
+        };
+        if word_len(rest) == 1 && !rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            out.push_str(&rest[..1]);
+            rest = &rest[1..];
+        }
+
+        // Suffixes, e.g. `~rc1` or `+git20240101`.
+        while !rest.is_empty() {
+            let pre_release = rest.starts_with('~');
+            rest = rest.strip_prefix(['.', '+', '~']).unwrap_or(rest);
+            let (suffix, after) = rest.split_at(word_len(rest));
+            let known = if pre_release {
+                APK_PRE_SUFFIXES.contains(&suffix)
+            } else {
+                APK_POST_SUFFIXES.contains(&suffix)
+            };
+            if !known {
+                return Err(unsupported());
+            }
+            let (number, after) = after.split_at(digits(after));
+            out.push_str(&format!("_{suffix}{number}"));
+            rest = after;
+        }
+
+        Ok(format!("{out}-r{}", self.pkgrel.unwrap_or(1)))
+    }
+}
+
 impl fmt::Display for Version {
     /// Format the [`Version`] as a string.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -142,34 +305,131 @@ impl fmt::Display for Version {
 
 impl PartialOrd for Version {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        if self == other {
-            return Some(Ordering::Equal);
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    /// Compare by epoch, then pkgver, then pkgrel. A missing epoch or pkgrel counts as `0`,
+    /// but sorts before an explicit `0` so only identical versions are equal.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch
+            .unwrap_or(0)
+            .cmp(&other.epoch.unwrap_or(0))
+            .then_with(|| self.pkgver.cmp(&other.pkgver))
+            .then_with(|| self.pkgrel.unwrap_or(0).cmp(&other.pkgrel.unwrap_or(0)))
+            .then_with(|| self.epoch.cmp(&other.epoch))
+            .then_with(|| self.pkgrel.cmp(&other.pkgrel))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> Version {
+        Version::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn accepts_real_world_versions() {
+        for ver in [
+            "1.2",
+            "2024.10.01.1",
+            "1.0rc1",
+            "5.15.0+git20240101",
+            "1.0~beta2",
+            "0.r42.abc1234",
+            "r42.abc1234",
+            "v1.0",
+        ] {
+            assert_eq!(v(ver).to_string(), ver);
         }
+        assert_eq!(v("2:1.0rc1-3").to_string(), "2:1.0rc1-3");
+    }
 
-        // First check if one epoch is bigger than the other.
-        let self_epoch = self.epoch.unwrap_or(0);
-        let other_epoch = other.epoch.unwrap_or(0);
-        let epoch_order = self_epoch
-            .partial_cmp(&other_epoch)
-            .expect("epoch should be comparable");
+    #[test]
+    fn rejects_invalid_versions() {
+        for ver in [
+            "", ".1", "1.0 beta", "1/0", "1.0-1-2", "a:1.0", "0.9_3", "~1", "+git1",
+        ] {
+            assert!(Version::from_str(ver).is_err(), "{ver} should be rejected");
+        }
+    }
 
-        if epoch_order != Ordering::Equal {
-            return Some(epoch_order);
+    #[test]
+    fn orders_upstream_versions() {
+        let ascending = [
+            ("1.0~rc1", "1.0"),
+            ("1.0", "1.0rc1"),
+            ("1.0~rc1", "1.0rc1"),
+            ("1.0~", "1.0"),
+            ("1.0~~", "1.0~"),
+            ("1.0a", "1.0+git"),
+            ("1.0+git", "1.0.1"),
+            ("1.0~beta2", "1.0~rc1"),
+            ("1.0", "1.0a"),
+            ("1.0", "1.0.1"),
+            ("1.2", "1.10"),
+            ("1.0", "1.0+git"),
+            ("5.15.0+git20240101", "5.15.1"),
+            ("1.0rc1", "1.0rc2"),
+            ("2024.9.30", "2024.10.01.1"),
+            ("5.15.0", "5.15.0+git20240101"),
+            ("9.9-1", "1:0.1-1"),
+            ("1.0-1", "1.0-2"),
+            ("r42.abc1234", "r123.abcdef"),
+        ];
+        for (lower, higher) in ascending {
+            assert!(v(lower) < v(higher), "{lower} should sort before {higher}");
+            assert!(v(higher) > v(lower), "{higher} should sort after {lower}");
         }
+    }
 
-        // Next, check against the pkgver.
-        let pkgver_order = self
-            .pkgver
-            .partial_cmp(&other.pkgver)
-            .expect("pkgver should be comparable");
-        if pkgver_order != Ordering::Equal {
-            return Some(pkgver_order);
+    #[test]
+    fn formats_apk_versions() {
+        for (version, apk) in [
+            ("1:1.0-2", "1.0-r2"),
+            ("2024.10.01.1", "2024.10.01.1-r1"),
+            ("1.0a-3", "1.0a-r3"),
+            ("1.0~rc1-1", "1.0_rc1-r1"),
+            ("5.15.0+git20240101-1", "5.15.0_git20240101-r1"),
+            ("1.0.p2-1", "1.0_p2-r1"),
+            ("1.0p2-1", "1.0_p2-r1"),
+        ] {
+            assert_eq!(v(version).to_apk().unwrap(), apk, "{version}");
+        }
+        for version in [
+            "0.r42.abc1234",
+            "1.0+dfsg",
+            "1.0ab",
+            "1.0rc1",
+            "1.0~git1",
+            "r123.abcdef",
+            "p1",
+        ] {
+            assert!(v(version).to_apk().is_err(), "{version} should be rejected");
         }
+    }
 
-        // Lastly, compare against pkgrel if all else fails.
-        let self_pkgrel = self.pkgrel.unwrap_or(0);
-        let other_pkgrel = other.pkgrel.unwrap_or(0);
+    #[test]
+    fn formats_full_and_deb_versions() {
+        assert_eq!(v("1.0").full(), "1.0-1");
+        assert_eq!(v("2:1.0-3").full(), "2:1.0-3");
+        assert_eq!(v("1.0").to_deb().unwrap(), "1.0-1");
+        assert_eq!(v("r123.abcdef-2").full(), "r123.abcdef-2");
+        assert!(matches!(
+            v("r123.abcdef").to_deb(),
+            Err(KocaError::UnsupportedPackageVersion(ver, "a deb")) if ver == "r123.abcdef"
+        ));
+    }
 
-        self_pkgrel.partial_cmp(&other_pkgrel)
+    #[test]
+    fn compares_numbers_by_value() {
+        assert_eq!(v("1:1.0-2"), v("1:1.0-2"));
+        assert_eq!(v("1.01"), v("1.1"));
+        assert_eq!(v("1.01").cmp(&v("1.1")), Ordering::Equal);
+        assert_ne!(v("1.0"), v("1.0-0"));
+        assert!(v("1.0") < v("1.0-0"));
     }
 }
@@ -0,0 +1,248 @@
+//! Credentials, proxies and CA certificates for fetching private sources.
+//!
+//! HTTP downloads pick up:
+//! - A login from `~/.netrc` (or `$NETRC`) for the URL's host, sent as basic auth.
+//! - A header from `KOCA_HTTP_HEADER_<HOST>`, where `<HOST>` is the host uppercased
+//!   with anything but letters and digits turned into `_` (`KOCA_HTTP_HEADER_GITHUB_COM`).
+//!   The value is either a full `Name: value` header or a bare token, sent as
+//!   `Authorization: Bearer <token>`.
+//! - `HTTPS_PROXY`/`HTTP_PROXY`/`NO_PROXY`, which reqwest reads itself.
+//! - Extra CA certificates from the PEM bundle at `KOCA_CA_BUNDLE`.
+//!
+//! Git fetches use the same proxy variables and CA bundle, and authenticate with
+//! the SSH agent, a netrc login, or git's configured credential helpers.
+use std::path::PathBuf;
+use std::sync::Once;
+
+use reqwest::header::{HeaderName, HeaderValue, AUTHORIZATION};
+
+/// Build the HTTP client for source downloads, trusting `KOCA_CA_BUNDLE` if it's set.
+///
+/// It doesn't follow redirects itself: reqwest would carry a custom header over to
+/// another host, so the caller follows them and calls [`authorize`] for each hop.
+pub(super) fn http_client() -> reqwest::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent("koca")
+        .redirect(reqwest::redirect::Policy::none());
+    if let Some(bundle) = ca_bundle() {
+        // An unreadable bundle is left for the TLS handshake to complain about.
+        if let Ok(pem) = std::fs::read(bundle) {
+            for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+    }
+    Ok(builder)
+}
+
+/// Add any configured credentials for `url`'s host to `request`, reading the
+/// `KOCA_HTTP_HEADER_*` variables through `env` (normally [`process_env`]).
+pub(super) fn authorize(
+    request: reqwest::RequestBuilder,
+    url: &str,
+    env: &(dyn Fn(&str) -> Option<String> + Sync),
+) -> reqwest::RequestBuilder {
+    let Some(host) = host(url) else {
+        return request;
+    };
+    if let Some((name, value)) = env(&header_var(&host)).and_then(|v| parse_header(&v)) {
+        return request.header(name, value);
+    }
+    match netrc_login(&host) {
+        Some((login, password)) => request.basic_auth(login, Some(password)),
+        None => request,
+    }
+}
+
+/// Read a variable from the process environment.
+pub(super) fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+/// Set up `fo` to use the configured proxy and CA bundle.
+pub(super) fn configure_git(fo: &mut git2::FetchOptions<'_>) {
+    static CA: Once = Once::new();
+    CA.call_once(|| {
+        if let Some(bundle) = ca_bundle() {
+            // SAFETY: this runs once, before any git fetch uses the setting.
+            let _ = unsafe { git2::opts::set_ssl_cert_file(bundle) };
+        }
+    });
+
+    let mut proxy = git2::ProxyOptions::new();
+    proxy.auto();
+    fo.proxy_options(proxy);
+}
+
+/// Answer libgit2's credential requests, giving up after a few tries so a rejected
+/// credential doesn't loop forever.
+pub(super) fn git_credentials(cb: &mut git2::RemoteCallbacks<'_>) {
+    let mut attempts = 0;
+    cb.credentials(move |url, username, allowed| {
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            return git2::Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            if let Some((login, password)) = host(url).and_then(|h| netrc_login(&h)) {
+                return git2::Cred::userpass_plaintext(&login, &password);
+            }
+            let config = git2::Config::open_default()?;
+            return git2::Cred::credential_helper(&config, url, username);
+        }
+        if allowed.contains(git2::CredentialType::USERNAME) {
+            return git2::Cred::username(username.unwrap_or("git"));
+        }
+        git2::Cred::default()
+    });
+}
+
+fn ca_bundle() -> Option<PathBuf> {
+    std::env::var_os("KOCA_CA_BUNDLE")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+fn host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(str::to_string)
+}
+
+/// The variable holding extra headers for `url`'s host, if it has one.
+pub(super) fn header_var_for(url: &str) -> Option<String> {
+    host(url).map(|host| header_var(&host))
+}
+
+/// The variable holding extra headers for `host`.
+fn header_var(host: &str) -> String {
+    let host: String = host
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("KOCA_HTTP_HEADER_{host}")
+}
+
+/// Parse `Name: value`, or a bare bearer token.
+fn parse_header(value: &str) -> Option<(HeaderName, HeaderValue)> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    match value.split_once(':') {
+        Some((name, v)) if !name.contains(char::is_whitespace) => Some((
+            HeaderName::from_bytes(name.as_bytes()).ok()?,
+            HeaderValue::from_str(v.trim()).ok()?,
+        )),
+        _ => Some((
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {value}")).ok()?,
+        )),
+    }
+}
+
+/// Look up the netrc login for `host`.
+fn netrc_login(host: &str) -> Option<(String, String)> {
+    let path = std::env::var_os("NETRC")
+        .map(PathBuf::from)
+        .or_else(|| directories::BaseDirs::new().map(|d| d.home_dir().join(".netrc")))?;
+    parse_netrc(&std::fs::read_to_string(path).ok()?, host)
+}
+
+/// Which netrc entry the tokens being read belong to.
+#[derive(Clone, Copy, PartialEq)]
+enum Entry {
+    Host,
+    Default,
+    Other,
+}
+
+/// Find the `login` and `password` for `host` in netrc `contents`, falling back to
+/// a `default` entry.
+fn parse_netrc(contents: &str, host: &str) -> Option<(String, String)> {
+    let mut tokens = contents.split_whitespace();
+    let (mut found, mut default) = (None, None);
+    let mut entry = Entry::Other;
+    let (mut login, mut password) = (None, None);
+
+    loop {
+        let token = tokens.next();
+        if matches!(token, None | Some("machine" | "default")) {
+            let creds = Option::zip(login.take(), password.take());
+            match entry {
+                Entry::Host if found.is_none() => found = creds,
+                Entry::Default if default.is_none() => default = creds,
+                _ => {}
+            }
+        }
+        match token {
+            None => break,
+            Some("machine") => {
+                entry = if tokens.next() == Some(host) {
+                    Entry::Host
+                } else {
+                    Entry::Other
+                };
+            }
+            Some("default") => entry = Entry::Default,
+            Some("login") => login = tokens.next().map(str::to_string),
+            Some("password") => password = tokens.next().map(str::to_string),
+            Some("account" | "macdef") => {
+                tokens.next();
+            }
+            Some(_) => {}
+        }
+    }
+    found.or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netrc_prefers_the_matching_machine() {
+        let netrc = "machine example.com login alice password one\n\
+                     default login anon password two\n\
+                     machine other.org login bob password three";
+        assert_eq!(
+            parse_netrc(netrc, "other.org"),
+            Some(("bob".to_string(), "three".to_string()))
+        );
+        assert_eq!(
+            parse_netrc(netrc, "unknown.net"),
+            Some(("anon".to_string(), "two".to_string()))
+        );
+    }
+
+    #[test]
+    fn headers_from_host_variables() {
+        assert_eq!(
+            header_var("api.github.com"),
+            "KOCA_HTTP_HEADER_API_GITHUB_COM"
+        );
+
+        let (name, value) = parse_header("PRIVATE-TOKEN: abc").unwrap();
+        assert_eq!(
+            (name.as_str(), value.to_str().unwrap()),
+            ("private-token", "abc")
+        );
+
+        let (name, value) = parse_header("abc").unwrap();
+        assert_eq!(
+            (name, value.to_str().unwrap()),
+            (AUTHORIZATION, "Bearer abc")
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! A content-addressed cache of fetched sources, shared between builds.
+//!
+//! Only sources whose contents are pinned get cached: downloads with at least one
+//! real checksum, keyed by URL plus checksums, and git sources at a fixed commit,
+//! keyed by URL plus commit and any keys it must be signed by. Anything else
+//! could change upstream under the same key, so it's always fetched fresh.
+//!
+//! Each entry is a directory named by the SHA-256 of its key, holding the
+//! fetched `data` (a file or a checkout) and a `source` file with the key
+//! itself, for listing.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::{GitRef, Source, SourceKind};
+use crate::{KocaError, KocaResult};
+
+/// The cached copy of a source.
+const DATA: &str = "data";
+/// The key an entry was stored under.
+const KEY: &str = "source";
+
+/// The source cache under the user's cache directory.
+pub struct SourceCache {
+    root: PathBuf,
+}
+
+/// One entry in the [`SourceCache`].
+#[derive(Debug)]
+pub struct CacheEntry {
+    /// What the entry was stored for: the URL, followed by its checksums or commit.
+    pub key: String,
+    /// The entry's size on disk, in bytes.
+    pub size: u64,
+    /// The entry's directory.
+    pub path: PathBuf,
+}
+
+impl SourceCache {
+    /// Open the cache in the user's cache directory (e.g. `~/.cache/koca/sources`).
+    ///
+    /// Returns [`KocaError::NoCacheDir`] if there's no home directory to put it in.
+    pub fn open() -> KocaResult<Self> {
+        let dirs =
+            directories::ProjectDirs::from("dev", "koca", "koca").ok_or(KocaError::NoCacheDir)?;
+        Ok(Self::at(dirs.cache_dir().join("sources")))
+    }
+
+    /// Use `root` as the cache directory.
+    pub fn at(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The cache directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Copy the cached copy of `source` to `dest`, returning whether there was one.
+    ///
+    /// This is always a real copy (which filesystems like btrfs turn into a
+    /// reflink), never a hard link, so a build that edits its sources in place
+    /// can't change the cached copy other builds get.
+    pub fn restore(&self, source: &Source, dest: &Path) -> io::Result<bool> {
+        let Some(dir) = self.entry_dir(source) else {
+            return Ok(false);
+        };
+        let data = dir.join(DATA);
+        if !data.exists() {
+            return Ok(false);
+        }
+        if data.is_dir() {
+            copy_dir(&data, dest)?;
+        } else {
+            fs::copy(&data, dest)?;
+        }
+        Ok(true)
+    }
+
+    /// Store the verified copy of `source` at `path`, unless it's already cached
+    /// or isn't pinned to fixed contents.
+    pub fn store(&self, source: &Source, path: &Path) -> io::Result<()> {
+        let (Some(dir), Some(key)) = (self.entry_dir(source), cache_key(source)) else {
+            return Ok(());
+        };
+        if dir.join(DATA).exists() {
+            return Ok(());
+        }
+
+        // Build the entry next to its final place, so a half-written one is never visible.
+        fs::create_dir_all(&self.root)?;
+        let tmp = tempfile::tempdir_in(&self.root)?;
+        if path.is_dir() {
+            copy_dir(path, &tmp.path().join(DATA))?;
+        } else {
+            fs::copy(path, tmp.path().join(DATA))?;
+        }
+        fs::write(tmp.path().join(KEY), key)?;
+
+        match fs::rename(tmp.keep(), &dir) {
+            // Another build stored it first.
+            Err(_) if dir.join(DATA).exists() => Ok(()),
+            other => other,
+        }
+    }
+
+    /// Drop the cached copy of `source`, e.g. after it failed verification.
+    pub fn evict(&self, source: &Source) -> io::Result<()> {
+        match self.entry_dir(source) {
+            Some(dir) if dir.exists() => fs::remove_dir_all(dir),
+            _ => Ok(()),
+        }
+    }
+
+    /// List every cached entry.
+    pub fn entries(&self) -> io::Result<Vec<CacheEntry>> {
+        let read_dir = match fs::read_dir(&self.root) {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut entries = Vec::new();
+        for dir_entry in read_dir {
+            let path = dir_entry?.path();
+            // Skip anything half-written by `store`.
+            let Ok(key) = fs::read_to_string(path.join(KEY)) else {
+                continue;
+            };
+            entries.push(CacheEntry {
+                key,
+                size: dir_size(&path)?,
+                path,
+            });
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+
+    /// Remove every cached entry, returning how many bytes were freed.
+    pub fn clean(&self) -> io::Result<u64> {
+        if !self.root.exists() {
+            return Ok(0);
+        }
+        let size = dir_size(&self.root)?;
+        fs::remove_dir_all(&self.root)?;
+        Ok(size)
+    }
+
+    fn entry_dir(&self, source: &Source) -> Option<PathBuf> {
+        let key = cache_key(source)?;
+        let hash: String = Sha256::digest(key.as_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        Some(self.root.join(hash))
+    }
+}
+
+/// What identifies `source`'s contents, or [`None`] if they aren't pinned.
+fn cache_key(source: &Source) -> Option<String> {
+    match &source.kind {
+        SourceKind::Http { url, .. } => {
+            let checksums: Vec<String> = source
+                .checksums
+                .iter()
+                .filter_map(|c| Some(format!("{}={}", c.kind.var_name(), c.expected.as_ref()?)))
+                .collect();
+            if checksums.is_empty() {
+                return None;
+            }
+            Some(format!("{url} {}", checksums.join(" ")))
+        }
+        SourceKind::Git {
+            url,
+            reference: Some(GitRef::Commit(commit)),
+            full_history,
+            signed,
+        } => {
+            let depth = if *full_history { "&depth=full" } else { "" };
+            let mut key = format!("git+{url}#commit={commit}{depth}");
+            if *signed {
+                let mut keys = source.validpgpkeys.clone();
+                keys.sort();
+                key.push_str(&format!("?signed validpgpkeys={}", keys.join(",")));
+            }
+            Some(key)
+        }
+        _ => None,
+    }
+}
+
+pub(super) fn copy_dir(src: &Path, dest: &Path) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in walkdir::WalkDir::new(path) {
+        let entry = entry.map_err(io::Error::other)?;
+        if entry.file_type().is_file() {
+            size += entry.metadata().map_err(io::Error::other)?.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{Checksum, ChecksumKind, SKIP};
+
+    fn http_source(checksum: &str) -> Source {
+        let mut source = Source::parse("https://example.com/foo.tar.gz").unwrap();
+        source.checksums = vec![Checksum::parse(ChecksumKind::Sha256, checksum).unwrap()];
+        source
+    }
+
+    #[test]
+    fn only_pinned_sources_have_keys() {
+        assert!(cache_key(&http_source(&"a".repeat(64))).is_some());
+        assert!(cache_key(&http_source(SKIP)).is_none());
+        assert!(cache_key(&Source::parse("git+https://example.com/r#tag=v1").unwrap()).is_none());
+        let unsigned = cache_key(&Source::parse("git+https://example.com/r#commit=abc").unwrap());
+        assert!(unsigned.is_some());
+
+        let mut signed = Source::parse("git+https://example.com/r#commit=abc?signed").unwrap();
+        signed.validpgpkeys = vec!["AAAA".to_string()];
+        let signed_key = cache_key(&signed);
+        assert_ne!(signed_key, unsigned);
+        signed.validpgpkeys = vec!["BBBB".to_string()];
+        assert_ne!(cache_key(&signed), signed_key);
+    }
+
+    #[test]
+    fn stores_restores_and_cleans() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = SourceCache::at(dir.path().join("cache"));
+        let source = http_source(&"a".repeat(64));
+        let fetched = dir.path().join("foo.tar.gz");
+        fs::write(&fetched, b"payload").unwrap();
+
+        let restored = dir.path().join("restored");
+        assert!(!cache.restore(&source, &restored).unwrap());
+
+        cache.store(&source, &fetched).unwrap();
+        assert!(cache.restore(&source, &restored).unwrap());
+        assert_eq!(fs::read(&restored).unwrap(), b"payload");
+
+        // Editing the restored copy leaves the cached one alone.
+        fs::write(&restored, b"patched").unwrap();
+        let again = dir.path().join("again");
+        assert!(cache.restore(&source, &again).unwrap());
+        assert_eq!(fs::read(&again).unwrap(), b"payload");
+
+        // A different checksum is a different entry.
+        assert!(!cache
+            .restore(&http_source(&"b".repeat(64)), &dir.path().join("other"))
+            .unwrap());
+
+        let entries = cache.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0]
+            .key
+            .starts_with("https://example.com/foo.tar.gz sha256sums="));
+
+        assert_eq!(cache.clean().unwrap(), entries[0].size);
+        assert!(cache.entries().unwrap().is_empty());
+    }
+}
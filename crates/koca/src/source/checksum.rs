@@ -0,0 +1,205 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use blake2::Blake2b512;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{vars, KocaError, KocaParserError, KocaResult};
+
+/// The placeholder used in a checksum array to skip verification of an entry.
+pub const SKIP: &str = "SKIP";
+
+/// A checksum algorithm supported in build files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChecksumKind {
+    /// SHA-256, from `sha256sums`.
+    Sha256,
+    /// SHA-512, from `sha512sums`.
+    Sha512,
+    /// BLAKE2b-512, from `b2sums`.
+    B2,
+}
+
+impl ChecksumKind {
+    /// All supported algorithms, in the order they're verified.
+    pub const ALL: [ChecksumKind; 3] =
+        [ChecksumKind::Sha256, ChecksumKind::Sha512, ChecksumKind::B2];
+
+    /// The build file array holding this algorithm's checksums (e.g. `sha256sums`).
+    pub fn var_name(&self) -> &'static str {
+        match self {
+            ChecksumKind::Sha256 => vars::SHA256SUMS,
+            ChecksumKind::Sha512 => vars::SHA512SUMS,
+            ChecksumKind::B2 => vars::B2SUMS,
+        }
+    }
+
+    /// Find the algorithm for a build file array name (e.g. `sha256sums` → [`ChecksumKind::Sha256`]).
+    pub fn from_var_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.var_name() == name)
+    }
+
+    /// Length of a hex-encoded digest for this algorithm.
+    fn hex_len(&self) -> usize {
+        match self {
+            ChecksumKind::Sha256 => 64,
+            ChecksumKind::Sha512 | ChecksumKind::B2 => 128,
+        }
+    }
+
+    /// Hash everything in `reader`, returning the lowercase hex digest.
+    pub fn digest_reader(&self, reader: impl Read) -> io::Result<String> {
+        match self {
+            ChecksumKind::Sha256 => digest_with::<Sha256>(reader),
+            ChecksumKind::Sha512 => digest_with::<Sha512>(reader),
+            ChecksumKind::B2 => digest_with::<Blake2b512>(reader),
+        }
+    }
+
+    /// Hash the file at `path`, returning the lowercase hex digest.
+    pub fn digest_file(&self, path: &Path) -> io::Result<String> {
+        self.digest_reader(File::open(path)?)
+    }
+}
+
+impl fmt::Display for ChecksumKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumKind::Sha256 => write!(f, "sha256"),
+            ChecksumKind::Sha512 => write!(f, "sha512"),
+            ChecksumKind::B2 => write!(f, "b2"),
+        }
+    }
+}
+
+fn digest_with<D: Digest>(mut reader: impl Read) -> io::Result<String> {
+    let mut hasher = D::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// An expected checksum for a single source entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checksum {
+    /// The algorithm this checksum uses.
+    pub kind: ChecksumKind,
+    /// The expected lowercase hex digest, or [`None`] for a `SKIP` entry.
+    pub expected: Option<String>,
+}
+
+impl Checksum {
+    /// Parse an entry from a checksum array, accepting a hex digest or `SKIP`.
+    pub fn parse(kind: ChecksumKind, value: &str) -> KocaResult<Self> {
+        if value == SKIP {
+            return Ok(Self {
+                kind,
+                expected: None,
+            });
+        }
+
+        if value.len() != kind.hex_len() || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(KocaParserError::InvalidChecksum(
+                kind.var_name().to_string(),
+                value.to_string(),
+            )
+            .into());
+        }
+
+        Ok(Self {
+            kind,
+            expected: Some(value.to_ascii_lowercase()),
+        })
+    }
+
+    /// Verify the file at `path` against this checksum.
+    ///
+    /// `SKIP` entries always pass. Returns [`KocaError::ChecksumMismatch`] naming
+    /// `entry` if the digest differs.
+    pub fn verify(&self, entry: &str, path: &Path) -> KocaResult<()> {
+        let Some(expected) = &self.expected else {
+            return Ok(());
+        };
+
+        let actual = self.kind.digest_file(path)?;
+        if &actual != expected {
+            return Err(KocaError::ChecksumMismatch {
+                entry: entry.to_string(),
+                kind: self.kind,
+                expected: expected.clone(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn parses_skip() {
+        let c = Checksum::parse(ChecksumKind::Sha256, "SKIP").unwrap();
+        assert_eq!(c.expected, None);
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        assert!(Checksum::parse(ChecksumKind::Sha256, "abc123").is_err());
+        assert!(Checksum::parse(ChecksumKind::Sha512, EMPTY_SHA256).is_err());
+    }
+
+    #[test]
+    fn rejects_non_hex() {
+        let bad = "z".repeat(64);
+        assert!(Checksum::parse(ChecksumKind::Sha256, &bad).is_err());
+    }
+
+    #[test]
+    fn normalises_to_lowercase() {
+        let c = Checksum::parse(ChecksumKind::Sha256, &EMPTY_SHA256.to_uppercase()).unwrap();
+        assert_eq!(c.expected.as_deref(), Some(EMPTY_SHA256));
+    }
+
+    #[test]
+    fn digests_match_known_values() {
+        assert_eq!(
+            ChecksumKind::Sha256.digest_reader(&b""[..]).unwrap(),
+            EMPTY_SHA256
+        );
+        assert_eq!(
+            ChecksumKind::B2.digest_reader(&b"abc"[..]).unwrap(),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+    }
+
+    #[test]
+    fn verify_reports_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::fs::write(&path, b"payload").unwrap();
+
+        let c = Checksum::parse(ChecksumKind::Sha256, EMPTY_SHA256).unwrap();
+        let err = c.verify("file", &path).unwrap_err();
+        assert!(matches!(err, KocaError::ChecksumMismatch { ref entry, .. } if entry == "file"));
+
+        let skip = Checksum::parse(ChecksumKind::Sha256, SKIP).unwrap();
+        assert!(skip.verify("file", &path).is_ok());
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// An archive format that's unpacked into `srcdir` after fetching.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    /// An uncompressed `.tar`.
+    Tar,
+    /// A gzip-compressed tarball (`.tar.gz`, `.tgz`).
+    TarGz,
+    /// An xz-compressed tarball (`.tar.xz`, `.txz`).
+    TarXz,
+    /// A bzip2-compressed tarball (`.tar.bz2`, `.tbz2`).
+    TarBz2,
+    /// A zstd-compressed tarball (`.tar.zst`, `.tzst`).
+    TarZst,
+    /// A `.zip` archive.
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Detect the archive format from a filename's extension.
+    pub fn detect(filename: &str) -> Option<Self> {
+        const SUFFIXES: [(&str, ArchiveKind); 10] = [
+            (".tar", ArchiveKind::Tar),
+            (".tar.gz", ArchiveKind::TarGz),
+            (".tgz", ArchiveKind::TarGz),
+            (".tar.xz", ArchiveKind::TarXz),
+            (".txz", ArchiveKind::TarXz),
+            (".tar.bz2", ArchiveKind::TarBz2),
+            (".tbz2", ArchiveKind::TarBz2),
+            (".tar.zst", ArchiveKind::TarZst),
+            (".tzst", ArchiveKind::TarZst),
+            (".zip", ArchiveKind::Zip),
+        ];
+
+        let lower = filename.to_ascii_lowercase();
+        SUFFIXES
+            .into_iter()
+            .find(|(suffix, _)| lower.ends_with(suffix))
+            .map(|(_, kind)| kind)
+    }
+
+    /// Unpack the archive at `archive` into `dest_dir`, overwriting existing files.
+    pub fn extract(&self, archive: &Path, dest_dir: &Path) -> io::Result<()> {
+        let file = BufReader::new(File::open(archive)?);
+        match self {
+            ArchiveKind::Tar => unpack_tar(file, dest_dir),
+            ArchiveKind::TarGz => unpack_tar(flate2::read::MultiGzDecoder::new(file), dest_dir),
+            ArchiveKind::TarXz => unpack_tar(liblzma::read::XzDecoder::new(file), dest_dir),
+            ArchiveKind::TarBz2 => unpack_tar(bzip2::read::MultiBzDecoder::new(file), dest_dir),
+            ArchiveKind::TarZst => unpack_tar(zstd::Decoder::new(file)?, dest_dir),
+            ArchiveKind::Zip => zip::ZipArchive::new(file)
+                .and_then(|mut zip| zip.extract(dest_dir))
+                .map_err(io::Error::other),
+        }
+    }
+}
+
+fn unpack_tar(reader: impl Read, dest_dir: &Path) -> io::Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_overwrite(true);
+    archive.unpack(dest_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_by_extension() {
+        assert_eq!(
+            ArchiveKind::detect("foo-1.0.tar.gz"),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(ArchiveKind::detect("foo.TGZ"), Some(ArchiveKind::TarGz));
+        assert_eq!(
+            ArchiveKind::detect("foo.tar.zst"),
+            Some(ArchiveKind::TarZst)
+        );
+        assert_eq!(ArchiveKind::detect("foo.tar"), Some(ArchiveKind::Tar));
+        assert_eq!(ArchiveKind::detect("foo.zip"), Some(ArchiveKind::Zip));
+        assert_eq!(ArchiveKind::detect("fix.patch"), None);
+        assert_eq!(ArchiveKind::detect("claude"), None);
+    }
+
+    #[test]
+    fn extracts_tar_gz() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("foo.tar.gz");
+
+        let gz = flate2::write::GzEncoder::new(
+            File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(7);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "foo-1.0/README", &b"payload"[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        ArchiveKind::TarGz.extract(&archive, dir.path()).unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("foo-1.0/README")).unwrap(),
+            b"payload"
+        );
+    }
+}
@@ -1,7 +1,12 @@
+use std::io;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use super::{GitRef, Source, SourceKind};
+use super::auth;
+use super::manifest::{self, Identity};
+use super::{GitRef, Source, SourceCache, SourceKind};
+use crate::{KocaError, KocaResult};
 use tempfile::NamedTempFile;
 
 /// Per-item progress state, updated by the fetcher, read by the UI.
@@ -54,19 +59,154 @@ pub fn format_bytes(b: u64) -> String {
     }
 }
 
-/// Fetch a single source to `dest_dir`, updating `progress[index]`.
+/// Fetch a single source to `dest_dir`, updating `progress[index]`, then verify
+/// it against the source's checksums and unpack it if it's an archive.
+///
+/// A copy already in `dest_dir` is only reused if the sidecar manifest says it
+/// was fetched from the same place; otherwise it's fetched again and reported as
+/// "updated". Sources pinned to fixed contents are restored from `cache` when
+/// they're missing, and stored in it once verified. The cache is only an
+/// optimisation, so pass [`None`] to always fetch.
+///
+/// Returns a:
+/// - [`KocaError::SourceFetch`] if the source couldn't be fetched.
+/// - [`KocaError::ChecksumMismatch`] if the fetched source doesn't match a checksum.
+/// - [`KocaError::Extract`] if an archive source couldn't be unpacked.
 pub async fn fetch_source(
     source: &Source,
     dest_dir: &Path,
     index: usize,
     progress: &SourceProgressState,
-) -> Result<(), String> {
-    match &source.kind {
-        SourceKind::Http { url } => fetch_http(url, source, dest_dir, index, progress).await,
-        SourceKind::Git { url, reference } => {
-            fetch_git(url, reference.as_ref(), source, dest_dir, index, progress).await
+    cache: Option<&SourceCache>,
+) -> KocaResult<()> {
+    let name = source.dest_name();
+    let dest = dest_dir.join(&name);
+    let identity = Identity::of(source);
+
+    let mut updated = false;
+    if let Some(identity) = &identity {
+        let stale = match manifest::recorded(dest_dir, &name) {
+            Some(recorded) => !recorded.is_current(identity, &dest),
+            None => true,
+        };
+        if dest.exists() && stale {
+            remove_path(&dest)
+                .map_err(|e| KocaError::SourceFetch(source.raw.clone(), e.to_string()))?;
+            updated = true;
+        }
+    }
+
+    let mut restored = false;
+    if let Some(cache) = cache {
+        if !dest.exists() {
+            restored = cache.restore(source, &dest).unwrap_or(false);
+        }
+    }
+
+    let fetched = match &source.kind {
+        SourceKind::Http { url, mirrors } => {
+            fetch_http(url, mirrors, source, dest_dir, index, progress).await
+        }
+        SourceKind::Git {
+            url,
+            reference,
+            full_history,
+            signed,
+        } => {
+            let options = GitOptions {
+                full_history: *full_history,
+                signed: *signed,
+            };
+            fetch_git(
+                url,
+                reference.as_ref(),
+                options,
+                source,
+                dest_dir,
+                index,
+                progress,
+            )
+            .await
+        }
+        SourceKind::Hg { url, revision } => {
+            let mut args = vec!["clone", "--noninteractive"];
+            if let Some(revision) = revision {
+                args.extend(["--updaterev", revision]);
+            }
+            args.push(url);
+            fetch_vcs("hg", &args, source, dest_dir, index, progress).await
+        }
+        SourceKind::Svn { url, revision } => {
+            let mut args = vec!["checkout", "--non-interactive"];
+            if let Some(revision) = revision {
+                args.extend(["--revision", revision]);
+            }
+            args.push(url);
+            fetch_vcs("svn", &args, source, dest_dir, index, progress).await
         }
         SourceKind::Local { path } => fetch_local(path, source, dest_dir, index, progress).await,
+    };
+
+    let mut result = fetched
+        .map_err(|e| KocaError::SourceFetch(source.raw.clone(), e))
+        .and_then(|()| source.verify(dest_dir));
+
+    if let (Ok(()), Some(identity)) = (&result, identity) {
+        result = manifest::record(dest_dir, &name, identity)
+            .map_err(|e| KocaError::SourceFetch(source.raw.clone(), e.to_string()));
+    }
+    if let (Ok(()), true) = (&result, updated) {
+        let mut s = progress.lock().unwrap();
+        s[index].detail = format!("{} (updated)", format_bytes(s[index].bytes));
+    }
+
+    if let Some(cache) = cache {
+        match &result {
+            Ok(()) => cache.store(source, &dest).ok(),
+            Err(KocaError::ChecksumMismatch { .. }) => cache.evict(source).ok(),
+            // e.g. a cached checkout whose signature no longer verifies.
+            Err(_) if restored => cache.evict(source).ok(),
+            Err(_) => None,
+        };
+    }
+
+    if let (Ok(()), Some(archive_kind)) = (&result, source.archive_kind()) {
+        {
+            let mut s = progress.lock().unwrap();
+            s[index].done = false;
+            s[index].detail = "extracting...".to_string();
+        }
+        let archive = dest_dir.join(source.dest_name());
+        let extract_dir = dest_dir.to_path_buf();
+        result = tokio::task::spawn_blocking(move || archive_kind.extract(&archive, &extract_dir))
+            .await
+            .map_err(io::Error::from)
+            .and_then(|r| r)
+            .map_err(|e| KocaError::Extract(source.dest_name(), e));
+        let mut s = progress.lock().unwrap();
+        s[index].done = true;
+        let note = if updated {
+            "updated, extracted"
+        } else {
+            "extracted"
+        };
+        s[index].detail = format!("{} ({note})", format_bytes(s[index].bytes));
+    }
+
+    if let Err(err) = &result {
+        let mut s = progress.lock().unwrap();
+        s[index].error = Some(err.to_string());
+        s[index].done = true;
+    }
+    result
+}
+
+/// Remove a stale file or checkout from srcdir.
+fn remove_path(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
     }
 }
 
@@ -81,8 +221,74 @@ fn mark_cached(dest: &Path, index: usize, progress: &SourceProgressState) -> Res
     Ok(())
 }
 
+/// How downloads are retried, read from the environment:
+/// - `KOCA_FETCH_RETRIES`: retries per URL after a transient failure (default 3).
+/// - `KOCA_CONNECT_TIMEOUT`: seconds to wait for a connection (default 30).
+/// - `KOCA_READ_TIMEOUT`: seconds a download may stall before it's retried (default 60).
+struct HttpOptions {
+    retries: u32,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+}
+
+impl HttpOptions {
+    fn from_env() -> Self {
+        fn var(name: &str, default: u64) -> u64 {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default)
+        }
+        Self {
+            retries: var("KOCA_FETCH_RETRIES", 3) as u32,
+            connect_timeout: Duration::from_secs(var("KOCA_CONNECT_TIMEOUT", 30)),
+            read_timeout: Duration::from_secs(var("KOCA_READ_TIMEOUT", 60)),
+        }
+    }
+}
+
+/// The environment variables that change how `sources` are fetched: the
+/// [`HttpOptions`] settings and the `KOCA_HTTP_HEADER_*` variable for each
+/// download host, mirrors included. `KOCA_CA_BUNDLE` names a file, so it's left
+/// to the caller.
+pub fn fetch_env_vars(sources: &[Source]) -> Vec<String> {
+    let mut vars: Vec<String> = [
+        "KOCA_FETCH_RETRIES",
+        "KOCA_CONNECT_TIMEOUT",
+        "KOCA_READ_TIMEOUT",
+    ]
+    .map(String::from)
+    .into();
+    for source in sources {
+        if let SourceKind::Http { url, mirrors } = &source.kind {
+            vars.extend(
+                std::iter::once(url)
+                    .chain(mirrors)
+                    .filter_map(|url| auth::header_var_for(url)),
+            );
+        }
+    }
+    vars.sort();
+    vars.dedup();
+    vars
+}
+
+/// Why a download attempt stopped short.
+enum AttemptError {
+    /// Worth trying the same URL again, e.g. a dropped connection or a 5xx.
+    Transient(String),
+    /// The URL won't work however often it's tried, e.g. a 404.
+    Fatal(String),
+}
+
+/// Wait before retry number `retry` (from 1): 1s, 2s, 4s, ... up to 30s.
+fn backoff(retry: u32) -> Duration {
+    Duration::from_secs(1u64 << retry.saturating_sub(1).min(5)).min(Duration::from_secs(30))
+}
+
 async fn fetch_http(
     url: &str,
+    mirrors: &[String],
     source: &Source,
     dest_dir: &Path,
     index: usize,
@@ -93,39 +299,126 @@ async fn fetch_http(
         return mark_cached(&dest, index, progress);
     }
 
-    let client = reqwest::Client::builder()
-        .user_agent("koca")
+    let options = HttpOptions::from_env();
+    let client = auth::http_client()
+        .map_err(|e| e.to_string())?
+        .connect_timeout(options.connect_timeout)
+        .read_timeout(options.read_timeout)
         .build()
         .map_err(|e| e.to_string())?;
 
-    let mut response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    // Partial downloads stay in the temp file, so retries pick up where they left off.
+    let tmp = NamedTempFile::new_in(dest_dir).map_err(|e| e.to_string())?;
+    let tmp_path = tmp.into_temp_path();
 
-    if !response.status().is_success() {
-        let msg = format!("HTTP {}", response.status());
-        let mut s = progress.lock().unwrap();
-        s[index].error = Some(msg.clone());
-        s[index].done = true;
-        return Err(msg);
+    let urls: Vec<&str> = std::iter::once(url)
+        .chain(mirrors.iter().map(String::as_str))
+        .collect();
+    let mut errors = Vec::new();
+    for (n, url) in urls.iter().enumerate() {
+        if n > 0 {
+            // A mirror might serve a different build of the file, so don't splice them.
+            tokio::fs::File::create(&tmp_path)
+                .await
+                .map_err(|e| e.to_string())?;
+            let mut s = progress.lock().unwrap();
+            s[index].detail = format!("trying mirror {n}/{}", urls.len() - 1);
+        }
+
+        let mut retry = 0;
+        let err = loop {
+            match download(&client, url, &tmp_path, index, progress).await {
+                Ok(()) => {
+                    tmp_path.persist(&dest).map_err(|e| e.to_string())?;
+                    let mut s = progress.lock().unwrap();
+                    s[index].done = true;
+                    return Ok(());
+                }
+                Err(AttemptError::Transient(err)) if retry < options.retries => {
+                    retry += 1;
+                    let delay = backoff(retry);
+                    {
+                        let mut s = progress.lock().unwrap();
+                        s[index].detail = format!(
+                            "{err}, retrying in {}s ({retry}/{})",
+                            delay.as_secs(),
+                            options.retries
+                        );
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+                Err(AttemptError::Transient(err) | AttemptError::Fatal(err)) => break err,
+            }
+        };
+        errors.push(if urls.len() > 1 {
+            format!("{url}: {err}")
+        } else {
+            err
+        });
+    }
+
+    let msg = errors.join("; ");
+    let mut s = progress.lock().unwrap();
+    s[index].error = Some(msg.clone());
+    s[index].done = true;
+    Err(msg)
+}
+
+/// Download `url` into `path`, resuming after whatever's already there if the server allows it.
+async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    path: &Path,
+    index: usize,
+    progress: &SourceProgressState,
+) -> Result<(), AttemptError> {
+    let io_err = |e: io::Error| AttemptError::Fatal(e.to_string());
+    let mut downloaded = tokio::fs::metadata(path).await.map_err(io_err)?.len();
+
+    let mut response = get(client, url, downloaded, &auth::process_env).await?;
+
+    let status = response.status();
+    let resumed = status == reqwest::StatusCode::PARTIAL_CONTENT;
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // What's there can't be resumed from; start over next time.
+        tokio::fs::File::create(path).await.map_err(io_err)?;
+        return Err(AttemptError::Transient(format!("HTTP {status}")));
     }
+    if !status.is_success() {
+        let msg = format!("HTTP {status}");
+        let transient = status.is_server_error()
+            || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            || status == reqwest::StatusCode::REQUEST_TIMEOUT;
+        return Err(if transient {
+            AttemptError::Transient(msg)
+        } else {
+            AttemptError::Fatal(msg)
+        });
+    }
+
+    let mut file = if resumed {
+        tokio::fs::OpenOptions::new().append(true).open(path).await
+    } else {
+        // The server sent the whole file.
+        downloaded = 0;
+        tokio::fs::File::create(path).await
+    }
+    .map_err(io_err)?;
 
-    let total = response.content_length().filter(|&l| l > 0);
+    let total = response
+        .content_length()
+        .filter(|&l| l > 0)
+        .map(|l| l + downloaded);
     if let Some(t) = total {
         let mut s = progress.lock().unwrap();
         s[index].total_bytes = Some(t);
-        s[index].detail = format!("0 B/{}", format_bytes(t));
+        s[index].detail = format!("{}/{}", format_bytes(downloaded), format_bytes(t));
     }
 
-    let tmp = NamedTempFile::new_in(dest_dir).map_err(|e| e.to_string())?;
-    let tmp_path = tmp.into_temp_path();
-    let mut file = tokio::fs::File::create(&tmp_path)
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let mut downloaded: u64 = 0;
-    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+    while let Some(chunk) = response.chunk().await.map_err(request_error)? {
         tokio::io::AsyncWriteExt::write_all(&mut file, &chunk)
             .await
-            .map_err(|e| e.to_string())?;
+            .map_err(io_err)?;
         downloaded += chunk.len() as u64;
 
         let (fraction, detail) = if let Some(t) = total {
@@ -142,15 +435,62 @@ async fn fetch_http(
         s[index].fraction = fraction;
         s[index].detail = detail;
     }
+    tokio::io::AsyncWriteExt::flush(&mut file)
+        .await
+        .map_err(io_err)?;
 
-    drop(file);
-    tmp_path.persist(&dest).map_err(|e| e.to_string())?;
-
-    let mut s = progress.lock().unwrap();
-    s[index].done = true;
+    if total.is_some_and(|t| downloaded < t) {
+        return Err(AttemptError::Transient(
+            "connection closed early".to_string(),
+        ));
+    }
     Ok(())
 }
 
+/// Send a GET for `url`, asking for everything after the first `from` bytes.
+///
+/// Redirects are followed here rather than by reqwest, so each hop only gets the
+/// credentials configured for its own host, as read through `env`.
+async fn get(
+    client: &reqwest::Client,
+    url: &str,
+    from: u64,
+    env: &(dyn Fn(&str) -> Option<String> + Sync),
+) -> Result<reqwest::Response, AttemptError> {
+    let mut url = reqwest::Url::parse(url).map_err(|e| AttemptError::Fatal(e.to_string()))?;
+    for _ in 0..=MAX_REDIRECTS {
+        let mut request = auth::authorize(client.get(url.clone()), url.as_str(), env);
+        if from > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={from}-"));
+        }
+        let response = request.send().await.map_err(request_error)?;
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+        let Some(location) = response.headers().get(reqwest::header::LOCATION) else {
+            return Ok(response);
+        };
+        url = location
+            .to_str()
+            .ok()
+            .and_then(|location| url.join(location).ok())
+            .ok_or_else(|| AttemptError::Fatal(format!("invalid redirect to {location:?}")))?;
+    }
+    Err(AttemptError::Fatal("too many redirects".to_string()))
+}
+
+/// How many redirects a download follows, the same as reqwest's default.
+const MAX_REDIRECTS: usize = 10;
+
+/// Classify a request error; anything but a malformed request may succeed on a retry.
+fn request_error(err: reqwest::Error) -> AttemptError {
+    if err.is_builder() {
+        AttemptError::Fatal(err.to_string())
+    } else {
+        AttemptError::Transient(err.to_string())
+    }
+}
+
 /// Init an empty repo at `dest` and shallow-fetch `refspec` from `url` into it.
 fn init_and_fetch(
     dest: &Path,
@@ -176,16 +516,149 @@ fn checkout_oid(repo: &git2::Repository, oid: git2::Oid) -> Result<(), String> {
     Ok(())
 }
 
+/// Check that `object` in the repo at `repo_dir` has a good signature from one of
+/// `valid_keys`, with `git verify-tag` or `git verify-commit` (`subcommand`).
+///
+/// GPG keys are matched by the fingerprint of the signing subkey or its primary
+/// key, SSH keys by their `SHA256:` fingerprint.
+fn verify_signature(
+    repo_dir: &Path,
+    subcommand: &str,
+    object: &str,
+    valid_keys: &[String],
+) -> Result<(), String> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(repo_dir)
+        .args([subcommand, "--raw", object])
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                "git isn't installed, but is needed to verify signatures".to_string()
+            }
+            _ => e.to_string(),
+        })?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        let reason = stderr
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("no valid signature");
+        return Err(format!(
+            "couldn't verify the signature on {object}: {reason}"
+        ));
+    }
+
+    let signers = signing_fingerprints(&stderr);
+    let trusted = signers.iter().any(|signer| {
+        valid_keys
+            .iter()
+            .any(|key| normalize_fingerprint(key) == normalize_fingerprint(signer))
+    });
+    if trusted {
+        return Ok(());
+    }
+    if signers.is_empty() {
+        return Err(format!("{object} isn't signed by a key in validpgpkeys"));
+    }
+    Err(format!(
+        "{object} is signed by {}, which isn't in validpgpkeys",
+        signers.join(", ")
+    ))
+}
+
+/// The fingerprints `git verify-* --raw` output reports a good signature from.
+fn signing_fingerprints(output: &str) -> Vec<String> {
+    let mut fingerprints = Vec::new();
+    for line in output.lines() {
+        // `VALIDSIG <fingerprint> <9 more fields> <primary key fingerprint>`
+        if let Some(fields) = line.strip_prefix("[GNUPG:] VALIDSIG ") {
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            fingerprints.extend(fields.first().map(|f| f.to_string()));
+            fingerprints.extend(fields.get(9).map(|f| f.to_string()));
+        } else if line.starts_with("Good \"git\" signature") {
+            fingerprints.extend(
+                line.split_whitespace()
+                    .find(|word| word.starts_with("SHA256:"))
+                    .map(str::to_string),
+            );
+        }
+    }
+    fingerprints.dedup();
+    fingerprints
+}
+
+/// GPG fingerprints are compared without spaces, a `0x` prefix, or case; SSH
+/// fingerprints (base64) as-is.
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    let fingerprint = fingerprint.trim();
+    if fingerprint.starts_with("SHA256:") {
+        return fingerprint.to_string();
+    }
+    let fingerprint = fingerprint
+        .strip_prefix("0x")
+        .unwrap_or(fingerprint)
+        .replace(' ', "");
+    fingerprint.to_ascii_uppercase()
+}
+
+/// Check out `repo`'s submodules at the commits it records, recursively.
+fn update_submodules(
+    repo: &git2::Repository,
+    fetch_options: &dyn Fn() -> git2::FetchOptions<'static>,
+) -> Result<(), String> {
+    for mut submodule in repo.submodules().map_err(|e| e.message().to_string())? {
+        let mut opts = git2::SubmoduleUpdateOptions::new();
+        opts.fetch(fetch_options());
+        submodule
+            .update(true, Some(&mut opts))
+            .map_err(|e| e.message().to_string())?;
+        let sub_repo = submodule.open().map_err(|e| e.message().to_string())?;
+        update_submodules(&sub_repo, fetch_options)?;
+    }
+    Ok(())
+}
+
+/// How a git source is fetched.
+struct GitOptions {
+    full_history: bool,
+    signed: bool,
+}
+
 async fn fetch_git(
     url: &str,
     reference: Option<&GitRef>,
+    options: GitOptions,
     source: &Source,
     dest_dir: &Path,
     index: usize,
     progress: &SourceProgressState,
 ) -> Result<(), String> {
     let dest = dest_dir.join(source.dest_name());
+    // What has to be signed: the tag itself, or the commit that ends up checked out.
+    let (object, subcommand) = match reference {
+        Some(GitRef::Commit(hash)) => (hash.clone(), "verify-commit"),
+        Some(GitRef::Tag(tag)) => (tag.clone(), "verify-tag"),
+        _ => ("HEAD".to_string(), "verify-commit"),
+    };
+    let valid_keys = source.validpgpkeys.clone();
+
     if dest.exists() {
+        // A checkout from an earlier build or the cache could have been tampered
+        // with since, so it's checked again every time.
+        if options.signed {
+            let (repo_dir, object, valid_keys) = (dest.clone(), object.clone(), valid_keys.clone());
+            let verified = tokio::task::spawn_blocking(move || {
+                verify_signature(&repo_dir, subcommand, &object, &valid_keys)
+            })
+            .await
+            .map_err(|e| e.to_string())?;
+            if let Err(err) = verified {
+                let _ = std::fs::remove_dir_all(&dest);
+                return Err(err);
+            }
+        }
         return mark_cached(&dest, index, progress);
     }
     let url = url.to_string();
@@ -193,36 +666,53 @@ async fn fetch_git(
     let progress = Arc::clone(progress);
 
     tokio::task::spawn_blocking(move || {
-        let mut cb = git2::RemoteCallbacks::new();
         let progress_clone = Arc::clone(&progress);
+        let fetch_options = move || {
+            let mut cb = git2::RemoteCallbacks::new();
+            let progress = Arc::clone(&progress_clone);
+            cb.transfer_progress(move |stats| {
+                let received = stats.received_objects() as u64;
+                let total = stats.total_objects() as u64;
+                let bytes = stats.received_bytes() as u64;
 
-        cb.transfer_progress(move |stats| {
-            let received = stats.received_objects() as u64;
-            let total = stats.total_objects() as u64;
-            let bytes = stats.received_bytes() as u64;
-
-            let mut s = progress_clone.lock().unwrap();
-            s[index].bytes = bytes;
-            if total > 0 {
-                s[index].fraction = Some(received as f64 / total as f64);
-                s[index].detail =
-                    format!("{}/{} objects ({})", received, total, format_bytes(bytes));
-            }
-            true
-        });
+                let mut s = progress.lock().unwrap();
+                s[index].bytes = bytes;
+                if total > 0 {
+                    s[index].fraction = Some(received as f64 / total as f64);
+                    s[index].detail =
+                        format!("{}/{} objects ({})", received, total, format_bytes(bytes));
+                }
+                true
+            });
+            auth::git_credentials(&mut cb);
 
-        let mut fo = git2::FetchOptions::new();
-        fo.remote_callbacks(cb);
-        fo.depth(1);
+            let mut fo = git2::FetchOptions::new();
+            auth::configure_git(&mut fo);
+            fo.remote_callbacks(cb);
+            fo
+        };
+        let mut fo = fetch_options();
+        if !options.full_history {
+            fo.depth(1);
+        }
+        let verify = || {
+            if options.signed {
+                verify_signature(&dest, subcommand, &object, &valid_keys)
+            } else {
+                Ok(())
+            }
+        };
 
         let result = (|| -> Result<(), String> {
-            match &reference {
+            let repo = match &reference {
                 // A bare commit hash: init an empty repo and fetch exactly that
                 // object, then detach onto it.
                 Some(GitRef::Commit(hash)) => {
                     let repo = init_and_fetch(&dest, &url, hash, &mut fo)?;
                     let oid = git2::Oid::from_str(hash).map_err(|e| e.message().to_string())?;
-                    checkout_oid(&repo, oid)
+                    verify()?;
+                    checkout_oid(&repo, oid)?;
+                    repo
                 }
                 // A tag: RepoBuilder won't materialise a tag under a shallow
                 // (depth=1) fetch -- its default refspec only follows
@@ -238,7 +728,9 @@ async fn fetch_git(
                         .and_then(|r| r.peel_to_commit())
                         .map_err(|e| e.message().to_string())?
                         .id();
-                    checkout_oid(&repo, oid)
+                    verify()?;
+                    checkout_oid(&repo, oid)?;
+                    repo
                 }
                 // A branch or the default branch: RepoBuilder checks out a real
                 // branch correctly, so let it drive the clone.
@@ -248,12 +740,20 @@ async fn fetch_git(
                     if let Some(GitRef::Branch(b)) = other {
                         builder.branch(b);
                     }
-                    builder
+                    // Nothing is checked out until the commit is trusted.
+                    let mut no_checkout = git2::build::CheckoutBuilder::new();
+                    no_checkout.dry_run();
+                    builder.with_checkout(no_checkout);
+                    let repo = builder
                         .clone(&url, &dest)
                         .map_err(|e| e.message().to_string())?;
-                    Ok(())
+                    verify()?;
+                    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+                        .map_err(|e| e.message().to_string())?;
+                    repo
                 }
-            }
+            };
+            update_submodules(&repo, &fetch_options)
         })();
 
         if result.is_err() {
@@ -267,6 +767,51 @@ async fn fetch_git(
     .map_err(|e| e.to_string())?
 }
 
+/// Check out an `hg+` or `svn+` source with the `hg` or `svn` command.
+async fn fetch_vcs(
+    program: &str,
+    args: &[&str],
+    source: &Source,
+    dest_dir: &Path,
+    index: usize,
+    progress: &SourceProgressState,
+) -> Result<(), String> {
+    let dest = dest_dir.join(source.dest_name());
+    if dest.exists() {
+        return mark_cached(&dest, index, progress);
+    }
+    progress.lock().unwrap()[index].detail = format!("running {program} {}", args[0]);
+
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .arg(&dest)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .await
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => format!("{program} isn't installed"),
+            _ => e.to_string(),
+        })?;
+    if !output.status.success() {
+        let _ = std::fs::remove_dir_all(&dest);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .map(|line| line.trim().to_string())
+            .unwrap_or_else(|| format!("{program} {}", output.status)));
+    }
+
+    let size = super::cache::dir_size(&dest).unwrap_or(0);
+    let mut s = progress.lock().unwrap();
+    s[index].bytes = size;
+    s[index].fraction = Some(1.0);
+    s[index].detail = format_bytes(size);
+    s[index].done = true;
+    Ok(())
+}
+
 async fn fetch_local(
     path: &Path,
     source: &Source,
@@ -275,14 +820,27 @@ async fn fetch_local(
     progress: &SourceProgressState,
 ) -> Result<(), String> {
     let dest = dest_dir.join(source.dest_name());
-    tokio::fs::copy(path, &dest)
+    let size = if tokio::fs::metadata(path)
         .await
-        .map_err(|e| e.to_string())?;
-
-    let meta = tokio::fs::metadata(&dest)
+        .map_err(|e| e.to_string())?
+        .is_dir()
+    {
+        let (path, dest) = (path.to_path_buf(), dest.clone());
+        tokio::task::spawn_blocking(move || {
+            if dest.exists() {
+                std::fs::remove_dir_all(&dest)?;
+            }
+            super::cache::copy_dir(&path, &dest)?;
+            super::cache::dir_size(&dest)
+        })
         .await
-        .map_err(|e| e.to_string())?;
-    let size = meta.len();
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?
+    } else {
+        tokio::fs::copy(path, &dest)
+            .await
+            .map_err(|e| e.to_string())?
+    };
 
     let mut s = progress.lock().unwrap();
     s[index].bytes = size;
@@ -291,3 +849,135 @@ async fn fetch_local(
     s[index].done = true;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Serve `responses` to successive connections, returning the server's address
+    /// and the requests it received.
+    async fn serve(responses: Vec<Vec<u8>>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                seen.lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&buf[..n]).to_lowercase());
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+        (addr, requests)
+    }
+
+    #[tokio::test]
+    async fn resumes_dropped_downloads_and_falls_back_to_mirrors() {
+        let (primary, _) = serve(vec![
+            b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".to_vec()
+        ])
+        .await;
+        let (mirror, requests) = serve(vec![
+            // Cut off halfway.
+            b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nhello".to_vec(),
+            b"HTTP/1.1 206 Partial Content\r\ncontent-length: 5\r\ncontent-range: bytes 5-9/10\r\n\r\nworld".to_vec(),
+        ])
+        .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let srcdir = dir.path().join("src");
+        std::fs::create_dir(&srcdir).unwrap();
+        let source =
+            Source::parse(&format!("{primary}/greeting.txt|{mirror}/greeting.txt")).unwrap();
+        let progress: SourceProgressState = Arc::new(Mutex::new(vec![SourceProgress::new()]));
+        let cache = SourceCache::at(dir.path().join("cache"));
+        fetch_source(&source, &srcdir, 0, &progress, Some(&cache))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(srcdir.join("greeting.txt")).unwrap(),
+            b"helloworld"
+        );
+        assert!(requests.lock().unwrap()[1].contains("range: bytes=5-"));
+    }
+
+    #[tokio::test]
+    async fn keeps_headers_to_their_own_host_across_redirects() {
+        let (other, other_requests) = serve(vec![
+            b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello".to_vec(),
+        ])
+        .await;
+        // Same server, but a different host as far as credentials go.
+        let other = other.replace("127.0.0.1", "localhost");
+        let redirect = format!(
+            "HTTP/1.1 302 Found\r\nlocation: {other}/greeting.txt\r\ncontent-length: 0\r\n\r\n"
+        );
+        let (origin, origin_requests) = serve(vec![redirect.into_bytes()]).await;
+        let env = |name: &str| {
+            (name == "KOCA_HTTP_HEADER_127_0_0_1").then(|| "X-Token: secret".to_string())
+        };
+
+        let client = auth::http_client().unwrap().build().unwrap();
+        let Ok(response) = get(&client, &format!("{origin}/greeting.txt"), 0, &env).await else {
+            panic!("the download should follow the redirect");
+        };
+        assert_eq!(response.bytes().await.unwrap().as_ref(), b"hello");
+        assert!(origin_requests.lock().unwrap()[0].contains("x-token: secret"));
+        assert!(!other_requests.lock().unwrap()[0].contains("x-token"));
+    }
+
+    #[test]
+    fn lists_fetch_env_for_download_hosts() {
+        let sources = [
+            Source::parse("https://example.com/a.tar.gz|https://mirror.example.org/a.tar.gz")
+                .unwrap(),
+            Source::parse("git+https://git.example.net/repo").unwrap(),
+        ];
+        let vars = fetch_env_vars(&sources);
+        assert!(vars.contains(&"KOCA_HTTP_HEADER_EXAMPLE_COM".to_string()));
+        assert!(vars.contains(&"KOCA_HTTP_HEADER_MIRROR_EXAMPLE_ORG".to_string()));
+        assert!(vars.contains(&"KOCA_FETCH_RETRIES".to_string()));
+        assert!(!vars.iter().any(|var| var.contains("GIT_EXAMPLE_NET")));
+    }
+
+    #[tokio::test]
+    async fn copies_local_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = dir.path().join("assets");
+        std::fs::create_dir_all(local.join("icons")).unwrap();
+        std::fs::write(local.join("icons/app.svg"), b"<svg/>").unwrap();
+        let srcdir = dir.path().join("src");
+        std::fs::create_dir(&srcdir).unwrap();
+
+        let source = Source::parse(local.to_str().unwrap()).unwrap();
+        let progress: SourceProgressState = Arc::new(Mutex::new(vec![SourceProgress::new()]));
+        let cache = SourceCache::at(dir.path().join("cache"));
+        fetch_source(&source, &srcdir, 0, &progress, Some(&cache))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read(srcdir.join("assets/icons/app.svg")).unwrap(),
+            b"<svg/>"
+        );
+        assert_eq!(progress.lock().unwrap()[0].bytes, 6);
+    }
+
+    #[test]
+    fn reads_signers_from_verify_output() {
+        let gpg = "[GNUPG:] NEWSIG\n\
+                   [GNUPG:] VALIDSIG 1111AAAA 2024-01-01 1704067200 0 4 0 1 10 00 2222BBBB\n";
+        assert_eq!(signing_fingerprints(gpg), ["1111AAAA", "2222BBBB"]);
+
+        let ssh = "Good \"git\" signature for dev@example.com with ED25519 key SHA256:abcDEF\n";
+        assert_eq!(signing_fingerprints(ssh), ["SHA256:abcDEF"]);
+
+        assert_eq!(normalize_fingerprint("0x2222 bbbb"), "2222BBBB");
+    }
+}
@@ -0,0 +1,222 @@
+//! The sidecar manifest recording where each fetched source in srcdir came from.
+//!
+//! It lives next to srcdir (`koca-build/sources.json`), keyed by each source's
+//! name in srcdir. A source already on disk is only reused if the manifest says
+//! it was fetched from the same place for the same checksums, and a downloaded
+//! file still hashes the same as when it was fetched, so a bumped `pkgver` or
+//! checksum behind a stable URL, or a truncated download, gets fetched again.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ChecksumKind, GitRef, Source, SourceKind};
+
+/// Sources are fetched concurrently, so updates to the file take turns.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// What a source in srcdir was fetched from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(super) struct Identity {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    /// The checksums the source was expected to match, like `sha256sums=...`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    checksums: Vec<String>,
+    /// Whether the checkout had to be signed (`?signed`), and by which keys.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    signed: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    validpgpkeys: Vec<String>,
+    /// SHA-256 of a downloaded file, checked before it's reused.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+impl Identity {
+    /// The identity of `source`, or [`None`] for local files, which are always copied afresh.
+    pub(super) fn of(source: &Source) -> Option<Self> {
+        let mut signed = false;
+        let (url, reference) = match &source.kind {
+            SourceKind::Http { url, .. } => (url.clone(), None),
+            SourceKind::Git {
+                url,
+                reference,
+                full_history,
+                signed: git_signed,
+            } => {
+                signed = *git_signed;
+                let mut parts: Vec<String> = reference
+                    .iter()
+                    .map(|reference| match reference {
+                        GitRef::Tag(tag) => format!("tag={tag}"),
+                        GitRef::Branch(branch) => format!("branch={branch}"),
+                        GitRef::Commit(commit) => format!("commit={commit}"),
+                    })
+                    .collect();
+                // A shallow clone won't do once the full history is wanted.
+                if *full_history {
+                    parts.push("depth=full".to_string());
+                }
+                (url.clone(), (!parts.is_empty()).then(|| parts.join("&")))
+            }
+            SourceKind::Hg { url, revision } | SourceKind::Svn { url, revision } => (
+                url.clone(),
+                revision
+                    .as_ref()
+                    .map(|revision| format!("revision={revision}")),
+            ),
+            SourceKind::Local { .. } => return None,
+        };
+        let checksums = source
+            .checksums
+            .iter()
+            .filter_map(|c| Some(format!("{}={}", c.kind.var_name(), c.expected.as_ref()?)))
+            .collect();
+        let mut validpgpkeys = if signed {
+            source.validpgpkeys.clone()
+        } else {
+            Vec::new()
+        };
+        validpgpkeys.sort();
+        Some(Self {
+            url,
+            reference,
+            checksums,
+            signed,
+            validpgpkeys,
+            sha256: None,
+        })
+    }
+
+    /// Whether the copy of the source at `path`, recorded as `self`, can be reused for `current`.
+    pub(super) fn is_current(&self, current: &Identity, path: &Path) -> bool {
+        let fetched_as = Identity {
+            sha256: None,
+            ..self.clone()
+        };
+        if &fetched_as != current {
+            return false;
+        }
+        match &self.sha256 {
+            Some(sha256) => ChecksumKind::Sha256
+                .digest_file(path)
+                .is_ok_and(|actual| &actual == sha256),
+            None => true,
+        }
+    }
+}
+
+/// Get what `name` in `srcdir` was recorded as fetched from.
+pub(super) fn recorded(srcdir: &Path, name: &str) -> Option<Identity> {
+    let _guard = LOCK.lock().unwrap();
+    read(srcdir).remove(name)
+}
+
+/// Record that `name` in `srcdir` was fetched as `identity`, hashing it if it's a file.
+pub(super) fn record(srcdir: &Path, name: &str, mut identity: Identity) -> std::io::Result<()> {
+    let path = srcdir.join(name);
+    if path.is_file() {
+        identity.sha256 = Some(ChecksumKind::Sha256.digest_file(&path)?);
+    }
+
+    let _guard = LOCK.lock().unwrap();
+    let mut entries = read(srcdir);
+    entries.insert(name.to_string(), identity);
+    let json = serde_json::to_string_pretty(&entries).map_err(std::io::Error::other)?;
+    fs::write(manifest_path(srcdir), json)
+}
+
+/// Read the manifest, treating a missing or unreadable one as empty.
+fn read(srcdir: &Path) -> BTreeMap<String, Identity> {
+    fs::read_to_string(manifest_path(srcdir))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn manifest_path(srcdir: &Path) -> PathBuf {
+    srcdir.parent().unwrap_or(srcdir).join("sources.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::Checksum;
+
+    #[test]
+    fn detects_changed_url_and_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let srcdir = dir.path().join("src");
+        fs::create_dir_all(&srcdir).unwrap();
+        fs::write(srcdir.join("hello"), b"v1").unwrap();
+
+        let v1 = Source::parse("hello::https://example.com/1.0/hello").unwrap();
+        let v2 = Source::parse("hello::https://example.com/2.0/hello").unwrap();
+        record(&srcdir, "hello", Identity::of(&v1).unwrap()).unwrap();
+
+        let recorded = recorded(&srcdir, "hello").unwrap();
+        let path = srcdir.join("hello");
+        assert!(recorded.is_current(&Identity::of(&v1).unwrap(), &path));
+        assert!(!recorded.is_current(&Identity::of(&v2).unwrap(), &path));
+
+        // A file changed or truncated since it was fetched isn't reused either.
+        fs::write(&path, b"v").unwrap();
+        assert!(!recorded.is_current(&Identity::of(&v1).unwrap(), &path));
+    }
+
+    #[test]
+    fn detects_changed_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let srcdir = dir.path().join("src");
+        fs::create_dir_all(&srcdir).unwrap();
+        fs::write(srcdir.join("hello"), b"v1").unwrap();
+
+        let with_sum = |sum: &str| {
+            let mut source = Source::parse("hello::https://example.com/hello").unwrap();
+            source.checksums = vec![Checksum::parse(ChecksumKind::Sha256, sum).unwrap()];
+            Identity::of(&source).unwrap()
+        };
+        let old = with_sum(&"a".repeat(64));
+        record(&srcdir, "hello", old.clone()).unwrap();
+
+        let recorded = recorded(&srcdir, "hello").unwrap();
+        let path = srcdir.join("hello");
+        assert!(recorded.is_current(&old, &path));
+        // Same URL, but upstream re-rolled the file and the checksum was bumped.
+        assert!(!recorded.is_current(&with_sum(&"b".repeat(64)), &path));
+    }
+
+    #[test]
+    fn detects_changed_signing_requirements() {
+        let dir = tempfile::tempdir().unwrap();
+        let srcdir = dir.path().join("src");
+        fs::create_dir_all(srcdir.join("repo")).unwrap();
+
+        let git = |raw: &str, keys: &[&str]| {
+            let mut source = Source::parse(raw).unwrap();
+            source.validpgpkeys = keys.iter().map(|key| key.to_string()).collect();
+            Identity::of(&source).unwrap()
+        };
+        let unsigned = git("git+https://example.com/repo#tag=v1", &["AAAA"]);
+        let signed = git("git+https://example.com/repo#tag=v1?signed", &["AAAA"]);
+        record(&srcdir, "repo", unsigned.clone()).unwrap();
+
+        let path = srcdir.join("repo");
+        let first = recorded(&srcdir, "repo").unwrap();
+        // validpgpkeys doesn't matter to an unsigned source.
+        assert!(first.is_current(&git("git+https://example.com/repo#tag=v1", &[]), &path));
+        assert!(!first.is_current(&signed, &path));
+
+        record(&srcdir, "repo", signed.clone()).unwrap();
+        let second = recorded(&srcdir, "repo").unwrap();
+        assert!(second.is_current(&signed, &path));
+        assert!(!second.is_current(
+            &git("git+https://example.com/repo#tag=v1?signed", &["BBBB"]),
+            &path
+        ));
+    }
+}
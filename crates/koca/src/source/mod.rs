@@ -1,10 +1,18 @@
+mod auth;
+mod cache;
+mod checksum;
+mod extract;
 mod fetch;
+mod manifest;
 
-pub use fetch::{fetch_source, format_bytes, SourceProgress, SourceProgressState};
+pub use cache::{CacheEntry, SourceCache};
+pub use checksum::{Checksum, ChecksumKind, SKIP};
+pub use extract::ArchiveKind;
+pub use fetch::{fetch_env_vars, fetch_source, format_bytes, SourceProgress, SourceProgressState};
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use crate::KocaError;
+use crate::{KocaError, KocaResult};
 
 /// A parsed source entry from a build file.
 #[derive(Clone, Debug)]
@@ -15,19 +23,43 @@ pub struct Source {
     pub kind: SourceKind,
     /// Original raw entry string.
     pub raw: String,
+    /// Expected checksums from `sha256sums`/`sha512sums`/`b2sums`, one per declared array.
+    pub checksums: Vec<Checksum>,
+    /// Whether this entry is listed in `noextract` and should be left as-is.
+    pub noextract: bool,
+    /// Fingerprints from `validpgpkeys` that may sign this source, if it's a signed git source.
+    pub validpgpkeys: Vec<String>,
 }
 
 /// What kind of source this is.
 #[derive(Clone, Debug)]
 pub enum SourceKind {
     /// HTTP/HTTPS/FTP download.
-    Http { url: String },
-    /// Git clone.
+    Http {
+        url: String,
+        /// Fallback URLs for the same file, tried in order if `url` fails.
+        mirrors: Vec<String>,
+    },
+    /// Git clone, with its submodules.
     Git {
         url: String,
         reference: Option<GitRef>,
+        /// Fetch the whole history rather than just the checked-out commit (`depth=full`).
+        full_history: bool,
+        /// Check the tag or commit is signed by one of `validpgpkeys` before checking it out.
+        signed: bool,
+    },
+    /// Mercurial clone, updated to `revision` (a changeset, tag or branch) if given.
+    Hg {
+        url: String,
+        revision: Option<String>,
     },
-    /// Local file path.
+    /// Subversion checkout, at `revision` if given.
+    Svn {
+        url: String,
+        revision: Option<String>,
+    },
+    /// Local file or directory path.
     Local { path: PathBuf },
 }
 
@@ -47,7 +79,11 @@ impl Source {
     /// Examples:
     /// - `https://example.com/foo.tar.gz`
     /// - `custom.tar.gz::https://example.com/foo.tar.gz`
+    /// - `https://example.com/foo.tar.gz|https://mirror.example.org/foo.tar.gz`
     /// - `git+https://github.com/user/repo#tag=v1.0`
+    /// - `git+https://github.com/user/repo#tag=v1.0&depth=full?signed`
+    /// - `hg+https://hg.example.com/repo#revision=abc123`
+    /// - `svn+https://svn.example.com/repo/trunk#revision=1234`
     pub fn parse(entry: &str) -> Result<Source, KocaError> {
         let raw = entry.to_string();
 
@@ -60,33 +96,87 @@ impl Source {
 
         // Check for git+ prefix.
         if let Some(after_git) = rest.strip_prefix("git+") {
-            // Split off #fragment.
+            // Split off a makepkg-style `?signed` suffix, then the #fragment.
+            let (after_git, mut signed) = match after_git.strip_suffix("?signed") {
+                Some(rest) => (rest, true),
+                None => (after_git, false),
+            };
             let (url_part, fragment) = if let Some(hash_idx) = after_git.find('#') {
                 (&after_git[..hash_idx], Some(&after_git[hash_idx + 1..]))
             } else {
                 (after_git, None)
             };
 
-            let reference = fragment.map(parse_git_fragment).transpose()?;
+            // The fragment is `&`-separated: at most one ref, plus options.
+            let mut reference = None;
+            let mut full_history = false;
+            for part in fragment.into_iter().flat_map(|f| f.split('&')) {
+                match part {
+                    "depth=full" => full_history = true,
+                    "signed" => signed = true,
+                    _ if reference.is_some() => {
+                        return Err(KocaError::InvalidSource(format!(
+                            "more than one git ref in fragment: {}",
+                            fragment.unwrap_or_default()
+                        )))
+                    }
+                    _ => reference = Some(parse_git_fragment(part)?),
+                }
+            }
 
             return Ok(Source {
                 filename,
                 kind: SourceKind::Git {
                     url: url_part.to_string(),
                     reference,
+                    full_history,
+                    signed,
                 },
                 raw,
+                checksums: vec![],
+                noextract: false,
+                validpgpkeys: vec![],
+            });
+        }
+
+        // Check for hg+ and svn+ prefixes.
+        if let Some(after_hg) = rest.strip_prefix("hg+") {
+            let (url, revision) = split_revision(after_hg, &["revision", "tag", "branch"])?;
+            return Ok(Source {
+                filename,
+                kind: SourceKind::Hg { url, revision },
+                raw,
+                checksums: vec![],
+                noextract: false,
+                validpgpkeys: vec![],
+            });
+        }
+        if let Some(after_svn) = rest.strip_prefix("svn+") {
+            let (url, revision) = split_revision(after_svn, &["revision"])?;
+            return Ok(Source {
+                filename,
+                kind: SourceKind::Svn { url, revision },
+                raw,
+                checksums: vec![],
+                noextract: false,
+                validpgpkeys: vec![],
             });
         }
 
         // Check for URL (contains ://).
         if rest.contains("://") {
+            // Split off `|`-separated mirrors.
+            let mut urls = rest.split('|').map(str::to_string);
             return Ok(Source {
                 filename,
                 kind: SourceKind::Http {
-                    url: rest.to_string(),
+                    url: urls.next().unwrap_or_default(),
+                    mirrors: urls.collect(),
                 },
                 raw,
+                checksums: vec![],
+                noextract: false,
+                validpgpkeys: vec![],
             });
         }
 
@@ -97,6 +187,9 @@ impl Source {
                 path: PathBuf::from(rest),
             },
             raw,
+            checksums: vec![],
+            noextract: false,
+            validpgpkeys: vec![],
         })
     }
 
@@ -108,8 +201,8 @@ impl Source {
     /// - `custom.tar.gz::https://example.com/f.tar.gz` → `https://example.com/f.tar.gz`
     pub fn display_url(&self) -> String {
         match &self.kind {
-            SourceKind::Http { url } => url.clone(),
-            SourceKind::Git { url, reference } => match reference {
+            SourceKind::Http { url, .. } => url.clone(),
+            SourceKind::Git { url, reference, .. } => match reference {
                 Some(git_ref) => {
                     let ref_str = match git_ref {
                         GitRef::Tag(t) => format!("tag={t}"),
@@ -120,6 +213,12 @@ impl Source {
                 }
                 None => url.clone(),
             },
+            SourceKind::Hg { url, revision } | SourceKind::Svn { url, revision } => {
+                match revision {
+                    Some(revision) => format!("{url} (revision={revision})"),
+                    None => url.clone(),
+                }
+            }
             SourceKind::Local { path } => path.display().to_string(),
         }
     }
@@ -132,18 +231,53 @@ impl Source {
             return name.clone();
         }
         match &self.kind {
-            SourceKind::Http { url } => url_last_segment(url),
+            SourceKind::Http { url, .. } => url_last_segment(url),
             SourceKind::Git { url, .. } => {
                 let seg = url_last_segment(url);
                 // Strip .git suffix if present.
                 seg.strip_suffix(".git").unwrap_or(&seg).to_string()
             }
+            SourceKind::Hg { url, .. } | SourceKind::Svn { url, .. } => url_last_segment(url),
             SourceKind::Local { path } => path
                 .file_name()
                 .map(|n| n.to_string_lossy().to_string())
                 .unwrap_or_else(|| "source".to_string()),
         }
     }
+
+    /// The archive format to unpack this source as, if it's an archive that should
+    /// be extracted into srcdir.
+    pub fn archive_kind(&self) -> Option<ArchiveKind> {
+        match self.kind {
+            SourceKind::Http { .. } | SourceKind::Local { .. } if !self.noextract => {
+                ArchiveKind::detect(&self.dest_name())
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether this is a version control checkout, which has no checksums.
+    pub fn is_vcs(&self) -> bool {
+        matches!(
+            self.kind,
+            SourceKind::Git { .. } | SourceKind::Hg { .. } | SourceKind::Svn { .. }
+        )
+    }
+
+    /// Verify the fetched copy of this source in `dest_dir` against its checksums.
+    ///
+    /// Returns [`KocaError::ChecksumMismatch`] on the first digest that doesn't match.
+    pub fn verify(&self, dest_dir: &Path) -> KocaResult<()> {
+        if self.checksums.is_empty() || self.is_vcs() {
+            return Ok(());
+        }
+
+        let path = dest_dir.join(self.dest_name());
+        for checksum in &self.checksums {
+            checksum.verify(&self.dest_name(), &path)?;
+        }
+        Ok(())
+    }
 }
 
 /// Parse a git fragment like `tag=v1.0`, `branch=main`, or `commit=abc123`.
@@ -164,6 +298,20 @@ fn parse_git_fragment(fragment: &str) -> Result<GitRef, KocaError> {
     }
 }
 
+/// Split an `hg+`/`svn+` URL from its `#key=value` fragment, where `key` is one of `keys`.
+fn split_revision(url: &str, keys: &[&str]) -> Result<(String, Option<String>), KocaError> {
+    let Some((url, fragment)) = url.split_once('#') else {
+        return Ok((url.to_string(), None));
+    };
+    match fragment.split_once('=') {
+        Some((key, value)) if keys.contains(&key) => Ok((url.to_string(), Some(value.to_string()))),
+        _ => Err(KocaError::InvalidSource(format!(
+            "invalid fragment (expected {}=...): {fragment}",
+            keys.join("=..., ")
+        ))),
+    }
+}
+
 /// Extract the last non-empty path segment from a URL.
 fn url_last_segment(url: &str) -> String {
     // Strip query string and fragment.
@@ -185,7 +333,7 @@ mod tests {
         let s = Source::parse("https://example.com/foo-1.0.tar.gz").unwrap();
         assert!(s.filename.is_none());
         assert!(
-            matches!(s.kind, SourceKind::Http { ref url } if url == "https://example.com/foo-1.0.tar.gz")
+            matches!(s.kind, SourceKind::Http { ref url, .. } if url == "https://example.com/foo-1.0.tar.gz")
         );
     }
 
@@ -194,7 +342,18 @@ mod tests {
         let s = Source::parse("custom.tar.gz::https://example.com/foo.tar.gz").unwrap();
         assert_eq!(s.filename.as_deref(), Some("custom.tar.gz"));
         assert!(
-            matches!(s.kind, SourceKind::Http { ref url } if url == "https://example.com/foo.tar.gz")
+            matches!(s.kind, SourceKind::Http { ref url, .. } if url == "https://example.com/foo.tar.gz")
+        );
+    }
+
+    #[test]
+    fn parse_http_mirrors() {
+        let s =
+            Source::parse("https://example.com/foo.tar.gz|https://mirror.example.org/foo.tar.gz")
+                .unwrap();
+        assert_eq!(s.dest_name(), "foo.tar.gz");
+        assert!(
+            matches!(s.kind, SourceKind::Http { ref url, ref mirrors } if url == "https://example.com/foo.tar.gz" && mirrors == &["https://mirror.example.org/foo.tar.gz"])
         );
     }
 
@@ -202,7 +361,7 @@ mod tests {
     fn parse_git_bare() {
         let s = Source::parse("git+https://github.com/user/repo").unwrap();
         assert!(
-            matches!(s.kind, SourceKind::Git { ref url, ref reference } if url == "https://github.com/user/repo" && reference.is_none())
+            matches!(s.kind, SourceKind::Git { ref url, ref reference, .. } if url == "https://github.com/user/repo" && reference.is_none())
         );
     }
 
@@ -230,6 +389,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_git_options() {
+        let s =
+            Source::parse("git+https://github.com/user/repo#tag=v1.0&depth=full?signed").unwrap();
+        assert!(
+            matches!(s.kind, SourceKind::Git { ref reference, full_history: true, signed: true, .. } if matches!(reference, Some(GitRef::Tag(t)) if t == "v1.0"))
+        );
+
+        let s = Source::parse("git+https://github.com/user/repo#signed").unwrap();
+        assert!(matches!(
+            s.kind,
+            SourceKind::Git {
+                reference: None,
+                full_history: false,
+                signed: true,
+                ..
+            }
+        ));
+
+        assert!(Source::parse("git+https://github.com/user/repo#depth=5").is_err());
+        assert!(Source::parse("git+https://github.com/user/repo#tag=a&tag=b").is_err());
+    }
+
     #[test]
     fn parse_git_rename() {
         let s = Source::parse("myrepo::git+https://github.com/user/repo#tag=v2").unwrap();
@@ -243,6 +425,26 @@ mod tests {
         assert!(Source::parse("git+https://github.com/user/repo#noequalssign").is_err());
     }
 
+    #[test]
+    fn parse_hg_and_svn() {
+        let s = Source::parse("hg+https://hg.example.com/repo#tag=1.0").unwrap();
+        assert!(
+            matches!(s.kind, SourceKind::Hg { ref url, ref revision } if url == "https://hg.example.com/repo" && revision.as_deref() == Some("1.0"))
+        );
+        assert_eq!(s.dest_name(), "repo");
+        assert_eq!(
+            s.display_url(),
+            "https://hg.example.com/repo (revision=1.0)"
+        );
+
+        let s = Source::parse("svn+https://svn.example.com/proj/trunk#revision=42").unwrap();
+        assert!(
+            matches!(s.kind, SourceKind::Svn { ref revision, .. } if revision.as_deref() == Some("42"))
+        );
+        assert_eq!(s.dest_name(), "trunk");
+        assert!(Source::parse("svn+https://svn.example.com/proj#tag=1.0").is_err());
+    }
+
     #[test]
     fn parse_local() {
         let s = Source::parse("/path/to/file.tar.gz").unwrap();
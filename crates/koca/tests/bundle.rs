@@ -0,0 +1,65 @@
+//! Integration tests for bundling: packages built twice from the same input
+//! must be byte-for-byte identical.
+//!
+//! These run the build in a temporary current directory, so they live in their
+//! own test binary.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use koca::source::ChecksumKind;
+use koca::{BuildFile, BundleFormat};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Build and bundle `reproducible.koca` in every format, returning each package's SHA-256.
+async fn build_and_hash() -> Vec<String> {
+    let mut bf = BuildFile::parse_file(fixture("reproducible.koca"))
+        .await
+        .unwrap();
+    bf.set_source_date_epoch(1_700_000_000);
+
+    std::fs::remove_dir_all("koca-build").ok();
+    bf.run_package_for("repro").await.unwrap();
+
+    [
+        BundleFormat::Deb,
+        BundleFormat::Rpm,
+        BundleFormat::Pacman,
+        BundleFormat::Apk,
+    ]
+    .into_iter()
+    .map(|format| {
+        let out = Path::new("koca-out").join(
+            format
+                .output_filename("repro", bf.version(), bf.target_arch())
+                .unwrap(),
+        );
+        std::fs::create_dir_all("koca-out").unwrap();
+        bf.bundle("repro", format, &out).unwrap();
+        ChecksumKind::Sha256
+            .digest_reader(File::open(&out).unwrap())
+            .unwrap()
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn bundles_are_reproducible() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_current_dir(dir.path()).unwrap();
+    let first = build_and_hash().await;
+
+    // Make sure the second build's files get newer mtimes than the first's, and
+    // that it runs from another checkout.
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let other_dir = tempfile::tempdir().unwrap();
+    std::env::set_current_dir(other_dir.path()).unwrap();
+    let second = build_and_hash().await;
+
+    assert_eq!(first, second);
+}
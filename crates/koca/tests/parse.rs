@@ -1,10 +1,13 @@
-//! Integration tests for parsing relationship fields (`depends`, `provides`,
-//! `conflicts`) from `.koca` files.
+//! Integration tests for parsing `.koca` files: relationship fields
+//! (`depends`, `provides`, `conflicts`), source checksum arrays, signed git
+//! sources, build steps, maintainer scripts (and which split package gets them), target architecture selection and
+//! per-package metadata overrides.
 
 use std::path::{Path, PathBuf};
 
 use koca::rfpm::relation::Op;
-use koca::BuildFile;
+use koca::source::ChecksumKind;
+use koca::{Arch, BuildFile, BundleFormat, KocaError, KocaParserError, ScriptHook};
 
 fn fixture(name: &str) -> PathBuf {
     Path::new(env!("CARGO_MANIFEST_DIR"))
@@ -41,3 +44,180 @@ async fn provides_rejects_range_operator() {
         .await
         .is_err());
 }
+
+#[tokio::test]
+async fn attaches_checksums_to_sources() {
+    let bf = BuildFile::parse_file(fixture("checksums.koca"))
+        .await
+        .unwrap();
+
+    let base = bf.sources(&Arch::Arm64);
+    assert_eq!(base.len(), 2);
+    assert_eq!(base[0].checksums.len(), 1);
+    assert_eq!(base[0].checksums[0].kind, ChecksumKind::Sha256);
+    assert_eq!(
+        base[0].checksums[0].expected.as_deref(),
+        Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+    );
+    assert_eq!(base[1].checksums[0].expected, None);
+
+    let x64 = bf.sources(&Arch::X64);
+    assert_eq!(x64.len(), 1);
+    assert_eq!(x64[0].checksums.len(), 1);
+    assert_eq!(x64[0].checksums[0].kind, ChecksumKind::B2);
+}
+
+#[tokio::test]
+async fn checksums_reject_length_mismatch() {
+    assert!(BuildFile::parse_file(fixture("checksums-count.koca"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn unchecked_parse_keeps_mismatched_checksum_kinds() {
+    let bf = BuildFile::parse_file_unchecked(fixture("checksums-count.koca"))
+        .await
+        .unwrap();
+
+    let sources = bf.sources(&Arch::X64);
+    assert_eq!(sources.len(), 2);
+    for source in sources {
+        assert_eq!(source.checksums.len(), 1);
+        assert_eq!(source.checksums[0].kind, ChecksumKind::Sha256);
+        assert_eq!(source.checksums[0].expected, None);
+    }
+}
+
+#[tokio::test]
+async fn hands_validpgpkeys_to_signed_sources() {
+    let bf = BuildFile::parse_file(fixture("signed.koca")).await.unwrap();
+    let sources = bf.sources(&Arch::X64);
+    assert_eq!(
+        sources[0].validpgpkeys,
+        ["ABAF11C65A2970B130ABE3C479BE3E4300411886"]
+    );
+    assert!(sources[1].validpgpkeys.is_empty());
+
+    // A signed source with nothing to check it against is an error.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("unkeyed.koca");
+    let contents = std::fs::read_to_string(fixture("signed.koca")).unwrap();
+    std::fs::write(&path, contents.replace("validpgpkeys=", "#validpgpkeys=")).unwrap();
+    assert!(BuildFile::parse_file(&path).await.is_err());
+}
+
+#[tokio::test]
+async fn detects_optional_build_steps() {
+    let bf = BuildFile::parse_file(fixture("steps.koca")).await.unwrap();
+    assert!(bf.has_prepare());
+    assert!(bf.has_build());
+    assert!(bf.has_check());
+
+    let bf = BuildFile::parse_file(fixture("relationships.koca"))
+        .await
+        .unwrap();
+    assert!(!bf.has_prepare());
+    assert!(!bf.has_check());
+}
+
+#[tokio::test]
+async fn collects_maintainer_scripts() {
+    let bf = BuildFile::parse_file(fixture("scripts.koca"))
+        .await
+        .unwrap();
+    let scripts = bf.scripts();
+
+    assert!(scripts.has(ScriptHook::PostInstall));
+    assert!(scripts.has(ScriptHook::PreRemove));
+    assert!(!scripts.has(ScriptHook::PostUpgrade));
+
+    let rpm = scripts.to_rfpm(BundleFormat::Rpm, "1.0-1");
+    assert!(rpm.pre_install.is_none());
+    assert!(rpm.post_install.is_some());
+    assert!(rpm.pre_remove.is_some());
+    assert!(rpm.post_remove.is_none());
+}
+
+#[tokio::test]
+async fn attaches_maintainer_scripts_to_pkgbase_only() {
+    let bf = BuildFile::parse_file(fixture("split-scripts.koca"))
+        .await
+        .unwrap();
+
+    assert!(bf.scripts_for("libfoo").has(ScriptHook::PostInstall));
+    assert!(bf.scripts_for("libfoo-doc").is_empty());
+    let rpm = bf
+        .scripts_for("libfoo-doc")
+        .to_rfpm(BundleFormat::Rpm, "1.0.0-1");
+    assert!(rpm.post_install.is_none());
+
+    // Without a package named pkgbase, the scripts would go nowhere.
+    let src = std::fs::read_to_string(fixture("split-scripts.koca"))
+        .unwrap()
+        .replace("pkgbase=libfoo", "pkgbase=foo");
+    let Err(errs) = BuildFile::parse(src.as_bytes()).await else {
+        panic!("a split build without a pkgbase package should not parse");
+    };
+    assert!(
+        errs.iter().any(|err| matches!(
+            err,
+            KocaError::Parser(KocaParserError::SplitPackageConflict(msg)) if msg.contains("pkgbase")
+        )),
+        "{errs:?}"
+    );
+}
+
+#[tokio::test]
+async fn selects_declared_target_arch() {
+    let mut bf = BuildFile::parse_file(fixture("checksums.koca"))
+        .await
+        .unwrap();
+    assert!(bf.arch().contains(bf.target_arch()));
+
+    bf.set_target_arch(&Arch::Arm64).unwrap();
+    assert_eq!(bf.target_arch(), &Arch::Arm64);
+    assert!(bf.set_target_arch(&Arch::All).is_err());
+    assert_eq!(bf.target_arch(), &Arch::Arm64);
+}
+
+#[tokio::test]
+async fn applies_per_package_overrides() {
+    let bf = BuildFile::parse_file(fixture("split-overrides.koca"))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        bf.pkgdesc_for("libfoo"),
+        "Fixture with per-package overrides"
+    );
+    assert_eq!(bf.depends_for("libfoo")[0].name, "libc6");
+    assert_eq!(bf.backup_for("libfoo"), ["/etc/foo.conf"]);
+
+    assert_eq!(bf.pkgdesc_for("libfoo-dev"), "Headers for libfoo");
+    assert_eq!(bf.depends_for("libfoo-dev").len(), 1);
+    assert_eq!(bf.depends_for("libfoo-dev")[0].name, "libfoo");
+    assert!(bf.backup_for("libfoo-dev").is_empty());
+    // Only top-level assignments in the function body count.
+    assert!(bf.conflicts_for("libfoo-dev").is_empty());
+}
+
+#[tokio::test]
+async fn expands_variables_in_overrides() {
+    let bf = BuildFile::parse_file(fixture("split-expansion.koca"))
+        .await
+        .unwrap();
+
+    assert_eq!(bf.pkgdesc_for("libfoo-dev"), "Headers for libfoo");
+    let dep = &bf.depends_for("libfoo-dev")[0];
+    assert_eq!(dep.name, "libfoo");
+    let constraint = dep.constraint.as_ref().unwrap();
+    assert_eq!(
+        (constraint.op, constraint.version.as_str()),
+        (Op::Equal, "1.0.0")
+    );
+    // Unquoted, the word parser splits this into several pieces.
+    let provide = &bf.provides_for("libfoo-dev")[0];
+    assert_eq!(provide.name, "libfoo-headers");
+    assert_eq!(provide.version.as_deref(), Some("1.0.0"));
+}
@@ -0,0 +1,90 @@
+//! Integration tests for `pkgver()`: its output becomes the package's version,
+//! both for later build functions and for the bundled package.
+//!
+//! These run the build in a temporary current directory, so they live in their
+//! own test binary.
+
+use std::path::{Path, PathBuf};
+
+use koca::{Arch, BuildFile, BuildOutputStream, BundleFormat, KocaError};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[tokio::test]
+async fn pkgver_function_replaces_the_version() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_current_dir(dir.path()).unwrap();
+
+    let mut bf = BuildFile::parse_file(fixture("pkgver.koca")).await.unwrap();
+    assert!(bf.has_pkgver());
+    assert_eq!(bf.version().to_string(), "0-1");
+
+    let mut lines = Vec::new();
+    let pkgver = bf
+        .run_pkgver_with_output(|line| lines.extend(line))
+        .await
+        .unwrap();
+    assert_eq!(pkgver.as_str(), "r123.abcdef");
+    assert_eq!(bf.version().to_string(), "r123.abcdef-1");
+    // Only stderr is shown; stdout is the version.
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].stream, BuildOutputStream::Stderr);
+
+    bf.run_package_for("nightly").await.unwrap();
+    assert_eq!(
+        std::fs::read_to_string("koca-build/pkg/nightly/usr/share/nightly/version").unwrap(),
+        "r123.abcdef\n"
+    );
+
+    // makepkg's usual VCS version bundles for the formats that allow it...
+    for (format, expected) in [
+        (BundleFormat::Rpm, "nightly_r123.abcdef-1_noarch.rpm"),
+        (
+            BundleFormat::Pacman,
+            "nightly-r123.abcdef-1-any.pkg.tar.zst",
+        ),
+    ] {
+        let name = format
+            .output_filename("nightly", bf.version(), &Arch::All)
+            .unwrap();
+        assert_eq!(name, expected);
+        bf.bundle("nightly", format, Path::new(&name)).unwrap();
+        assert!(Path::new(&name).exists());
+    }
+
+    // ...and only fails for the ones whose versions must start with a digit.
+    for format in [BundleFormat::Deb, BundleFormat::Apk] {
+        let err = format
+            .output_filename("nightly", bf.version(), &Arch::All)
+            .unwrap_err();
+        assert!(
+            matches!(&err, KocaError::UnsupportedPackageVersion(ver, _) if ver == "r123.abcdef"),
+            "{err}"
+        );
+        let out = Path::new("nightly.out");
+        assert!(bf.bundle("nightly", format, out).is_err());
+        assert!(!out.exists());
+    }
+
+    // Output that isn't a pkgver at all names the constraint it breaks. This shares
+    // the test with the above, since both need the temporary current directory.
+    let mut bf = BuildFile::parse(
+        "pkgname=nightly\npkgver=0\narch=('any')\npkgdesc='Nightly'\n\
+         pkgver() { echo 1.0-2; }\npackage() { :; }\n"
+            .as_bytes(),
+    )
+    .await
+    .unwrap();
+    let err = bf.run_pkgver_with_output(|_| {}).await.unwrap_err();
+    assert!(
+        matches!(&err, KocaError::InvalidPkgverOutput(out) if out == "1.0-2"),
+        "{err}"
+    );
+    assert!(err
+        .to_string()
+        .contains("must start with a letter or digit"));
+}